@@ -4,7 +4,13 @@
 
 extern crate rustc_serialize;
 
+use std::cell::RefCell;
 use std::collections::{HashMap};
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+
+pub use storage::{Storage, StorageIter};
+
+mod storage;
 
 /// Entity index type.
 pub type Idx = u32;
@@ -31,50 +37,140 @@ impl Entity {
     }
 }
 
+/// Index from prototype entities to the entities that have them as parent.
+///
+/// The index is derived from the parent table, so it isn't serialized. It
+/// is rebuilt from the parent table when first needed.
+#[derive(Clone)]
+pub struct ChildIndex(RefCell<Option<HashMap<Idx, Vec<Idx>>>>);
+
+impl ChildIndex {
+    pub fn new() -> ChildIndex { ChildIndex(RefCell::new(None)) }
+
+    /// Record a new parent link.
+    pub fn insert(&mut self, parent: Idx, child: Idx) {
+        if let Some(ref mut map) = *self.0.borrow_mut() {
+            map.entry(parent).or_insert_with(Vec::new).push(child);
+        }
+    }
+
+    /// Forget a removed parent link.
+    pub fn remove(&mut self, parent: Idx, child: Idx) {
+        if let Some(ref mut map) = *self.0.borrow_mut() {
+            if let Some(children) = map.get_mut(&parent) { children.retain(|&x| x != child); }
+        }
+    }
+
+    /// Run a function with the index, building it first if needed.
+    fn with<A, F>(&self, parents: &HashMap<Idx, Entity>, f: F) -> A
+        where F: FnOnce(&HashMap<Idx, Vec<Idx>>) -> A {
+        if self.0.borrow().is_none() {
+            let mut map = HashMap::new();
+            for (&child, parent) in parents.iter() {
+                map.entry(parent.idx).or_insert_with(Vec::new).push(child);
+            }
+            *self.0.borrow_mut() = Some(map);
+        }
+        f(self.0.borrow().as_ref().unwrap())
+    }
+}
+
+impl Encodable for ChildIndex {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_nil()
+    }
+}
+
+impl Decodable for ChildIndex {
+    fn decode<D: Decoder>(d: &mut D) -> Result<ChildIndex, D::Error> {
+        try!(d.read_nil());
+        Ok(ChildIndex::new())
+    }
+}
+
 /// Immutable component accessor.
 pub struct CompRef<'a, C: 'static> {
     parents: &'a HashMap<Idx, Entity>,
+    children: &'a ChildIndex,
     active: &'a HashMap<Idx, Uid>,
-    data: &'a HashMap<Idx, Option<C>>,
+    data: &'a Storage<C>,
 }
 
+impl<'a, C> Clone for CompRef<'a, C> {
+    fn clone(&self) -> CompRef<'a, C> { *self }
+}
+
+impl<'a, C> Copy for CompRef<'a, C> {}
+
 impl<'a, C> CompRef<'a, C> {
-    pub fn new(parents: &'a HashMap<Idx, Entity>, active: &'a HashMap<Idx, Uid>, data: &'a HashMap<Idx, Option<C>>) -> CompRef<'a, C> {
+    pub fn new(parents: &'a HashMap<Idx, Entity>, children: &'a ChildIndex,
+               active: &'a HashMap<Idx, Uid>, data: &'a Storage<C>) -> CompRef<'a, C> {
         CompRef {
             parents: parents,
+            children: children,
             active: active,
             data: data,
         }
     }
 
     /// Fetch a component from given entity or its parent.
-    pub fn get(&'a self, e: Entity) -> Option<&'a C> {
-        self.check_uid(e);
-        let mut current = e;
-        loop {
-            match self.data.get(&current.idx) {
-                Some(&None) => { return None; }
-                Some(x) => { return x.as_ref(); }
-                None => {
-                    if let Some(&p) = self.parents.get(&current.idx) {
-                        current = p;
-                    } else {
-                        return None;
-                    }
-                }
-            }
+    pub fn get<E: Into<Entity>>(self, e: E) -> Option<&'a C> {
+        let e = e.into();
+        check_uid(self.active, e);
+        match find_provider(self.parents, self.data, e) {
+            Some(p) => self.data.get(p.idx).map_or(None, |x| x.as_ref()),
+            None => None
         }
     }
 
     /// Fetch a component from given entity. Do not search parent entities.
-    pub fn get_local(&'a self, e: Entity) -> Option<&'a C> {
-        self.check_uid(e);
-        self.data.get(&e.idx).map_or(None, |x| x.as_ref())
+    pub fn get_local<E: Into<Entity>>(self, e: E) -> Option<&'a C> {
+        let e = e.into();
+        check_uid(self.active, e);
+        self.data.get(e.idx).map_or(None, |x| x.as_ref())
     }
 
-    #[inline]
-    fn check_uid(&'a self, e: Entity) {
-        assert!(self.active.get(&e.idx) == Some(&e.uid), "Stale entity handle");
+    /// Return whether the entity or its parent has the component.
+    pub fn has<E: Into<Entity>>(self, e: E) -> bool { self.get(e).is_some() }
+
+    /// Return the entities that have a local component in index order. Does
+    /// not include entities that inherit the component from a parent.
+    pub fn entities_local(self) -> Vec<Entity> {
+        let mut ret: Vec<Entity> = self.data.iter()
+            .filter(|&(_, x)| x.is_some())
+            .map(|(idx, _)| Entity { idx: idx, uid: self.active[&idx] })
+            .collect();
+        ret.sort();
+        ret
+    }
+
+    /// Return all the entities that have the component, including the ones
+    /// that inherit it from a parent, in index order. Prototype entities are
+    /// included.
+    ///
+    /// Join queries for multiple components should call this for the
+    /// rarest component and filter the result with `has` for the others.
+    pub fn entities(self) -> Vec<Entity> {
+        let mut ret = self.entities_local();
+        let data = self.data;
+        let active = self.active;
+        self.children.with(self.parents, |children| {
+            // Only prototypes have children, follow their descendants that
+            // don't have a local or hidden value of their own.
+            let mut stack: Vec<Idx> = ret.iter()
+                .filter(|e| e.is_prototype())
+                .map(|e| e.idx)
+                .collect();
+            while let Some(idx) = stack.pop() {
+                for &child in children.get(&idx).map_or(&[][..], |x| &x[..]).iter() {
+                    if data.contains_key(child) { continue; }
+                    ret.push(Entity { idx: child, uid: active[&child] });
+                    stack.push(child);
+                }
+            }
+        });
+        ret.sort();
+        ret
     }
 }
 
@@ -83,11 +179,11 @@ impl<'a, C> CompRef<'a, C> {
 pub struct CompRefMut<'a, C: 'static> {
     parents: &'a HashMap<Idx, Entity>,
     active: &'a HashMap<Idx, Uid>,
-    data: &'a mut HashMap<Idx, Option<C>>,
+    data: &'a mut Storage<C>,
 }
 
 impl<'a, C: Clone> CompRefMut<'a, C> {
-    pub fn new(parents: &'a HashMap<Idx, Entity>, active: &'a HashMap<Idx, Uid>, data: &'a mut HashMap<Idx, Option<C>>) -> CompRefMut<'a, C> {
+    pub fn new(parents: &'a HashMap<Idx, Entity>, active: &'a HashMap<Idx, Uid>, data: &'a mut Storage<C>) -> CompRefMut<'a, C> {
         CompRefMut {
             parents: parents,
             active: active,
@@ -97,64 +193,93 @@ impl<'a, C: Clone> CompRefMut<'a, C> {
 
     /// Fetch a component from given entity. Copy-on-write from parent
     /// if found on parent but not locally.
-    pub fn get(&'a mut self, e: Entity) -> Option<&'a mut C> {
-        self.check_uid(e);
-        if let Some(c) = self.cow_source(e) {
-            let comp = self.data.get(&c.idx).unwrap().as_ref().unwrap().clone();
-            self.data.insert(e.idx, Some(comp));
-        }
-
-        self.data.get_mut(&e.idx).map_or(None, |x| x.as_mut())
-    }
-
-    fn cow_source(&'a self, e: Entity) -> Option<Entity> {
-        let mut current = e;
-        loop {
-            match self.data.get(&current.idx) {
-                Some(&None) => { return None; }
-                Some(_) => {
-                    if current != e { return Some(current); } else { return None; }
-                }
-                None => {
-                    if let Some(&p) = self.parents.get(&current.idx) {
-                        current = p;
-                    } else {
-                        return None;
-                    }
-                }
+    pub fn get<E: Into<Entity>>(self, e: E) -> Option<&'a mut C> {
+        let e = e.into();
+        check_uid(self.active, e);
+        let data = self.data;
+        match find_provider(self.parents, data, e) {
+            None => { return None; }
+            Some(p) if p != e => {
+                let comp = data.get(p.idx).unwrap().as_ref().unwrap().clone();
+                data.insert(e.idx, Some(comp));
             }
+            _ => {}
         }
+
+        data.get_mut(e.idx).map_or(None, |x| x.as_mut())
     }
 
     /// Insert a component
-    pub fn insert(&'a mut self, e: Entity, comp: C) {
-        self.check_uid(e);
+    pub fn insert<E: Into<Entity>>(self, e: E, comp: C) {
+        let e = e.into();
+        check_uid(self.active, e);
         self.data.insert(e.idx, Some(comp));
     }
 
     /// Clear a component from  the entity. Will make parent prototype's
     /// version visible if there is one.
-    pub fn clear(&'a mut self, e: Entity) {
-        self.check_uid(e);
-        self.data.remove(&e.idx);
+    pub fn clear<E: Into<Entity>>(self, e: E) {
+        let e = e.into();
+        check_uid(self.active, e);
+        self.data.remove(e.idx);
     }
 
     /// Make the component locally invisible even if the parent
     /// prototype has it.
-    pub fn hide(&'a mut self, e: Entity) {
-        self.check_uid(e);
+    pub fn hide<E: Into<Entity>>(self, e: E) {
+        let e = e.into();
+        check_uid(self.active, e);
         self.data.insert(e.idx, None);
     }
+}
 
-    #[inline]
-    fn check_uid(&'a self, e: Entity) {
-        assert!(self.active.get(&e.idx) == Some(&e.uid), "Stale entity handle");
+#[inline]
+fn check_uid(active: &HashMap<Idx, Uid>, e: Entity) {
+    assert!(active.get(&e.idx) == Some(&e.uid), "Stale entity handle");
+}
+
+/// Find the entity in the parent chain that has a local or hidden value for
+/// the component.
+fn find_provider<C>(parents: &HashMap<Idx, Entity>, data: &Storage<C>, e: Entity) -> Option<Entity> {
+    let mut current = e;
+    loop {
+        if data.contains_key(current.idx) { return Some(current); }
+        match parents.get(&current.idx) {
+            Some(&p) => { current = p; }
+            None => { return None; }
+        }
     }
 }
 
 /// Opaque identifier for a component type
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct CompId(pub u16);
 
+/// Set of component types.
+///
+/// Used to clear or hide several components from an entity at once, see
+/// `Ecs::clear_components` and `Ecs::hide_components`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct CompMask(pub u64);
+
+impl CompMask {
+    /// Create an empty mask.
+    pub fn new() -> CompMask { CompMask(0) }
+
+    /// Return the mask with the given component added.
+    pub fn with(self, id: CompId) -> CompMask {
+        assert!(id.0 < 64, "Component mask only supports 64 components");
+        CompMask(self.0 | (1 << id.0))
+    }
+
+    /// Return whether the mask contains the component.
+    pub fn contains(self, id: CompId) -> bool {
+        id.0 < 64 && self.0 & (1 << id.0) != 0
+    }
+
+    pub fn is_empty(self) -> bool { self.0 == 0 }
+}
+
 /// Common operations for all component types
 pub trait Component<E> {
     /// Create an uniform syntax for attaching components to
@@ -193,7 +318,8 @@ macro_rules! Ecs {
     } => {
         mod _ecs_inner {
             use ::std::collections::{HashMap};
-            use ::calx_ecs::{Idx, Uid, Entity, CompRef, CompRefMut, CompId, Component};
+            use ::calx_ecs::{Idx, Uid, Entity, CompRef, CompRefMut, CompId, CompMask, Component, Storage};
+            use ::calx_ecs::ChildIndex;
 
             // Use the enum to convert components to numbers for component bit masks etc.
             #[allow(non_camel_case_types)]
@@ -212,7 +338,7 @@ macro_rules! Ecs {
 
             // Don't create noise if the user doesn't use every method.
             /// Entity component system main container.
-            #[derive(RustcEncodable, RustcDecodable)]
+            #[derive(Clone, RustcEncodable, RustcDecodable)]
             pub struct Ecs {
                 /// Next positional index
                 next_idx: Idx,
@@ -236,12 +362,15 @@ macro_rules! Ecs {
                 // TODO: Use BitVec when it's stable and serializable.
                 parents: HashMap<Idx, Entity>,
 
+                /// Children of the prototypes, derived from the parent table
+                children: ChildIndex,
+
                 // Component value storage.
                 //
                 // An explicit "None" value for a component means it's
                 // considered not present even if there's a parent entity that
                 // does have it.
-                $($compname: HashMap<Idx, Option<$comptype>>,)+
+                $($compname: Storage<$comptype>,)+
             }
 
             impl Ecs {
@@ -254,8 +383,9 @@ macro_rules! Ecs {
                         next_prototype_uid: -1,
                         active: HashMap::new(),
                         parents: HashMap::new(),
+                        children: ChildIndex::new(),
 
-                        $($compname: HashMap::new(),)+
+                        $($compname: Storage::new(),)+
                     }
                 }
 
@@ -300,7 +430,7 @@ macro_rules! Ecs {
                 fn remove_internal(&mut self, e: Entity) {
                     assert!(self.contains(e), "Deleting an entity not contained in ECS");
                     assert!(!e.is_prototype(), "Prototype entities cannot be deleted.");
-                    self.parents.remove(&e.idx);
+                    self.set_parent(e, None);
                     self.reusable_idxs.push(e.idx);
                     self.active.remove(&e.idx);
                 }
@@ -308,12 +438,14 @@ macro_rules! Ecs {
 
                 /// Set or unset a prototype parent for an entity.
                 pub fn set_parent(&mut self, e: Entity, parent: Option<Entity>) {
+                    if let Some(old) = self.parents.remove(&e.idx) {
+                        self.children.remove(old.idx, e.idx);
+                    }
                     if let Some(parent) = parent {
                         assert!(parent.is_prototype(), "Trying to assign non-prototype parent entity");
                         assert!(self.contains(parent), "Parent of entity not present in ECS");
                         self.parents.insert(e.idx, parent);
-                    } else {
-                        self.parents.remove(&e.idx);
+                        self.children.insert(parent.idx, e.idx);
                     }
                 }
 
@@ -373,7 +505,7 @@ macro_rules! Ecs {
                     if id >= TBL.len() { return false; }
                     match TBL[id] {
                         $(ComponentNum::$compname =>
-                          self.$compname().get(e).is_some(),
+                          self.$compname().has(e),
                         )+
                     }
                 }
@@ -387,10 +519,72 @@ macro_rules! Ecs {
                     self.remove_internal(e);
                 }
 
+                /// Return the set of components the entity has, including
+                /// the ones inherited from its parent.
+                pub fn mask(&self, e: Entity) -> CompMask {
+                    let mut ret = CompMask::new();
+                    $(
+                    if self.$compname().has(e) {
+                        ret = ret.with(CompId(ComponentNum::$compname as u16));
+                    }
+                    )+
+                    ret
+                }
+
+                /// Clear the local values of the components in the mask from
+                /// an entity. Values from the parent will become visible.
+                pub fn clear_components(&mut self, e: Entity, mask: CompMask) {
+                    $(
+                    if mask.contains(CompId(ComponentNum::$compname as u16)) {
+                        self.mu().$compname().clear(e);
+                    }
+                    )+
+                }
+
+                /// Hide the components in the mask from an entity, including
+                /// the ones it would inherit from its parent.
+                pub fn hide_components(&mut self, e: Entity, mask: CompMask) {
+                    $(
+                    if mask.contains(CompId(ComponentNum::$compname as u16)) {
+                        self.mu().$compname().hide(e);
+                    }
+                    )+
+                }
+
+                /// Make a new entity with the same parent and local component
+                /// values as an existing one. Cloning a prototype makes a new
+                /// prototype.
+                pub fn clone_entity(&mut self, e: Entity) -> Entity {
+                    assert!(self.contains(e), "Stale entity handle");
+                    let parent = self.get_parent(e);
+                    let ret = if e.is_prototype() {
+                        self.make_prototype(parent)
+                    } else {
+                        self.make(parent)
+                    };
+
+                    $({
+                        let value = self.$compname.get(e.idx).cloned();
+                        if let Some(value) = value {
+                            self.$compname.insert(ret.idx, value);
+                        }
+                    })+
+
+                    ret
+                }
+
+                /// Iterate the non-prototype entities in index order.
+                pub fn iter<'a>(&'a self) -> Iter<'a> {
+                    Iter {
+                        ecs: self,
+                        next: self.first_entity(),
+                    }
+                }
+
                 $(
                 /// Get immutable accessor to $compname.
                 pub fn $compname<'a>(&'a self) -> CompRef<'a, $comptype> {
-                    CompRef::new(&self.parents, &self.active, &self.$compname)
+                    CompRef::new(&self.parents, &self.children, &self.active, &self.$compname)
                 })+
 
                 /// Get mutable component accessor structure.
@@ -408,11 +602,28 @@ macro_rules! Ecs {
                 // Mutable accessors
                 $(
                 /// Get mutable accessor to $compname.
-                pub fn $compname<'b>(&'b mut self) -> CompRefMut<'b, $comptype> {
-                    CompRefMut::new(&self.ecs.parents, &self.ecs.active, &mut self.ecs.$compname)
+                pub fn $compname(self) -> CompRefMut<'a, $comptype> {
+                    let ecs = self.ecs;
+                    CompRefMut::new(&ecs.parents, &ecs.active, &mut ecs.$compname)
                 })+
             }
 
+            /// Iterator for the non-prototype entities in the ECS.
+            pub struct Iter<'a> {
+                ecs: &'a Ecs,
+                next: Option<Entity>,
+            }
+
+            impl<'a> Iterator for Iter<'a> {
+                type Item = Entity;
+
+                fn next(&mut self) -> Option<Entity> {
+                    let ret = self.next;
+                    if let Some(e) = ret { self.next = self.ecs.next_entity(e); }
+                    ret
+                }
+            }
+
             /// A helper struct for fluently adding components to an entity
             pub struct Build<'a> {
                 ecs: &'a mut Ecs,
@@ -449,6 +660,6 @@ macro_rules! Ecs {
             }
         }
 
-        pub use self::_ecs_inner::{Ecs, Build};
+        pub use self::_ecs_inner::{Ecs, Build, Iter};
     }
 }
@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::iter::{Cloned, Zip};
+use std::slice;
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+use Idx;
+
+/// Sparse set storage for a component type.
+///
+/// The components are kept in a dense array for fast iteration, with a
+/// sparse table from entity index to dense position for lookups. A `None`
+/// value is a component hidden from the parent entity, see
+/// `CompRefMut::hide`.
+#[derive(Clone)]
+pub struct Storage<C> {
+    /// Dense array position for each entity index.
+    sparse: Vec<Option<usize>>,
+    /// Entity indices of the dense values.
+    idxs: Vec<Idx>,
+    values: Vec<Option<C>>,
+}
+
+pub type StorageIter<'a, C> = Zip<Cloned<slice::Iter<'a, Idx>>, slice::Iter<'a, Option<C>>>;
+
+impl<C> Storage<C> {
+    pub fn new() -> Storage<C> {
+        Storage {
+            sparse: Vec::new(),
+            idxs: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    fn pos(&self, idx: Idx) -> Option<usize> {
+        let idx = idx as usize;
+        if idx < self.sparse.len() { self.sparse[idx] } else { None }
+    }
+
+    pub fn contains_key(&self, idx: Idx) -> bool { self.pos(idx).is_some() }
+
+    pub fn get(&self, idx: Idx) -> Option<&Option<C>> {
+        self.pos(idx).map(|p| &self.values[p])
+    }
+
+    pub fn get_mut(&mut self, idx: Idx) -> Option<&mut Option<C>> {
+        match self.pos(idx) {
+            Some(p) => Some(&mut self.values[p]),
+            None => None
+        }
+    }
+
+    pub fn insert(&mut self, idx: Idx, value: Option<C>) {
+        if let Some(p) = self.pos(idx) {
+            self.values[p] = value;
+            return;
+        }
+
+        while self.sparse.len() <= idx as usize { self.sparse.push(None); }
+        self.sparse[idx as usize] = Some(self.idxs.len());
+        self.idxs.push(idx);
+        self.values.push(value);
+    }
+
+    pub fn remove(&mut self, idx: Idx) {
+        if let Some(p) = self.pos(idx) {
+            self.sparse[idx as usize] = None;
+            self.idxs.swap_remove(p);
+            self.values.swap_remove(p);
+            // Fix the index of the value that was moved into the hole.
+            if p < self.idxs.len() {
+                self.sparse[self.idxs[p] as usize] = Some(p);
+            }
+        }
+    }
+
+    /// Iterate the entity indices and values in the storage in no
+    /// particular order.
+    pub fn iter<'a>(&'a self) -> StorageIter<'a, C> {
+        self.idxs.iter().cloned().zip(self.values.iter())
+    }
+
+    pub fn len(&self) -> usize { self.idxs.len() }
+}
+
+impl<C: Decodable> Decodable for Storage<C> {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Storage<C>, D::Error> {
+        // Insert in index order so that iteration order doesn't depend on
+        // hash map ordering.
+        let map: BTreeMap<Idx, Option<C>> = try!(Decodable::decode(d));
+        let mut ret = Storage::new();
+        for (idx, value) in map.into_iter() {
+            ret.insert(idx, value);
+        }
+        Ok(ret)
+    }
+}
+
+impl<C: Encodable> Encodable for Storage<C> {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        // Same format as a plain index to value map.
+        let map: BTreeMap<Idx, &Option<C>> = self.iter().collect();
+        map.encode(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Storage;
+
+    #[test]
+    fn test_storage() {
+        let mut s = Storage::new();
+        s.insert(5, Some('a'));
+        s.insert(1, Some('b'));
+        s.insert(3, None);
+        assert_eq!(s.len(), 3);
+        assert_eq!(s.get(5), Some(&Some('a')));
+        assert_eq!(s.get(3), Some(&None));
+        assert_eq!(s.get(2), None);
+
+        s.remove(5);
+        assert!(!s.contains_key(5));
+        assert_eq!(s.get(1), Some(&Some('b')));
+        assert_eq!(s.get(3), Some(&None));
+
+        let mut items: Vec<(u32, Option<char>)> = s.iter().map(|(i, &v)| (i, v)).collect();
+        items.sort();
+        assert_eq!(items, vec![(1, Some('b')), (3, None)]);
+    }
+}
@@ -12,6 +12,7 @@ use ::{AtlasBuilder, Atlas, AtlasItem, V2, IterTiles};
 use super::event::{Event, MouseButton};
 use super::key::{Key};
 use super::renderer::{Renderer, Vertex};
+use super::soft_renderer::{SoftRenderer};
 use super::scancode;
 use super::{WidgetId, CanvasMagnify};
 
@@ -31,6 +32,7 @@ static SOLID_IDX: usize = 96;
 pub struct CanvasBuilder {
     title: String,
     size: V2<u32>,
+    window_size: Option<V2<u32>>,
     frame_interval: Option<f64>,
     fullscreen: bool,
     layout_independent_keys: bool,
@@ -43,6 +45,7 @@ impl CanvasBuilder {
         let mut ret = CanvasBuilder {
             title: "".to_string(),
             size: V2(640, 360),
+            window_size: None,
             frame_interval: None,
             fullscreen: false,
             layout_independent_keys: true,
@@ -73,6 +76,13 @@ impl CanvasBuilder {
         self
     }
 
+    /// Set the initial window size in pixels. By default the window is made
+    /// the largest even multiple of the canvas size that fits the screen.
+    pub fn set_window_size(mut self, width: u32, height: u32) -> CanvasBuilder {
+        self.window_size = Some(V2(width, height));
+        self
+    }
+
     /// Get the key values from the user's keyboard layout instead of the
     /// hardware keyboard map. Hardware keymap lookup may not work correctly
     /// on all platforms.
@@ -104,6 +114,15 @@ impl CanvasBuilder {
         Canvas::new(self)
     }
 
+    /// Build a canvas that renders in software without opening a window.
+    ///
+    /// The canvas gets no input, `next_event` returns only render events.
+    /// Use `screenshot` to get the rendered image after the frame has been
+    /// finished by the next `next_event` call.
+    pub fn build_software<'a>(self) -> Canvas<'a> {
+        Canvas::new_software(self)
+    }
+
     /// Load the default font into the texture atlas.
     fn init_font(&mut self) {
         let mut font_sheet = ::color_key(
@@ -126,9 +145,8 @@ impl CanvasBuilder {
 
 /// Interface to render to a live display.
 pub struct Canvas<'a> {
-    display: glium::Display,
+    backend: Backend<'a>,
     events: Vec<glutin::Event>,
-    renderer: Renderer<'a>,
 
     atlas: Atlas,
 
@@ -155,6 +173,14 @@ pub struct Canvas<'a> {
     pub last_widget: Option<WidgetId>,
 }
 
+/// Where the canvas geometry gets rendered.
+enum Backend<'a> {
+    /// OpenGL rendering to a window.
+    Display(glium::Display, Renderer<'a>),
+    /// Software rendering to an image.
+    Software(SoftRenderer),
+}
+
 #[derive(PartialEq)]
 enum State {
     Normal,
@@ -165,13 +191,12 @@ impl<'a> Canvas<'a> {
     fn new(builder: CanvasBuilder) -> Canvas<'a> {
         use glutin::{GlRequest, Api};
         let size = builder.size;
-        let title = &builder.title[..];
-        let frame_interval = builder.frame_interval;
+        let title = builder.title.clone();
         let atlas = Atlas::new(&builder.atlas_builder);
 
         let mut glutin = glutin::WindowBuilder::new()
             .with_gl(GlRequest::Specific(Api::OpenGl, (3, 2)))
-            .with_title(title.to_string());
+            .with_title(title);
 
         if builder.fullscreen {
             // FIXME: Glutin's X11 fullscreen is broken, this is only enabled
@@ -179,6 +204,8 @@ impl<'a> Canvas<'a> {
             if cfg!(windows) {
                 glutin = glutin.with_fullscreen(glutin::get_primary_monitor());
             }
+        } else if let Some(V2(x, y)) = builder.window_size {
+            glutin = glutin.with_dimensions(x, y);
         } else {
             // Zoom up the window to the biggest even pixel multiple that fits
             // the user's monitor.
@@ -205,18 +232,29 @@ impl<'a> Canvas<'a> {
             tex_image.clone().into_raw(), image_dimensions);
         let renderer = Renderer::new(size, &display, tex_image, builder.magnify);
 
+        Canvas::from_parts(builder, atlas, Backend::Display(display, renderer), V2(w as i32, h as i32))
+    }
+
+    fn new_software(builder: CanvasBuilder) -> Canvas<'a> {
+        let atlas = Atlas::new(&builder.atlas_builder);
+        let renderer = SoftRenderer::new(builder.size, atlas.image.clone());
+        let resolution = builder.size.map(|x| x as i32);
+        Canvas::from_parts(builder, atlas, Backend::Software(renderer), resolution)
+    }
+
+    fn from_parts(builder: CanvasBuilder, atlas: Atlas, backend: Backend<'a>,
+                  window_resolution: V2<i32>) -> Canvas<'a> {
         Canvas {
-            display: display,
+            backend: backend,
             events: Vec::new(),
-            renderer: renderer,
 
             atlas: atlas,
 
             state: State::Normal,
-            frame_interval: frame_interval,
+            frame_interval: builder.frame_interval,
             last_render_time: time::precise_time_s(),
-            size: size,
-            window_resolution: V2(w as i32, h as i32),
+            size: builder.size,
+            window_resolution: window_resolution,
 
             meshes: vec![Mesh::new()],
 
@@ -232,6 +270,28 @@ impl<'a> Canvas<'a> {
         }
     }
 
+    /// Set the frame rate.
+    pub fn set_frame_interval(&mut self, interval_s: f64) {
+        assert!(interval_s > 0.00001);
+        self.frame_interval = Some(interval_s);
+    }
+
+    /// Change how the canvas is scaled to the window.
+    pub fn set_magnify(&mut self, magnify: CanvasMagnify) {
+        if let Backend::Display(_, ref mut renderer) = self.backend {
+            renderer.set_magnify(magnify);
+        }
+    }
+
+    /// Resize the window.
+    pub fn set_window_size(&mut self, width: u32, height: u32) {
+        if let Backend::Display(ref display, _) = self.backend {
+            if let Some(window) = display.get_window() {
+                window.set_inner_size(width, height);
+            }
+        }
+    }
+
     /// Clear the screen.
     pub fn clear(&mut self) {
         // TODO: use the color.
@@ -319,7 +379,10 @@ impl<'a> Canvas<'a> {
 
     /// Return a screenshot image of the last frame rendered.
     pub fn screenshot(&self) -> ImageBuffer<image::Rgb<u8>, Vec<u8>> {
-        self.renderer.canvas_pixels()
+        match self.backend {
+            Backend::Display(_, ref renderer) => renderer.canvas_pixels(),
+            Backend::Software(ref renderer) => renderer.canvas_pixels(),
+        }
     }
 
     fn imgui_prepare(&mut self) {
@@ -345,23 +408,42 @@ impl<'a> Canvas<'a> {
         if self.state == State::EndFrame {
             self.state = State::Normal;
 
-            let mut target = self.display.draw();
-
-            self.renderer.init(&self.display);
             let meshes = mem::replace(&mut self.meshes, vec![Mesh::new()]);
-            for mesh in meshes.into_iter() {
-                // Move out the accumulated geometry data.
-                self.renderer.draw(&self.display, mesh.vertices, mesh.indices);
+            match self.backend {
+                Backend::Display(ref display, ref mut renderer) => {
+                    let mut target = display.draw();
+
+                    renderer.init(display);
+                    for mesh in meshes.into_iter() {
+                        // Move out the accumulated geometry data.
+                        renderer.draw(display, mesh.vertices, mesh.indices);
+                    }
+                    renderer.show(display, &mut target);
+                    target.finish();
+                }
+                Backend::Software(ref mut renderer) => {
+                    renderer.init();
+                    for mesh in meshes.into_iter() {
+                        renderer.draw(mesh.vertices, mesh.indices);
+                    }
+                }
             }
-            self.renderer.show(&self.display, &mut target);
-            target.finish();
 
             self.imgui_finish();
         }
 
+        if let Backend::Software(_) = self.backend {
+            // No window, no input events, just keep rendering.
+            self.state = State::EndFrame;
+            self.imgui_prepare();
+            return Event::RenderFrame;
+        }
+
         let mut app_focused = true;
         loop {
-            self.events.extend(self.display.poll_events());
+            if let Backend::Display(ref display, _) = self.backend {
+                self.events.extend(display.poll_events());
+            }
 
             if !self.events.is_empty() {
                 app_focused = true;
@@ -388,7 +470,10 @@ impl<'a> Canvas<'a> {
                         }
                     }
                     glutin::Event::MouseMoved(x, y) => {
-                        let pixel_pos = self.renderer.screen_to_canvas(V2(x, y));
+                        let pixel_pos = match self.backend {
+                            Backend::Display(_, ref renderer) => renderer.screen_to_canvas(V2(x, y)),
+                            Backend::Software(_) => V2(x, y),
+                        };
                         self.mouse_pos = pixel_pos.map(|x| x as f32);
                         return Event::MouseMoved((pixel_pos.0, pixel_pos.1));
                     }
@@ -435,8 +520,10 @@ impl<'a> Canvas<'a> {
 
                 self.state = State::EndFrame;
 
-                let (w, h) = self.display.get_framebuffer_dimensions();
-                self.window_resolution = V2(w as i32, h as i32);
+                if let Backend::Display(ref display, _) = self.backend {
+                    let (w, h) = display.get_framebuffer_dimensions();
+                    self.window_resolution = V2(w as i32, h as i32);
+                }
 
                 self.imgui_prepare();
 
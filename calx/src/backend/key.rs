@@ -1,3 +1,6 @@
+use std::fmt;
+use std::str::FromStr;
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Key {
     Space = 2,
@@ -105,3 +108,137 @@ pub enum Key {
     RightAlt = 104,
     RightSuper = 105,
 }
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Parse a key from its variant name, eg. "PageUp" or "Pad5".
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Key, String> {
+        match s {
+            "Space" => Ok(Key::Space),
+            "Apostrophe" => Ok(Key::Apostrophe),
+            "Comma" => Ok(Key::Comma),
+            "Minus" => Ok(Key::Minus),
+            "Period" => Ok(Key::Period),
+            "Slash" => Ok(Key::Slash),
+            "Num0" => Ok(Key::Num0),
+            "Num1" => Ok(Key::Num1),
+            "Num2" => Ok(Key::Num2),
+            "Num3" => Ok(Key::Num3),
+            "Num4" => Ok(Key::Num4),
+            "Num5" => Ok(Key::Num5),
+            "Num6" => Ok(Key::Num6),
+            "Num7" => Ok(Key::Num7),
+            "Num8" => Ok(Key::Num8),
+            "Num9" => Ok(Key::Num9),
+            "Semicolon" => Ok(Key::Semicolon),
+            "Equals" => Ok(Key::Equals),
+            "A" => Ok(Key::A),
+            "B" => Ok(Key::B),
+            "C" => Ok(Key::C),
+            "D" => Ok(Key::D),
+            "E" => Ok(Key::E),
+            "F" => Ok(Key::F),
+            "G" => Ok(Key::G),
+            "H" => Ok(Key::H),
+            "I" => Ok(Key::I),
+            "J" => Ok(Key::J),
+            "K" => Ok(Key::K),
+            "L" => Ok(Key::L),
+            "M" => Ok(Key::M),
+            "N" => Ok(Key::N),
+            "O" => Ok(Key::O),
+            "P" => Ok(Key::P),
+            "Q" => Ok(Key::Q),
+            "R" => Ok(Key::R),
+            "S" => Ok(Key::S),
+            "T" => Ok(Key::T),
+            "U" => Ok(Key::U),
+            "V" => Ok(Key::V),
+            "W" => Ok(Key::W),
+            "X" => Ok(Key::X),
+            "Y" => Ok(Key::Y),
+            "Z" => Ok(Key::Z),
+            "LeftBracket" => Ok(Key::LeftBracket),
+            "Backslash" => Ok(Key::Backslash),
+            "RightBracket" => Ok(Key::RightBracket),
+            "Grave" => Ok(Key::Grave),
+            "Escape" => Ok(Key::Escape),
+            "Enter" => Ok(Key::Enter),
+            "Tab" => Ok(Key::Tab),
+            "Backspace" => Ok(Key::Backspace),
+            "Insert" => Ok(Key::Insert),
+            "Delete" => Ok(Key::Delete),
+            "Right" => Ok(Key::Right),
+            "Left" => Ok(Key::Left),
+            "Down" => Ok(Key::Down),
+            "Up" => Ok(Key::Up),
+            "PageUp" => Ok(Key::PageUp),
+            "PageDown" => Ok(Key::PageDown),
+            "Home" => Ok(Key::Home),
+            "End" => Ok(Key::End),
+            "CapsLock" => Ok(Key::CapsLock),
+            "ScrollLock" => Ok(Key::ScrollLock),
+            "NumLock" => Ok(Key::NumLock),
+            "PrintScreen" => Ok(Key::PrintScreen),
+            "Pause" => Ok(Key::Pause),
+            "F1" => Ok(Key::F1),
+            "F2" => Ok(Key::F2),
+            "F3" => Ok(Key::F3),
+            "F4" => Ok(Key::F4),
+            "F5" => Ok(Key::F5),
+            "F6" => Ok(Key::F6),
+            "F7" => Ok(Key::F7),
+            "F8" => Ok(Key::F8),
+            "F9" => Ok(Key::F9),
+            "F10" => Ok(Key::F10),
+            "F11" => Ok(Key::F11),
+            "F12" => Ok(Key::F12),
+            "Pad0" => Ok(Key::Pad0),
+            "Pad1" => Ok(Key::Pad1),
+            "Pad2" => Ok(Key::Pad2),
+            "Pad3" => Ok(Key::Pad3),
+            "Pad4" => Ok(Key::Pad4),
+            "Pad5" => Ok(Key::Pad5),
+            "Pad6" => Ok(Key::Pad6),
+            "Pad7" => Ok(Key::Pad7),
+            "Pad8" => Ok(Key::Pad8),
+            "Pad9" => Ok(Key::Pad9),
+            "PadDecimal" => Ok(Key::PadDecimal),
+            "PadDivide" => Ok(Key::PadDivide),
+            "PadMultiply" => Ok(Key::PadMultiply),
+            "PadMinus" => Ok(Key::PadMinus),
+            "PadPlus" => Ok(Key::PadPlus),
+            "PadEnter" => Ok(Key::PadEnter),
+            "PadEquals" => Ok(Key::PadEquals),
+            "LeftShift" => Ok(Key::LeftShift),
+            "LeftControl" => Ok(Key::LeftControl),
+            "LeftAlt" => Ok(Key::LeftAlt),
+            "LeftSuper" => Ok(Key::LeftSuper),
+            "RightShift" => Ok(Key::RightShift),
+            "RightControl" => Ok(Key::RightControl),
+            "RightAlt" => Ok(Key::RightAlt),
+            "RightSuper" => Ok(Key::RightSuper),
+            _ => Err(format!("Unknown key '{}'", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Key;
+
+    #[test]
+    fn test_key_names() {
+        for &k in [Key::Space, Key::A, Key::F12, Key::Pad5, Key::RightSuper].iter() {
+            assert_eq!(format!("{}", k).parse::<Key>(), Ok(k));
+        }
+        assert!("Foo".parse::<Key>().is_err());
+    }
+}
@@ -10,13 +10,16 @@ pub use backend::key::Key;
 pub use backend::fonter::{Fonter, Align};
 pub use backend::event::{Event, MouseButton};
 pub use backend::sprite_cache::{SpriteCache, SpriteKey};
+pub use backend::recorder::{Recorder, RecordFormat};
 
 mod canvas;
 mod canvas_util;
 mod event;
 mod fonter;
 mod key;
+mod recorder;
 mod renderer;
+mod soft_renderer;
 mod sprite_cache;
 
 #[cfg(target_os = "macos")]
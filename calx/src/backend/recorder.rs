@@ -0,0 +1,280 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write, BufWriter};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use time;
+use image::{self, ImageBuffer, GenericImage};
+use gif;
+use color_quant::NeuQuant;
+
+/// File format for recorded frames.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RecordFormat {
+    /// Single animated GIF file.
+    Gif,
+    /// Directory of numbered PNG files.
+    PngSequence,
+}
+
+/// Records a sequence of frames into an animated GIF or a PNG sequence.
+///
+/// Feed it every rendered frame with `add_frame`, it'll skip frames as
+/// configured and stop when the output grows past the size cap.
+pub struct Recorder {
+    format: RecordFormat,
+    /// Output file for GIF, output directory for PNG sequence.
+    path: PathBuf,
+    /// Number of frames to drop after each recorded one.
+    frame_skip: u32,
+    /// Stop recording after writing this many bytes.
+    max_bytes: u64,
+    /// Seconds between rendered frames.
+    frame_interval: f64,
+    frames_seen: u32,
+    frames_written: u32,
+    bytes_written: Rc<Cell<u64>>,
+    gif: Option<gif::Encoder<Counter<BufWriter<File>>>>,
+}
+
+impl Recorder {
+    /// Start a recording into a timestamped file or directory in `dir`.
+    /// Frame interval is the time between rendered frames in seconds.
+    pub fn new(format: RecordFormat, dir: &Path, basename: &str,
+               frame_interval: f64) -> Result<Recorder, String> {
+        try!(fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.to_string_lossy(), e)));
+
+        let timestamp = time::strftime("%Y%m%d-%H%M%S", &time::now()).unwrap();
+        // Add a running number if there already is a recording from the
+        // same second, never overwrite old recordings.
+        let mut path = None;
+        for i in 0..100 {
+            let name = if i == 0 { format!("{}-{}", basename, timestamp) }
+                else { format!("{}-{}-{}", basename, timestamp, i) };
+            match format {
+                RecordFormat::Gif => {
+                    let candidate = dir.join(format!("{}.gif", name));
+                    if fs::metadata(&candidate).is_err() {
+                        path = Some(candidate);
+                        break;
+                    }
+                }
+                RecordFormat::PngSequence => {
+                    // Creating the directory fails if it already exists.
+                    let candidate = dir.join(name);
+                    if fs::create_dir(&candidate).is_ok() {
+                        path = Some(candidate);
+                        break;
+                    }
+                }
+            }
+        }
+        let path = try!(path.ok_or(
+                format!("{}: Too many recordings named {}-{}", dir.to_string_lossy(),
+                        basename, timestamp)));
+
+        Ok(Recorder {
+            format: format,
+            path: path,
+            frame_skip: 0,
+            max_bytes: 20 * 1024 * 1024,
+            frame_interval: frame_interval,
+            frames_seen: 0,
+            frames_written: 0,
+            bytes_written: Rc::new(Cell::new(0)),
+            gif: None,
+        })
+    }
+
+    /// Record only every n + 1th frame.
+    pub fn frame_skip(mut self, n: u32) -> Recorder {
+        self.frame_skip = n;
+        self
+    }
+
+    /// Stop recording when the output is larger than this.
+    pub fn max_bytes(mut self, bytes: u64) -> Recorder {
+        self.max_bytes = bytes;
+        self
+    }
+
+    /// GIF frame delay in hundredths of a second.
+    fn delay(&self) -> u16 {
+        let delay = (self.frame_interval * (self.frame_skip + 1) as f64 * 100.0).round();
+        // Browsers play delays below 2 as 10, don't go under it.
+        delay.max(2.0).min(0xFFFF as f64) as u16
+    }
+
+    /// Where the recording is written to.
+    pub fn path(&self) -> &Path { &self.path }
+
+    /// Number of frames recorded so far.
+    pub fn frames(&self) -> u32 { self.frames_written }
+
+    /// Whether the size cap has been reached. The recording should be
+    /// finished then.
+    pub fn is_full(&self) -> bool { self.bytes_written.get() >= self.max_bytes }
+
+    /// Add a rendered frame to the recording. The grab function is only
+    /// called for frames that aren't skipped, since reading back the frame
+    /// from the GPU is slow. Frames after the size cap has been reached are
+    /// ignored.
+    pub fn add_frame<F>(&mut self, grab: F) -> Result<(), String>
+        where F: FnOnce() -> ImageBuffer<image::Rgb<u8>, Vec<u8>> {
+        let skip = self.frames_seen % (self.frame_skip + 1) != 0;
+        self.frames_seen += 1;
+        if skip || self.is_full() { return Ok(()); }
+
+        let img = grab();
+        let img = &img;
+
+        match self.format {
+            RecordFormat::Gif => try!(self.add_gif_frame(img)),
+            RecordFormat::PngSequence => {
+                let path = self.path.join(format!("{:05}.png", self.frames_written));
+                try!(image::save_buffer(&path, img, img.width(), img.height(), image::ColorType::RGB(8))
+                     .map_err(|e| format!("{}: {}", path.to_string_lossy(), e)));
+                let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                self.bytes_written.set(self.bytes_written.get() + size);
+            }
+        }
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    fn add_gif_frame(&mut self, img: &ImageBuffer<image::Rgb<u8>, Vec<u8>>) -> Result<(), String> {
+        let (w, h) = img.dimensions();
+        if w > 0xFFFF || h > 0xFFFF { return Err("Frame too large for GIF".to_string()); }
+
+        if self.gif.is_none() {
+            let file = try!(File::create(&self.path).map_err(|e| self.io_error(e)));
+            let counter = Counter { inner: BufWriter::new(file), count: self.bytes_written.clone() };
+            let mut encoder = try!(gif::Encoder::new(counter, w as u16, h as u16, &[])
+                                   .map_err(|e| self.io_error(e)));
+            try!(encoder.write_extension(gif::ExtensionData::Repetitions(gif::Repeat::Infinite))
+                 .map_err(|e| self.io_error(e)));
+            self.gif = Some(encoder);
+        }
+
+        let mut frame = quantize(w as u16, h as u16, img.clone().into_raw());
+        frame.delay = self.delay();
+        let result = self.gif.as_mut().unwrap().write_frame(&frame);
+        result.map_err(|e| self.io_error(e))
+    }
+
+    fn io_error(&self, e: io::Error) -> String {
+        format!("{}: {}", self.path.to_string_lossy(), e)
+    }
+
+    /// Finish writing the recording and return the path it was written to.
+    pub fn finish(mut self) -> Result<PathBuf, String> {
+        if let Some(encoder) = self.gif.take() {
+            // Dropping the encoder writes the GIF trailer, the BufWriter
+            // gets flushed when it's dropped with the encoder.
+            drop(encoder);
+        }
+        if self.frames_written == 0 {
+            let _ = match self.format {
+                RecordFormat::Gif => fs::remove_file(&self.path),
+                RecordFormat::PngSequence => fs::remove_dir(&self.path),
+            };
+            return Err("No frames recorded".to_string());
+        }
+        Ok(self.path)
+    }
+}
+
+/// Convert an RGB frame into an indexed GIF frame.
+///
+/// Uses the exact colors if there are few enough of them, which is usually
+/// the case for pixel art, and falls back to NeuQuant otherwise.
+fn quantize(w: u16, h: u16, pixels: Vec<u8>) -> gif::Frame<'static> {
+    let mut palette = Vec::new();
+    let mut indices: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut buffer = Vec::with_capacity(pixels.len() / 3);
+
+    for p in pixels.chunks(3) {
+        let key = [p[0], p[1], p[2]];
+        if let Some(&i) = indices.get(&key) {
+            buffer.push(i);
+            continue;
+        }
+        if indices.len() == 256 {
+            // Too many colors.
+            buffer.clear();
+            break;
+        }
+        let i = indices.len() as u8;
+        indices.insert(key, i);
+        palette.extend(key.iter().cloned());
+        buffer.push(i);
+    }
+
+    if buffer.len() != pixels.len() / 3 {
+        let rgba: Vec<u8> = pixels.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 0xFF]).collect();
+        // Sample every 10th pixel, the full quality setting is too slow to
+        // keep up with rendering.
+        let nq = NeuQuant::new(10, 256, &rgba);
+        buffer = rgba.chunks(4).map(|p| nq.index_of(p) as u8).collect();
+        palette = nq.color_map_rgb();
+    }
+
+    let mut frame = gif::Frame::default();
+    frame.width = w;
+    frame.height = h;
+    frame.palette = Some(palette);
+    frame.buffer = Cow::Owned(buffer);
+    frame
+}
+
+/// Writer that keeps track of how many bytes have gone through it.
+struct Counter<W> {
+    inner: W,
+    count: Rc<Cell<u64>>,
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = try!(self.inner.write(buf));
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use time;
+    use image::{self, ImageBuffer};
+    use super::{quantize, Recorder, RecordFormat};
+
+    #[test]
+    fn test_unique_paths() {
+        let dir = env::temp_dir().join(format!("calx-recorder-{}", time::precise_time_ns()));
+        let a = Recorder::new(RecordFormat::PngSequence, &dir, "test", 0.1).unwrap();
+        let b = Recorder::new(RecordFormat::PngSequence, &dir, "test", 0.1).unwrap();
+        assert!(a.path != b.path);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_quantize() {
+        let img = ImageBuffer::from_fn(4, 4, |x, y| {
+            if (x + y) % 2 == 0 { image::Rgb([255, 0, 0]) } else { image::Rgb([0, 0, 255]) }
+        });
+        let frame = quantize(4, 4, img.into_raw());
+        assert_eq!(frame.palette, Some(vec![255, 0, 0, 0, 0, 255]));
+        assert_eq!(&frame.buffer[..4], &[0, 1, 0, 1]);
+
+        // Over 256 colors.
+        let img = ImageBuffer::from_fn(32, 32, |x, y| image::Rgb([x as u8 * 8, y as u8 * 8, 0]));
+        let frame = quantize(32, 32, img.into_raw());
+        assert_eq!(frame.buffer.len(), 32 * 32);
+        assert_eq!(frame.palette.unwrap().len(), 256 * 3);
+    }
+}
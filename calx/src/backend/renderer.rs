@@ -72,6 +72,11 @@ impl<'a> Renderer<'a> {
         }
     }
 
+    /// Change how the canvas is scaled to the window.
+    pub fn set_magnify(&mut self, magnify: CanvasMagnify) {
+        self.magnify = magnify;
+    }
+
     /// Draw sprites on target.
     fn draw_sprites<S>(&self, display: &glium::Display, target: &mut S,
                        vertices: Vec<Vertex>, indices: Vec<u16>)
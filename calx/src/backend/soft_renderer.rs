@@ -0,0 +1,151 @@
+use image::{self, GenericImage, ImageBuffer, Rgba};
+use ::V2;
+use ::rgb::{to_srgb};
+use super::renderer::{Vertex};
+
+/// CPU rasterizer for the canvas geometry.
+///
+/// Follows the same rules as the sprite shader of the OpenGL renderer:
+/// counterclockwise triangles are culled, texels with zero alpha are
+/// discarded, depth test passes on less or equal and the result is alpha
+/// blended.
+pub struct SoftRenderer {
+    /// Canvas size.
+    size: V2<u32>,
+    /// Atlas texture.
+    atlas: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    /// Render target in linear color.
+    buffer: Vec<[f32; 4]>,
+    depth: Vec<f32>,
+}
+
+impl SoftRenderer {
+    pub fn new(size: V2<u32>, atlas: ImageBuffer<Rgba<u8>, Vec<u8>>) -> SoftRenderer {
+        let n = (size.0 * size.1) as usize;
+        SoftRenderer {
+            size: size,
+            atlas: atlas,
+            buffer: vec![[0.0; 4]; n],
+            depth: vec![1.0; n],
+        }
+    }
+
+    /// Call at the start of drawing
+    pub fn init(&mut self) {
+        for p in self.buffer.iter_mut() { *p = [0.0; 4]; }
+        for d in self.depth.iter_mut() { *d = 1.0; }
+    }
+
+    /// Draw a geometry buffer.
+    pub fn draw(&mut self, vertices: Vec<Vertex>, indices: Vec<u16>) {
+        for tri in indices.chunks(3) {
+            if tri.len() < 3 { break; }
+            self.draw_triangle(&vertices[tri[0] as usize],
+                               &vertices[tri[1] as usize],
+                               &vertices[tri[2] as usize]);
+        }
+    }
+
+    fn draw_triangle(&mut self, a: &Vertex, b: &Vertex, c: &Vertex) {
+        let (w, h) = (self.size.0 as f32, self.size.1 as f32);
+        // Device coordinates to pixel coordinates.
+        let to_pixel = |v: &Vertex| V2((v.pos[0] + 1.0) * w / 2.0, (1.0 - v.pos[1]) * h / 2.0);
+        let (pa, pb, pc) = (to_pixel(a), to_pixel(b), to_pixel(c));
+
+        // The y axis is flipped from device coordinates, so clockwise
+        // triangles in device space have positive area here.
+        let area = edge(pa, pb, pc);
+        if area <= 0.0 { return; }
+
+        let x0 = pa.0.min(pb.0).min(pc.0).floor().max(0.0).min(w) as u32;
+        let y0 = pa.1.min(pb.1).min(pc.1).floor().max(0.0).min(h) as u32;
+        let x1 = pa.0.max(pb.0).max(pc.0).ceil().max(0.0).min(w) as u32;
+        let y1 = pa.1.max(pb.1).max(pc.1).ceil().max(0.0).min(h) as u32;
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // Sample at pixel center.
+                let p = V2(x as f32 + 0.5, y as f32 + 0.5);
+                let wa = edge(pb, pc, p) / area;
+                let wb = edge(pc, pa, p) / area;
+                let wc = edge(pa, pb, p) / area;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 { continue; }
+
+                let lerp = |fa: f32, fb: f32, fc: f32| wa * fa + wb * fb + wc * fc;
+
+                let z = lerp(a.pos[2], b.pos[2], c.pos[2]);
+                let idx = (y * self.size.0 + x) as usize;
+                if z > self.depth[idx] { continue; }
+
+                let tex = self.texel(lerp(a.tex_coord[0], b.tex_coord[0], c.tex_coord[0]),
+                                     lerp(a.tex_coord[1], b.tex_coord[1], c.tex_coord[1]));
+                if tex[3] == 0.0 { continue; }
+
+                let mut frag = [0.0; 4];
+                for i in 0..4 {
+                    let col = lerp(a.color[i], b.color[i], c.color[i]);
+                    let back = lerp(a.back_color[i], b.back_color[i], c.back_color[i]);
+                    frag[i] = col * tex[i] + back * (1.0 - tex[i]);
+                }
+
+                let dst = self.buffer[idx];
+                let alpha = frag[3];
+                let mut out = [0.0; 4];
+                for i in 0..4 {
+                    out[i] = frag[i] * alpha + dst[i] * (1.0 - alpha);
+                }
+                self.buffer[idx] = out;
+                self.depth[idx] = z;
+            }
+        }
+    }
+
+    /// Nearest neighbor sample from the atlas.
+    fn texel(&self, u: f32, v: f32) -> [f32; 4] {
+        let (w, h) = self.atlas.dimensions();
+        let x = ((u * w as f32).floor().max(0.0) as u32).min(w - 1);
+        let y = ((v * h as f32).floor().max(0.0) as u32).min(h - 1);
+        let p = self.atlas.get_pixel(x, y).data;
+        [p[0] as f32 / 255.0, p[1] as f32 / 255.0, p[2] as f32 / 255.0, p[3] as f32 / 255.0]
+    }
+
+    pub fn canvas_pixels(&self) -> image::ImageBuffer<image::Rgb<u8>, Vec<u8>> {
+        let w = self.size.0;
+        ImageBuffer::from_fn(self.size.0, self.size.1, |x, y| {
+            let p = self.buffer[(y * w + x) as usize];
+            let c = |x: f32| (to_srgb(x.max(0.0).min(1.0)) * 255.0).round() as u8;
+            image::Rgb([c(p[0]), c(p[1]), c(p[2])])
+        })
+    }
+}
+
+/// Edge function, twice the signed area of triangle abc.
+#[inline(always)]
+fn edge(a: V2<f32>, b: V2<f32>, c: V2<f32>) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+#[cfg(test)]
+mod test {
+    use ::{V2, Rect};
+    use ::color;
+    use backend::{CanvasBuilder, CanvasUtil, Event};
+
+    #[test]
+    fn test_render() {
+        let mut ctx = CanvasBuilder::new().set_size(16, 16).build_software();
+        assert!(ctx.next_event() == Event::RenderFrame);
+        ctx.fill_rect(&Rect(V2(4.0, 4.0), V2(8.0, 8.0)), 0.5, color::WHITE);
+        // Further away, hidden by the first rect.
+        ctx.fill_rect(&Rect(V2(0.0, 0.0), V2(8.0, 8.0)), 0.6, color::RED);
+        // Finish the frame.
+        assert!(ctx.next_event() == Event::RenderFrame);
+
+        let img = ctx.screenshot();
+        assert_eq!(img.get_pixel(0, 0).data, [255, 0, 0]);
+        assert_eq!(img.get_pixel(5, 5).data, [255, 255, 255]);
+        assert_eq!(img.get_pixel(11, 11).data, [255, 255, 255]);
+        assert_eq!(img.get_pixel(12, 12).data, [0, 0, 0]);
+        assert_eq!(img.get_pixel(15, 0).data, [0, 0, 0]);
+    }
+}
@@ -24,6 +24,39 @@ impl HexGeom for V2<i32> {
     }
 }
 
+/// Return the cells on a straight line from a to b, including both ends.
+pub fn hex_line(a: V2<i32>, b: V2<i32>) -> Vec<V2<i32>> {
+    let n = (b - a).hex_dist();
+    if n == 0 { return vec![a]; }
+
+    // Interpolate in cube coordinates (x, -y, y - x), where every hex step
+    // changes two of the three coordinates by one.
+    let cube = |v: V2<i32>| [v.0 as f32, -v.1 as f32, (v.1 - v.0) as f32];
+    let (ca, cb) = (cube(a), cube(b));
+    // Nudge the line off the cell edges so that ties break the same way
+    // everywhere. The nudges sum to zero like the cube coordinates.
+    let nudge = [1e-4, 2e-4, -3e-4];
+
+    (0..(n + 1)).map(|i| {
+        let t = i as f32 / n as f32;
+        let mut c = [0.0f32; 3];
+        let mut r = [0.0f32; 3];
+        for j in 0..3 {
+            c[j] = ca[j] + (cb[j] - ca[j]) * t + nudge[j];
+            r[j] = c[j].round();
+        }
+        // Rounding can break the zero sum, recompute the coordinate that
+        // was rounded the most from the other two.
+        let d = [(r[0] - c[0]).abs(), (r[1] - c[1]).abs(), (r[2] - c[2]).abs()];
+        if d[0] > d[1] && d[0] > d[2] {
+            r[0] = -r[1] - r[2];
+        } else if d[1] > d[2] {
+            r[1] = -r[0] - r[2];
+        }
+        V2(r[0] as i32, -r[1] as i32)
+    }).collect()
+}
+
 /// Hex grid directions.
 #[derive(Copy, Eq, PartialEq, Clone, Debug, RustcEncodable, RustcDecodable)]
 pub enum Dir6 {
@@ -324,7 +357,7 @@ impl PolarPoint {
 mod test {
     use geom::V2;
     // XXX: Why doesn't super::* work here?
-    use super::{Dir6};
+    use super::{Dir6, HexGeom, hex_line};
     use super::Dir6::*;
 
     #[test]
@@ -360,4 +393,24 @@ mod test {
             assert_eq!(d, Dir6::from_v2(v * 3 + v2));
         }
     }
+
+    #[test]
+    fn test_hex_line() {
+        assert_eq!(hex_line(V2(2, 3), V2(2, 3)), vec![V2(2, 3)]);
+        assert_eq!(hex_line(V2(0, 0), V2(3, 3)),
+                   vec![V2(0, 0), V2(1, 1), V2(2, 2), V2(3, 3)]);
+
+        let ends = [V2(0, 0), V2(5, 1), V2(-3, 4), V2(2, -7), V2(-6, -2)];
+        for &a in ends.iter() {
+            for &b in ends.iter() {
+                let line = hex_line(a, b);
+                assert_eq!(line.len() as i32, (b - a).hex_dist() + 1);
+                assert_eq!(line[0], a);
+                assert_eq!(line[line.len() - 1], b);
+                for i in 1..line.len() {
+                    assert_eq!((line[i] - line[i - 1]).hex_dist(), 1);
+                }
+            }
+        }
+    }
 }
@@ -12,6 +12,8 @@ extern crate num;
 extern crate vec_map;
 extern crate nalgebra;
 extern crate image;
+extern crate gif;
+extern crate color_quant;
 extern crate glutin;
 
 #[macro_use] extern crate glium;
@@ -28,10 +30,10 @@ pub use geom::{V2, V3, Rect, RectIter, IterTiles};
 pub use img::{color_key};
 pub use atlas::{AtlasBuilder, Atlas, AtlasItem};
 pub use search::{LatticeNode, Dijkstra, astar_path_with};
-pub use hex::{HexGeom, Dir6, HexFov};
+pub use hex::{HexGeom, Dir6, HexFov, hex_line};
 pub use kernel::{Kernel, KernelTerrain};
 pub use projection::{Projection};
-pub use rng::{EncodeRng, RngExt};
+pub use rng::{XorShift128, RngExt};
 
 mod atlas;
 mod fs;
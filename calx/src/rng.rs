@@ -1,4 +1,3 @@
-use std::mem;
 use rand::{Rng, SeedableRng};
 use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
 use ::{to_log_odds};
@@ -40,57 +39,89 @@ impl<T: Rng> RngExt for T {
     }
 }
 
-/// A wrapper that makes a Rng implementation encodable.
+/// Xorshift random number generator with a portable, serializable state.
 ///
-/// For games that want to store the current Rng state as a part of the save
-/// game. Works by casting the Rng representation into a binary blob, will
-/// crash and burn if the Rng struct is not plain-old-data.
-pub struct EncodeRng<T> {
-    inner: T
+/// This is Marsaglia's 128-bit xorshift generator, the same algorithm as
+/// `rand::XorShiftRng`. Unlike the rand version, the algorithm is guaranteed
+/// to stay the same across versions, and the state is serialized as four
+/// u32 words in the order x, y, z, w, so saves and replays work identically
+/// on every platform.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct XorShift128 {
+    x: u32,
+    y: u32,
+    z: u32,
+    w: u32,
 }
 
-impl<T: Rng+'static> EncodeRng<T> {
-    pub fn new(inner: T) -> EncodeRng<T> {
-        EncodeRng { inner: inner }
+impl XorShift128 {
+    /// Create a generator from state words. The state must not be all
+    /// zeroes, that would make the generator return only zeroes.
+    pub fn new(x: u32, y: u32, z: u32, w: u32) -> XorShift128 {
+        assert!(x != 0 || y != 0 || z != 0 || w != 0, "XorShift128 state must not be all zero");
+        XorShift128 { x: x, y: y, z: z, w: w }
     }
+
+    /// Return the state words [x, y, z, w].
+    pub fn state(&self) -> [u32; 4] { [self.x, self.y, self.z, self.w] }
 }
 
-impl<T: SeedableRng<S>+Rng+'static, S> SeedableRng<S> for EncodeRng<T> {
-    fn reseed(&mut self, seed: S) {
-        self.inner.reseed(seed);
+impl SeedableRng<[u32; 4]> for XorShift128 {
+    fn reseed(&mut self, seed: [u32; 4]) {
+        *self = SeedableRng::from_seed(seed);
     }
 
-    fn from_seed(seed: S) -> EncodeRng<T> {
-        EncodeRng::new(SeedableRng::from_seed(seed))
+    fn from_seed(seed: [u32; 4]) -> XorShift128 {
+        XorShift128::new(seed[0], seed[1], seed[2], seed[3])
     }
 }
 
-impl<T: Rng> Rng for EncodeRng<T> {
-    fn next_u32(&mut self) -> u32 { self.inner.next_u32() }
+impl Rng for XorShift128 {
+    fn next_u32(&mut self) -> u32 {
+        let t = self.x ^ (self.x << 11);
+        self.x = self.y;
+        self.y = self.z;
+        self.z = self.w;
+        self.w = self.w ^ (self.w >> 19) ^ (t ^ (t >> 8));
+        self.w
+    }
 }
 
-impl<T: Rng+'static> Decodable for EncodeRng<T> {
-    fn decode<D: Decoder>(d: &mut D) -> Result<EncodeRng<T>, D::Error> {
-        let blob: Vec<u8> = try!(Decodable::decode(d));
-        unsafe {
-            if blob.len() == mem::size_of::<T>() {
-                Ok(EncodeRng::new(mem::transmute_copy(&blob[0])))
-            } else {
-                Err(d.error("Bad RNG blob length"))
-            }
+impl Decodable for XorShift128 {
+    fn decode<D: Decoder>(d: &mut D) -> Result<XorShift128, D::Error> {
+        let (x, y, z, w): (u32, u32, u32, u32) = try!(Decodable::decode(d));
+        if x == 0 && y == 0 && z == 0 && w == 0 {
+            Err(d.error("All-zero RNG state"))
+        } else {
+            Ok(XorShift128::new(x, y, z, w))
         }
     }
 }
 
-impl<T: 'static> Encodable for EncodeRng<T> {
+impl Encodable for XorShift128 {
     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
-        let mut vec = Vec::new();
-        unsafe {
-            let view = self as *const _ as *const u8;
-            for i in 0..(mem::size_of::<T>()) {
-                vec.push(*view.offset(i as isize));
-            }
-        }
-        vec.encode(s)
+        (self.x, self.y, self.z, self.w).encode(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, SeedableRng};
+    use rustc_serialize::json;
+    use super::XorShift128;
+
+    #[test]
+    fn test_xorshift() {
+        let mut rng: XorShift128 = SeedableRng::from_seed([1, 2, 3, 4]);
+        // Reference values for the algorithm, must never change.
+        assert_eq!(rng.next_u32(), 2061);
+        assert_eq!(rng.next_u32(), 6175);
+
+        let saved = json::encode(&rng).unwrap();
+        let mut restored: XorShift128 = json::decode(&saved).unwrap();
+        assert_eq!(restored, rng);
+        assert_eq!(restored.next_u32(), rng.next_u32());
+
+        assert!(json::decode::<XorShift128>("[0, 0, 0, 0]").is_err());
     }
 }
@@ -70,6 +70,18 @@ impl<N: LatticeNode> Dijkstra<N> {
         ret.sort_by(|&(w1, _), &(w2, _)| w1.cmp(w2));
         ret.into_iter().map(|(_, n)| n).collect()
     }
+
+    /// Return the distance of a node from the nearest goal, or None if the
+    /// node isn't covered by the map.
+    pub fn weight(&self, node: &N) -> Option<u32> {
+        self.weights.get(node).map(|&w| w)
+    }
+
+    /// Return the distances from the nearest goal for all the nodes covered
+    /// by the map.
+    pub fn weights(&self) -> &HashMap<N, u32> {
+        &self.weights
+    }
 }
 
 pub fn astar_path_with<N: LatticeNode, F, T>(metric: F, from: N, to: N, mut limit: u32) -> Option<Vec<N>>
@@ -155,4 +167,16 @@ mod test {
         assert!(path[0] == V2(1, 1));
         assert!(path[path.len() - 1] == V2(10, 10));
     }
+
+    #[test]
+    fn test_dijkstra_weights() {
+        use super::Dijkstra;
+        use geom::{V2};
+
+        let map = Dijkstra::new(vec![V2(0, 0)], |p: &V2<i32>| p.0.abs() < 5 && p.1.abs() < 5, 3);
+        assert_eq!(map.weight(&V2(0, 0)), Some(0));
+        assert_eq!(map.weight(&V2(1, 1)), Some(2));
+        assert_eq!(map.weight(&V2(3, 0)), None);
+        assert!(map.weights().values().all(|&w| w < 3));
+    }
 }
@@ -159,3 +159,92 @@ fn test_uid_invalidation() {
     assert!(ecs.pos().get(e1).is_none());
     assert!(ecs.mu().pos().get(e1).is_none());
 }
+
+#[test]
+fn test_iter() {
+    let mut ecs = Ecs::new();
+    let p = ecs.make_prototype(None);
+    let e1 = ecs.make(Some(p));
+    let e2 = ecs.make(None);
+    let e3 = ecs.make(None);
+    ecs.remove(e2);
+
+    assert_eq!(ecs.iter().collect::<Vec<_>>(), vec![e1, e3]);
+}
+
+#[test]
+fn test_query() {
+    let mut ecs = Ecs::new();
+    let p1 = Build::prototype(&mut ecs, None)
+        .c(Desc { name: "Orc".to_string(), icon: 5 })
+        .e();
+    let p2 = ecs.make_prototype(Some(p1));
+    let e1 = ecs.make(Some(p1));
+    let e2 = ecs.make(Some(p2));
+    let e3 = ecs.make(Some(p2));
+    let e4 = ecs.make(None);
+    ecs.mu().desc().hide(e3);
+    ecs.mu().pos().insert(e4, Pos { x: 1, y: 2 });
+
+    assert_eq!(ecs.desc().entities_local(), vec![p1]);
+    assert_eq!(ecs.desc().entities(), vec![p1, p2, e1, e2]);
+    assert!(ecs.desc().has(e2));
+    assert!(!ecs.desc().has(e3));
+    assert_eq!(ecs.pos().entities(), vec![e4]);
+
+    // The child index must follow later changes to the parents.
+    let e5 = ecs.make(Some(p2));
+    ecs.remove(e1);
+    ecs.set_parent(e4, Some(p1));
+    assert_eq!(ecs.desc().entities(), vec![p1, p2, e2, e4, e5]);
+}
+
+#[test]
+fn test_mask() {
+    use calx_ecs::{Component, CompMask};
+
+    let mut ecs = Ecs::new();
+    let p = Build::prototype(&mut ecs, None)
+        .c(Desc { name: "Orc".to_string(), icon: 5 })
+        .e();
+    let e = Build::entity(&mut ecs, Some(p))
+        .c(Desc { name: "Blork".to_string(), icon: 6 })
+        .c(Pos { x: 1, y: 2 })
+        .e();
+
+    let all = CompMask::new().with(Desc::id()).with(Pos::id());
+    assert_eq!(ecs.mask(e), all);
+    assert_eq!(ecs.mask(p), CompMask::new().with(Desc::id()));
+
+    ecs.clear_components(e, all);
+    assert_eq!(ecs.desc().get(e).map(|x| x.icon), Some(5));
+    assert_eq!(ecs.pos().get(e), None);
+
+    ecs.hide_components(e, CompMask::new().with(Desc::id()));
+    assert!(ecs.mask(e).is_empty());
+}
+
+#[test]
+fn test_clone() {
+    let mut ecs = Ecs::new();
+    let p = Build::prototype(&mut ecs, None)
+        .c(Desc { name: "Orc".to_string(), icon: 5 })
+        .e();
+    let e1 = Build::entity(&mut ecs, Some(p))
+        .c(Pos { x: 1, y: 2 })
+        .e();
+
+    let e2 = ecs.clone_entity(e1);
+    assert!(!e2.is_prototype());
+    assert_eq!(ecs.get_parent(e2), Some(p));
+    assert_eq!(ecs.pos().get_local(e2), Some(&Pos { x: 1, y: 2 }));
+    assert_eq!(ecs.desc().get_local(e2), None);
+
+    // The clone is independent of the original.
+    ecs.mu().pos().get(e2).map(|x| x.x = 10);
+    assert_eq!(ecs.pos().get(e1), Some(&Pos { x: 1, y: 2 }));
+
+    let p2 = ecs.clone_entity(p);
+    assert!(p2.is_prototype());
+    assert_eq!(ecs.desc().get(p2).map(|x| x.icon), Some(5));
+}
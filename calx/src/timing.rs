@@ -1,23 +1,37 @@
 use time;
 use std::thread;
+use std::cell::Cell;
+
+thread_local!(static FROZEN_TIME: Cell<Option<f64>> = Cell::new(None));
+
+/// Make the animation functions see a fixed time instead of the clock, or
+/// the clock again with None. Gives repeatable frames for tests.
+pub fn freeze(t: Option<f64>) {
+    FROZEN_TIME.with(|c| c.set(t));
+}
+
+/// Time in seconds for the animation functions.
+fn anim_time() -> f64 {
+    FROZEN_TIME.with(|c| c.get()).unwrap_or_else(time::precise_time_s)
+}
 
 pub fn cycle_anim<'a, T>(period_s: f64, frames: &'a [T]) -> &'a T {
     assert!(period_s > 0.0);
     assert!(frames.len() > 0);
-    let idx = (time::precise_time_s() / period_s) as usize % frames.len();
+    let idx = (anim_time() / period_s) as usize % frames.len();
 
     &frames[idx]
 }
 
 /// Time-plot that spikes at given intervals for the given time.
 pub fn spike(down_s: f64, up_s: f64) -> bool {
-    time::precise_time_s() % (down_s + up_s) > down_s
+    anim_time() % (down_s + up_s) > down_s
 }
 
 pub fn single_anim<'a, T>(start_s: f64, period_s: f64, frames: &'a [T]) -> &'a T {
     assert!(period_s > 0.0);
     assert!(frames.len() > 0);
-    let mut idx = ((time::precise_time_s() - start_s) / period_s) as i32;
+    let mut idx = ((anim_time() - start_s) / period_s) as i32;
     if idx < 0 { idx = 0; }
     if idx >= frames.len() as i32 { idx = frames.len() as i32 - 1; }
 
@@ -1,6 +1,7 @@
 /*!
   Encode binary data into human-readable random words.
  */
+use std::fmt;
 use self::Decode::{V, C};
 
 static VS: [char; 5] = ['a', 'e', 'i', 'o', 'u'];
@@ -79,14 +80,16 @@ fn c(c: char) -> Result<u16, ()> {
 fn durov_chunk(s: &str) -> Result<u16, ()> {
     if s.len() != 5 { return Err(()); }
     let s: Vec<char> = s.chars().collect();
-    let (mut ret, mut n) = (0u16, 1u16);
-    ret += try!(c(s[4])) * n; n *= CS.len() as u16;
-    ret += try!(v(s[3])) * n; n *= VS.len() as u16;
-    ret += try!(c(s[2])) * n; n *= CS.len() as u16;
-    ret += try!(v(s[1])) * n; n *= VS.len() as u16;
-    ret += try!(c(s[0])) * n;
-
-    Ok(ret)
+    // The chunk space is a bit larger than u16, calculate in u32.
+    let (mut ret, mut n) = (0u32, 1u32);
+    ret += try!(c(s[4])) as u32 * n; n *= CS.len() as u32;
+    ret += try!(v(s[3])) as u32 * n; n *= VS.len() as u32;
+    ret += try!(c(s[2])) as u32 * n; n *= CS.len() as u32;
+    ret += try!(v(s[1])) as u32 * n; n *= VS.len() as u32;
+    ret += try!(c(s[0])) as u32 * n;
+
+    if ret > 0xffff { return Err(()); }
+    Ok(ret as u16)
 }
 
 /// A vorud string.
@@ -99,7 +102,7 @@ impl Vorud {
         let ch = &s[..].chars().collect::<Vec<char>>();
         let mut i = 0;
         loop {
-            if i > ch.len() - 5 { return Err(()); }
+            if i + 5 > ch.len() { return Err(()); }
             try!(c(ch[i]));
             try!(v(ch[i + 1]));
             try!(c(ch[i + 2]));
@@ -112,6 +115,12 @@ impl Vorud {
     }
 }
 
+impl fmt::Display for Vorud {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 /// Convert vorud into data.
 pub trait FromVorud<E>: Sized {
     fn from_vorud(v: &Vorud) -> Result<Self, E>;
@@ -140,8 +149,8 @@ impl FromVorud<()> for Vec<u8> {
         let mut ret = Vec::new();
         for chunk in (&s[..]).split('-') {
             let x = try!(durov_chunk(chunk));
-            ret.push((x / 0xff) as u8);
-            ret.push((x % 0xff) as u8);
+            ret.push((x >> 8) as u8);
+            ret.push((x & 0xff) as u8);
         }
         Ok(ret)
     }
@@ -150,10 +159,10 @@ impl FromVorud<()> for Vec<u8> {
 impl ToVorud for u32 {
     fn to_vorud(&self) -> Vorud {
         let mut vec = Vec::new();
-        vec.push(((*self >> 24) % 0xff) as u8);
-        vec.push(((*self >> 16) % 0xff) as u8);
-        vec.push(((*self >> 8) % 0xff) as u8);
-        vec.push((*self % 0xff) as u8);
+        vec.push((*self >> 24) as u8);
+        vec.push((*self >> 16) as u8);
+        vec.push((*self >> 8) as u8);
+        vec.push(*self as u8);
         (&vec[..]).to_vorud()
     }
 }
@@ -183,5 +192,10 @@ mod test {
         assert_eq!(super::Vorud("babab-babab".to_string()), 0u32.to_vorud());
         assert_eq!(super::Vorud("babab-babad".to_string()), 1u32.to_vorud());
         assert_eq!(Ok(1u32), super::FromVorud::from_vorud(&super::Vorud("babab-babad".to_string())));
+        for &x in [256u32, 0xdeadbeef, 0xffffffff].iter() {
+            assert_eq!(Ok(x), super::FromVorud::from_vorud(&x.to_vorud()));
+        }
+        assert!(super::Vorud::new("bab".to_string()).is_err());
+        assert!(super::durov_chunk("zuzuz").is_err());
     }
 }
@@ -0,0 +1,23 @@
+/*! Convert Phage save files between the JSON and binary formats. */
+
+extern crate world;
+
+use std::env;
+use std::path::Path;
+use std::process;
+use world::action;
+
+pub fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        println!("Usage: {} [input] [output]", args[0]);
+        println!("File formats are chosen by extension: .json, .jsonz (compressed JSON),");
+        println!(".sav (binary) or .savz (compressed binary)");
+        process::exit(1);
+    }
+
+    if let Err(e) = action::convert_save(Path::new(&args[1]), Path::new(&args[2])) {
+        println!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,512 @@
+/*! Play Phage in a text terminal, eg. over SSH.
+
+Needs a terminal with ANSI escape codes and 24-bit color and a Unix `stty`
+command.
+*/
+
+extern crate calx;
+extern crate world;
+
+use std::io::{self, Read, Write};
+use std::process::{self, Command, Stdio};
+use calx::{V2, SRgba, scolor, Dir6};
+use calx::Dir6::*;
+use world::{Location, Entity, FovStatus, Msg};
+use world::action;
+use world::action::Input;
+use world::action::ControlState::*;
+use world::item::Slot;
+
+// The version lives in the main game crate, which can't be linked to as a
+// library.
+#[path = "../version.rs"]
+mod version;
+
+/// Number of message lines shown below the map.
+static MESSAGE_LINES: usize = 3;
+
+/// Maximum number of steps taken with one autoexplore command.
+static MAX_EXPLORE_STEPS: usize = 100;
+
+/// Inventory slots with the keys that select them in the inventory view.
+static SLOTS: [(char, Slot, &'static str); 34] = [
+    ('1', Slot::Spell1, "Ability"),
+    ('2', Slot::Spell2, "Ability"),
+    ('3', Slot::Spell3, "Ability"),
+    ('4', Slot::Spell4, "Ability"),
+    ('5', Slot::Spell5, "Ability"),
+    ('6', Slot::Spell6, "Ability"),
+    ('7', Slot::Spell7, "Ability"),
+    ('8', Slot::Spell8, "Ability"),
+    ('a', Slot::Melee, "Weapon"),
+    ('b', Slot::Ranged, "Ranged"),
+    ('c', Slot::Head, "Head"),
+    ('d', Slot::Body, "Body"),
+    ('e', Slot::Feet, "Feet"),
+    ('f', Slot::TrinketF, "Trinket"),
+    ('g', Slot::TrinketG, "Trinket"),
+    ('h', Slot::TrinketH, "Trinket"),
+    ('i', Slot::TrinketI, "Trinket"),
+    ('j', Slot::InventoryJ, ""),
+    ('k', Slot::InventoryK, ""),
+    ('l', Slot::InventoryL, ""),
+    ('m', Slot::InventoryM, ""),
+    ('n', Slot::InventoryN, ""),
+    ('o', Slot::InventoryO, ""),
+    ('p', Slot::InventoryP, ""),
+    ('q', Slot::InventoryQ, ""),
+    ('r', Slot::InventoryR, ""),
+    ('s', Slot::InventoryS, ""),
+    ('t', Slot::InventoryT, ""),
+    ('u', Slot::InventoryU, ""),
+    ('v', Slot::InventoryV, ""),
+    ('w', Slot::InventoryW, ""),
+    ('x', Slot::InventoryX, ""),
+    ('y', Slot::InventoryY, ""),
+    ('z', Slot::InventoryZ, ""),
+];
+
+/// Puts the terminal in raw mode and restores the previous mode when
+/// dropped.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn new() -> Result<RawMode, String> {
+        let saved = try!(stty(&["-g"]));
+        try!(stty(&["raw", "-echo"]));
+        Ok(RawMode { saved: saved.trim().to_string() })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved[..]]);
+    }
+}
+
+/// Run stty on the terminal of the standard input.
+fn stty(args: &[&str]) -> Result<String, String> {
+    let output = try!(Command::new("stty").args(args)
+                      .stdin(Stdio::inherit())
+                      .output()
+                      .map_err(|e| format!("Running stty failed: {}", e)));
+    if !output.status.success() {
+        return Err(format!("stty failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Return the terminal size in columns and rows.
+fn terminal_size() -> V2<usize> {
+    if let Ok(s) = stty(&["size"]) {
+        let parts: Vec<usize> = s.split_whitespace().filter_map(|x| x.parse().ok()).collect();
+        if parts.len() == 2 && parts[0] > 0 && parts[1] > 0 {
+            return V2(parts[1], parts[0]);
+        }
+    }
+    V2(80, 24)
+}
+
+/// Map a character cell offset from the center of the view to a location
+/// offset.
+///
+/// Hex columns go along the x - y axis and rows along the x + y axis. Every
+/// other column is shifted half a row down so that north and south are
+/// straight up and down and the diagonal directions go to the neighboring
+/// columns.
+fn cell_to_offset(col: i32, row: i32) -> V2<i32> {
+    let parity = ((col % 2) + 2) % 2;
+    let sum = 2 * row + parity;
+    V2((sum + col) / 2, (sum - col) / 2)
+}
+
+/// Glyph and color for a terrain type.
+fn terrain_glyph(loc: Location) -> (char, SRgba) {
+    match loc.terrain().name() {
+        "floor" => ('.', scolor::SLATEGRAY),
+        "chasm" => (' ', scolor::BLACK),
+        "water" => ('~', scolor::ROYALBLUE),
+        "shallows" => ('~', scolor::STEELBLUE),
+        "magma" => ('~', scolor::ORANGERED),
+        "wall" => ('#', scolor::LIGHTGRAY),
+        "rock" => ('#', scolor::DARKGOLDENROD),
+        "tree" => ('&', scolor::FORESTGREEN),
+        "dead tree" => ('&', scolor::SADDLEBROWN),
+        "grass" => (',', scolor::DARKGREEN),
+        "tall grass" => ('"', scolor::GREEN),
+        "stalagmite" => ('^', scolor::DARKGOLDENROD),
+        "door" => ('+', scolor::SADDLEBROWN),
+        "open door" => ('\'', scolor::SADDLEBROWN),
+        "window" => ('=', scolor::LIGHTSKYBLUE),
+        "table" => ('T', scolor::SADDLEBROWN),
+        "barrel" => ('0', scolor::SADDLEBROWN),
+        "stone" => ('*', scolor::GRAY),
+        "crater" => ('.', scolor::DIMGRAY),
+        "pod" => ('%', scolor::DARKCYAN),
+        "lamp" => ('i', scolor::LIGHTYELLOW),
+        // Void and anything new.
+        _ => (' ', scolor::BLACK),
+    }
+}
+
+/// Glyph and color for an entity.
+fn entity_glyph(e: Entity) -> Option<(char, SRgba)> {
+    let (_, color) = match e.get_icon() {
+        Some(x) => x,
+        None => return None,
+    };
+    let glyph = if e.is_player() { '@' } else {
+        e.glyph().unwrap_or_else(|| e.name().chars().next().unwrap_or('?'))
+    };
+    Some((glyph, SRgba::from(color)))
+}
+
+fn is_game_over() -> bool {
+    match action::player() {
+        Some(p) => p.is_corpse(),
+        None => true,
+    }
+}
+
+struct Term {
+    messages: Vec<String>,
+}
+
+impl Term {
+    fn new() -> Term {
+        Term { messages: Vec::new() }
+    }
+
+    fn msg(&mut self, text: String) {
+        for line in text.lines() {
+            if !line.trim().is_empty() { self.messages.push(line.to_string()); }
+        }
+        let n = self.messages.len();
+        if n > MESSAGE_LINES { self.messages.drain(..n - MESSAGE_LINES); }
+    }
+
+    /// Read the text messages from the world, the effect messages are only
+    /// for the graphical frontend.
+    fn pop_msgs(&mut self) {
+        loop {
+            match world::pop_msg() {
+                Some(Msg::Text(txt)) => self.msg(txt),
+                Some(Msg::Caption(txt)) => self.msg(txt.to_uppercase()),
+                Some(_) => {}
+                None => break,
+            }
+        }
+    }
+
+    fn draw(&self) {
+        let size = terminal_size();
+        let map_rows = map_rows(size);
+        let center = V2((size.0 / 2) as i32, (map_rows / 2) as i32);
+        let origin = match action::player().and_then(|p| p.location()) {
+            Some(loc) => loc,
+            None => world::camera(),
+        };
+
+        let mut out = String::new();
+        // Home the cursor, redraw everything over the old frame.
+        out.push_str("\x1b[H");
+        for row in 0..map_rows {
+            let mut current_color = None;
+            for col in 0..size.0 {
+                let offset = cell_to_offset(col as i32 - center.0, row as i32 - center.1);
+                let loc = origin + offset;
+                let (glyph, color) = match loc.fov_status() {
+                    None => (' ', scolor::BLACK),
+                    Some(FovStatus::Remembered) => (terrain_glyph(loc).0, scolor::DIMGRAY),
+                    Some(FovStatus::Seen) => {
+                        let e = loc.mob_at().or_else(|| loc.entities().into_iter().next());
+                        match e.and_then(entity_glyph) {
+                            Some(x) => x,
+                            None => terrain_glyph(loc),
+                        }
+                    }
+                };
+                let rgb = (color.r, color.g, color.b);
+                if current_color != Some(rgb) {
+                    out.push_str(&format!("\x1b[38;2;{};{};{}m", rgb.0, rgb.1, rgb.2));
+                    current_color = Some(rgb);
+                }
+                out.push(glyph);
+            }
+            out.push_str("\r\n");
+        }
+
+        out.push_str("\x1b[0m");
+        let status = match action::player() {
+            Some(p) if !p.is_corpse() => format!("HP {}/{}  {}", p.hp(), p.max_hp(), origin.name()),
+            _ => "Phage destroyed".to_string(),
+        };
+        self.finish_frame(out, &status, size);
+    }
+
+    /// Draw the inventory slots of the player in two columns.
+    fn draw_inventory(&self) {
+        let size = terminal_size();
+        let player = action::player().unwrap();
+        let rows = (SLOTS.len() + 1) / 2;
+        let width = size.0 / 2;
+
+        let mut out = String::new();
+        out.push_str("\x1b[H\x1b[0m");
+        for row in 0..map_rows(size) {
+            let mut text = String::new();
+            for &i in [row, row + rows].iter() {
+                let cell = match SLOTS.get(i) {
+                    Some(&(key, slot, name)) if row < rows => {
+                        let item = player.equipped(slot).map(|e| e.name()).unwrap_or(String::new());
+                        if name.is_empty() {
+                            format!("{}] {}", key, item)
+                        } else {
+                            format!("{}] {}: {}", key, name, item)
+                        }
+                    }
+                    _ => String::new(),
+                };
+                text.push_str(&line(&cell, width));
+            }
+            out.push_str(&line(&text, size.0));
+            out.push_str("\r\n");
+        }
+        self.finish_frame(out, "Letter to equip/unequip, shift+letter to drop, ESC to close", size);
+    }
+
+    /// Add the status line and the messages to the frame and write it out.
+    fn finish_frame(&self, mut out: String, status: &str, size: V2<usize>) {
+        out.push_str(&line(status, size.0));
+        for i in 0..MESSAGE_LINES {
+            out.push_str("\r\n");
+            out.push_str(&line(self.messages.get(i).map(|x| &x[..]).unwrap_or(""), size.0));
+        }
+
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let _ = stdout.write_all(out.as_bytes());
+        let _ = stdout.flush();
+    }
+
+    /// Show the inventory until it's closed.
+    fn inventory(&mut self) {
+        loop {
+            self.pop_msgs();
+            self.draw_inventory();
+            let ch = match read_bytes() {
+                None => return,
+                Some(ref b) if b.len() == 1 && b[0] == 27 => return,
+                Some(ref b) if b.len() == 1 => b[0] as char,
+                Some(_) => continue,
+            };
+            if let Some(e) = slot_command(ch) { self.msg(e); }
+        }
+    }
+}
+
+/// Number of terminal rows for the map or the inventory above the status
+/// line and the messages.
+fn map_rows(size: V2<usize>) -> usize {
+    if size.1 > MESSAGE_LINES + 1 { size.1 - MESSAGE_LINES - 1 } else { 1 }
+}
+
+/// Equip or unequip the item in the slot of a lowercase key, or drop the
+/// item in the slot of an uppercase key. Returns a message if the command
+/// couldn't be done.
+fn slot_command(ch: char) -> Option<String> {
+    let player = action::player().unwrap();
+    let lower = ch.to_lowercase().next().unwrap();
+    let slot = match SLOTS.iter().find(|x| x.0 == lower) {
+        Some(&(_, slot, _)) => slot,
+        None => return None,
+    };
+    let item = match player.equipped(slot) {
+        Some(x) => x,
+        None => return None,
+    };
+
+    if ch != lower {
+        action::input(Input::DropItem(slot));
+    } else if slot.is_gear_slot() {
+        match player.free_bag_slot() {
+            Some(bag) => action::input(Input::SwapItems(slot, bag)),
+            None => return Some("No room in the bag".to_string()),
+        }
+    } else {
+        match item.equip_slots().into_iter().find(|&x| player.equipped(x).is_none()) {
+            Some(gear) => action::input(Input::SwapItems(slot, gear)),
+            None => return Some(format!("Can't equip {}", item.name())),
+        }
+    }
+    None
+}
+
+/// Pad or cut text to exactly the terminal width.
+fn line(text: &str, width: usize) -> String {
+    let mut ret: String = text.chars().take(width).collect();
+    let len = ret.chars().count();
+    for _ in len..width { ret.push(' '); }
+    ret
+}
+
+/// Keyboard commands.
+enum Key {
+    Move(Dir6),
+    Pass,
+    Explore,
+    Inventory,
+    Quit,
+    Other,
+}
+
+/// Read the bytes of one keypress. Returns None if the input is closed.
+fn read_bytes() -> Option<Vec<u8>> {
+    let mut buf = [0u8; 8];
+    match io::stdin().read(&mut buf) {
+        Ok(0) | Err(_) => None,
+        Ok(n) => Some(buf[..n].to_vec()),
+    }
+}
+
+fn read_key() -> Key {
+    let buf = match read_bytes() {
+        Some(x) => x,
+        None => return Key::Quit,
+    };
+    let byte = |i: usize| buf.get(i).map(|&x| x).unwrap_or(0);
+    match (buf.len(), byte(0), byte(1), byte(2)) {
+        (1, 27, _, _) => Key::Quit,
+        (3, 27, b'[', b'A') => Key::Move(North),
+        (3, 27, b'[', b'B') => Key::Move(South),
+        (1, b'q', _, _) => Key::Move(NorthWest),
+        (1, b'w', _, _) => Key::Move(North),
+        (1, b'e', _, _) => Key::Move(NorthEast),
+        (1, b'a', _, _) => Key::Move(SouthWest),
+        (1, b's', _, _) => Key::Move(South),
+        (1, b'd', _, _) => Key::Move(SouthEast),
+        (1, b' ', _, _) | (1, b'.', _, _) => Key::Pass,
+        (1, b'x', _, _) => Key::Explore,
+        (1, b'i', _, _) => Key::Inventory,
+        (1, b'Q', _, _) => Key::Quit,
+        _ => Key::Other,
+    }
+}
+
+/// Run the world until the player can act. Returns false if the game is
+/// over.
+fn run_world() -> bool {
+    loop {
+        if is_game_over() { return false; }
+        match action::control_state() {
+            AwaitingInput => return true,
+            ReadyToUpdate => action::update(),
+        }
+    }
+}
+
+/// Take one autoexplore step. Returns false if exploring should stop.
+fn explore_step() -> bool {
+    let player = action::player().unwrap();
+    if !player.is_threatened(6).is_empty() { return false; }
+    if let Some(pathing) = action::autoexplore_map(32) {
+        let loc = player.location().unwrap();
+        let steps = pathing.sorted_neighbors(&loc);
+        if steps.len() > 0 {
+            action::input(Input::Step(loc.dir6_towards(steps[0]).unwrap()));
+            return true;
+        }
+    }
+    false
+}
+
+pub fn main() {
+    let version = version::version();
+
+    world::init_world(None);
+    if let Err(e) = action::load_game() {
+        println!("{}", e);
+        process::exit(1);
+    }
+
+    let raw = match RawMode::new() {
+        Ok(x) => x,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    };
+    // Clear screen and hide cursor.
+    print!("\x1b[2J\x1b[?25l");
+
+    let mut term = Term::new();
+    term.msg("Move with q,w,e, a,s,d, wait with space, x to explore, i for inventory".to_string());
+    term.msg("ESC to save and quit".to_string());
+
+    let mut error = None;
+    while run_world() {
+        term.pop_msgs();
+        term.draw();
+
+        match read_key() {
+            Key::Move(dir) => action::smart_move(dir),
+            Key::Pass => action::input(Input::Pass),
+            Key::Explore => {
+                for _ in 0..MAX_EXPLORE_STEPS {
+                    if !explore_step() || !run_world() { break; }
+                }
+            }
+            Key::Inventory => term.inventory(),
+            Key::Quit => {
+                if let Err(e) = action::save_game(&version) {
+                    error = Some(format!("Save failed: {}", e));
+                }
+                break;
+            }
+            Key::Other => {}
+        }
+    }
+
+    if is_game_over() {
+        term.pop_msgs();
+        term.msg("Game over, press any key".to_string());
+        term.draw();
+        read_key();
+    }
+
+    print!("\x1b[0m\x1b[?25h\x1b[2J\x1b[H");
+    let _ = io::stdout().flush();
+    drop(raw);
+
+    if let Some(e) = error {
+        println!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use calx::{V2, HexGeom, Dir6};
+    use super::cell_to_offset;
+
+    #[test]
+    fn test_cell_to_offset() {
+        assert_eq!(cell_to_offset(0, 0), V2(0, 0));
+        assert_eq!(cell_to_offset(0, -1), Dir6::North.to_v2());
+        assert_eq!(cell_to_offset(0, 1), Dir6::South.to_v2());
+
+        // Every offset on a ring comes from the cell at its column and row.
+        for r in 1..6 {
+            for v in (-r..r + 1).flat_map(|y| (-r..r + 1).map(move |x| V2(x, y)))
+                                 .filter(|v| v.hex_dist() == r) {
+                let col = v.0 - v.1;
+                let parity = ((col % 2) + 2) % 2;
+                let row = (v.0 + v.1 - parity) / 2;
+                assert_eq!(cell_to_offset(col, row), v);
+                assert!(col.abs() <= 2 * r && row.abs() <= r);
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+/*! Command line options. */
+
+use std::path::PathBuf;
+use calx::V2;
+use calx::backend::CanvasMagnify;
+use settings;
+use seed;
+
+pub static USAGE: &'static str = "\
+Usage: phage [options]
+
+Options:
+  --seed <seed>      Start a new game with a numeric or word code seed
+  --load <file>      Start from a saved game file, implies --no-save
+  --replay <file>    Play back the inputs recorded in a replay file, implies
+                     --no-save
+  --no-save          Never write or remove the saved game
+  --window <WxH>     Window size in pixels, eg. 1280x720
+  --fullscreen       Run in fullscreen mode
+  --magnify <mode>   Screen scaling, pixelperfect, nearest or smooth
+  --version          Show the version and exit
+  --help             Show this message and exit
+
+The window options override the settings file without changing it.";
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Options {
+    pub seed: Option<u32>,
+    pub load: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub no_save: bool,
+    pub window_size: Option<V2<u32>>,
+    pub fullscreen: bool,
+    pub magnify: Option<CanvasMagnify>,
+    pub version: bool,
+    pub help: bool,
+}
+
+impl Options {
+    /// Parse the options from the command line arguments, not including the
+    /// program name.
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut ret: Options = Default::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || match args.next() {
+                Some(x) => Ok(&x[..]),
+                None => Err(format!("Missing value for {}", arg)),
+            };
+
+            match &arg[..] {
+                "--seed" => { ret.seed = Some(try!(seed::parse_seed(try!(value())))); }
+                "--load" => { ret.load = Some(PathBuf::from(try!(value()))); }
+                "--replay" => { ret.replay = Some(PathBuf::from(try!(value()))); }
+                "--no-save" => { ret.no_save = true; }
+                "--window" => { ret.window_size = Some(try!(settings::parse_size(try!(value())))); }
+                "--fullscreen" => { ret.fullscreen = true; }
+                "--magnify" => { ret.magnify = Some(try!(settings::parse_magnify(try!(value())))); }
+                "--version" => { ret.version = true; }
+                "--help" | "-h" => { ret.help = true; }
+                _ => return Err(format!("Unknown option '{}'", arg)),
+            }
+        }
+
+        let starts = ret.seed.is_some() as u32 + ret.load.is_some() as u32 +
+            ret.replay.is_some() as u32;
+        if starts > 1 {
+            return Err("Use only one of --seed, --load and --replay".to_string());
+        }
+        // Games started from a file mustn't replace the player's own save.
+        if ret.load.is_some() || ret.replay.is_some() {
+            ret.no_save = true;
+        }
+        Ok(ret)
+    }
+
+    /// Return whether the options skip the title screen.
+    pub fn starts_game(&self) -> bool {
+        self.seed.is_some() || self.load.is_some() || self.replay.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use calx::V2;
+    use calx::backend::CanvasMagnify;
+    use super::Options;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(|x| x.to_string()).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(""), Ok(Default::default()));
+
+        let options = parse("--seed 42 --no-save --window 1280x720 --magnify smooth").unwrap();
+        assert_eq!(options.seed, Some(42));
+        assert!(options.no_save);
+        assert_eq!(options.window_size, Some(V2(1280, 720)));
+        assert_eq!(options.magnify, Some(CanvasMagnify::Smooth));
+        assert!(options.starts_game());
+
+        assert_eq!(parse("--load game.json").unwrap().load, Some(PathBuf::from("game.json")));
+        assert!(parse("--load game.json").unwrap().no_save);
+        assert!(parse("--replay bug.txt").unwrap().no_save);
+        assert!(!parse("--seed 42").unwrap().no_save);
+        assert!(parse("--version").unwrap().version);
+
+        assert!(parse("--seed").is_err());
+        assert!(parse("--seed xyzzy").is_err());
+        assert!(parse("--window 640").is_err());
+        assert!(parse("--seed 1 --load game.json").is_err());
+        assert!(parse("--frobnicate").is_err());
+    }
+}
@@ -1,17 +1,44 @@
-use std::fmt::Write;
-use calx::{timing, color, Anchor, V2};
+use calx::{self, timing, color, Anchor, V2, Dir6};
 use calx::backend::{Canvas, Event, Key, Fonter};
+use world::action;
+use world::{Entity, Location};
+use seed;
+
+/// Command names for tab completion.
+static COMMANDS: [&'static str; 12] = [
+    "depth", "godmode", "heal", "help", "kill", "load", "replay", "reveal",
+    "save", "seed", "spawn", "teleport",
+];
+
+static HELP: &'static str = "\
+spawn <prototype> [dir]  Spawn next to player, dir is n, ne, se, s, sw or nw
+teleport <x> <y>         Move player
+reveal                   Reveal the current map
+godmode                  Toggle player invulnerability
+depth [n]                Show or change the current depth
+heal                     Heal player fully
+kill all                 Kill every mob except player
+seed                     Show the game seed
+save <file>              Save game, relative paths go in the data directory
+load <file>              Load game
+replay <file>            Save the inputs since game start for --replay";
 
 pub struct Console {
     text: String,
     command: String,
+    /// Entered commands, oldest first.
+    history: Vec<String>,
+    /// Position in history when browsing it with the arrow keys.
+    history_pos: Option<usize>,
 }
 
 impl Console {
     pub fn new() -> Console {
         Console {
-            text: "Welcome to console\n".to_string(),
+            text: "Welcome to console, type 'help' for commands\n".to_string(),
             command: String::new(),
+            history: Vec::new(),
+            history_pos: None,
         }
     }
 
@@ -35,20 +62,13 @@ impl Console {
             Event::Char('`') => { return false; }
             Event::KeyPressed(Key::Escape) => { return false; }
 
-            Event::KeyPressed(Key::Backspace) => {
-                let len = self.command.len();
-                if len > 0 {
-                    // XXX: This is very bad juju if the string isn't entirely
-                    // ASCII7.
-                    self.command.truncate(len - 1);
-                }
-            }
+            Event::KeyPressed(Key::Backspace) => { self.command.pop(); }
             Event::KeyPressed(Key::Enter) => { self.process_command(); }
             Event::KeyPressed(Key::Tab) => { self.tab_complete(); }
             Event::KeyPressed(Key::Up) => { self.history_prev(); }
             Event::KeyPressed(Key::Down) => { self.history_next(); }
 
-            Event::Char(ch) if (ch as i32) >= 32 && (ch as i32) < 128 => {
+            Event::Char(ch) if !ch.is_control() => {
                 self.command.push(ch);
             }
 
@@ -59,27 +79,308 @@ impl Console {
         return true;
     }
 
+    /// Add a line of output to the console.
+    fn print(&mut self, line: &str) {
+        self.text.push_str(line);
+        self.text.push('\n');
+    }
+
     fn process_command(&mut self) {
-        // TODO: Why can't I use write! macros here?
-        //writeln!(self.text, "]{}", self.command).unwrap();
-        //writeln!(self.text, "TODO: Handle input");
-        self.text.write_str(&format!("]{}\n", self.command)[..]).unwrap();
-        self.text.write_str("TODO: Handle input\n").unwrap();
-        self.command = "".to_string();
+        let command = self.command.trim().to_string();
+        self.command = String::new();
+        self.history_pos = None;
+
+        self.print(&format!("]{}", command));
+        if command.is_empty() { return; }
+
+        if self.history.last() != Some(&command) {
+            self.history.push(command.clone());
+        }
+
+        match run(&command) {
+            Ok(ref output) if output.is_empty() => {}
+            Ok(output) => self.print(&output),
+            Err(e) => self.print(&e),
+        }
     }
 
     /// Try to tab-complete the current input string.
     fn tab_complete(&mut self) {
-        // TODO
+        let (prefix, partial, candidates): (&str, String, Vec<String>) = match self.command.find(' ') {
+            None => ("", self.command.clone(),
+                     COMMANDS.iter().map(|x| x.to_string()).collect()),
+            Some(i) if &self.command[..i] == "spawn" =>
+                ("spawn ", self.command[i + 1..].trim_left().to_string(),
+                 action::prototype_names()),
+            _ => return
+        };
+
+        let mut matches: Vec<String> = candidates.into_iter()
+            .filter(|x| x.starts_with(&partial[..]))
+            .collect();
+        if matches.is_empty() { return; }
+        matches.sort();
+
+        let common = common_prefix(&matches);
+        self.command = format!("{}{}", prefix, common);
+        if matches.len() == 1 {
+            // Ready for arguments.
+            if prefix.is_empty() { self.command.push(' '); }
+        } else if common.len() == partial.len() {
+            // Can't complete any further, show the options.
+            let options = matches.join("  ");
+            self.print(&options);
+        }
     }
 
     /// Bring the previous command in command history to current prompt.
     fn history_prev(&mut self) {
-        // TODO
+        if self.history.is_empty() { return; }
+        let pos = match self.history_pos {
+            None => self.history.len() - 1,
+            Some(0) => 0,
+            Some(i) => i - 1,
+        };
+        self.history_pos = Some(pos);
+        self.command = self.history[pos].clone();
     }
 
     /// Bring the next command in command history to current prompt.
     fn history_next(&mut self) {
-        // TODO
+        match self.history_pos {
+            Some(i) if i + 1 < self.history.len() => {
+                self.history_pos = Some(i + 1);
+                self.command = self.history[i + 1].clone();
+            }
+            Some(_) => {
+                // Past the newest command, back to an empty prompt.
+                self.history_pos = None;
+                self.command = String::new();
+            }
+            None => {}
+        }
+    }
+}
+
+/// Run a console command, return the output or an error message.
+fn run(command: &str) -> Result<String, String> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let args = &words[1..];
+
+    match words[0] {
+        "help" => Ok(HELP.to_string()),
+
+        "spawn" => {
+            if args.is_empty() { return Err("Usage: spawn <prototype> [dir]".to_string()); }
+            // Prototype names can have spaces, the direction is the last word
+            // if there is one.
+            let mut name = args.join(" ");
+            let mut dir = None;
+            if action::find_prototype(&name).is_none() && args.len() > 1 {
+                if let Some(d) = parse_dir(args[args.len() - 1]) {
+                    name = args[..args.len() - 1].join(" ");
+                    dir = Some(d);
+                }
+            }
+            if action::find_prototype(&name).is_none() {
+                return Err(format!("Unknown prototype '{}'", name));
+            }
+
+            let origin = try!(player_location());
+            let loc = match dir {
+                Some(d) => {
+                    let loc = origin + d.to_v2();
+                    if loc.blocks_walk() { return Err("That cell is blocked".to_string()); }
+                    loc
+                }
+                None => try!(origin.spill(|x| x != origin && !x.blocks_walk())
+                             .ok_or("No room to spawn".to_string())),
+            };
+            action::spawn_named(&name, loc);
+            Ok(format!("Spawned {}", name))
+        }
+
+        "teleport" => {
+            if args.len() != 2 { return Err("Usage: teleport <x> <y>".to_string()); }
+            let x = try!(parse_num::<i8>(args[0]));
+            let y = try!(parse_num::<i8>(args[1]));
+            let loc = Location::new(x, y);
+            if loc != try!(player_location()) && loc.blocks_walk() {
+                return Err("That cell is blocked".to_string());
+            }
+            try!(player()).place(loc);
+            Ok(String::new())
+        }
+
+        "reveal" => {
+            action::reveal_map();
+            Ok("Map revealed".to_string())
+        }
+
+        "godmode" => {
+            let on = !action::godmode();
+            action::set_godmode(on);
+            Ok(format!("Godmode {}", if on { "on" } else { "off" }))
+        }
+
+        "depth" => {
+            if args.is_empty() { return Ok(format!("Depth {}", action::current_depth())); }
+            let depth = try!(parse_num::<i32>(args[0]));
+            if depth < 1 { return Err("Depth must be at least 1".to_string()); }
+            try!(player());
+            action::start_level(depth);
+            Ok(String::new())
+        }
+
+        "heal" => {
+            let p = try!(player());
+            p.heal(p.max_hp());
+            Ok(String::new())
+        }
+
+        "kill" => {
+            if args.len() != 1 || args[0] != "all" { return Err("Usage: kill all".to_string()); }
+            Ok(format!("Killed {} mobs", action::kill_all()))
+        }
+
+        "seed" => Ok(format!("Seed {}", seed::seed_code(action::seed()))),
+
+        "save" => {
+            if args.len() != 1 { return Err("Usage: save <file>".to_string()); }
+            let path = calx::app_data_path("phage").join(args[0]);
+            try!(action::save_game_to(&path, &::version()));
+            Ok(format!("Saved to {}", path.to_string_lossy()))
+        }
+
+        "load" => {
+            if args.len() != 1 { return Err("Usage: load <file>".to_string()); }
+            let path = calx::app_data_path("phage").join(args[0]);
+            try!(action::load_game_from(&path));
+            Ok(format!("Loaded {}", path.to_string_lossy()))
+        }
+
+        "replay" => {
+            if args.len() != 1 { return Err("Usage: replay <file>".to_string()); }
+            let replay = try!(action::replay()
+                              .ok_or("No replay for a loaded game".to_string()));
+            let path = calx::app_data_path("phage").join(args[0]);
+            try!(replay.save(&path));
+            Ok(format!("Saved {} inputs to {}", replay.inputs.len(), path.to_string_lossy()))
+        }
+
+        _ => Err(format!("Unknown command '{}'", words[0])),
+    }
+}
+
+fn player() -> Result<Entity, String> {
+    match action::player() {
+        Some(p) if !p.is_corpse() => Ok(p),
+        _ => Err("No player".to_string()),
+    }
+}
+
+fn player_location() -> Result<Location, String> {
+    try!(player()).location().ok_or("Player is nowhere".to_string())
+}
+
+fn parse_num<T: ::std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("Bad number '{}'", s))
+}
+
+fn parse_dir(s: &str) -> Option<Dir6> {
+    match s {
+        "n" => Some(Dir6::North),
+        "ne" => Some(Dir6::NorthEast),
+        "se" => Some(Dir6::SouthEast),
+        "s" => Some(Dir6::South),
+        "sw" => Some(Dir6::SouthWest),
+        "nw" => Some(Dir6::NorthWest),
+        _ => None
+    }
+}
+
+/// Longest common prefix of the strings.
+fn common_prefix(words: &[String]) -> String {
+    let mut ret = words[0].clone();
+    for w in words[1..].iter() {
+        let len: usize = ret.chars().zip(w.chars())
+            .take_while(|&(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        ret.truncate(len);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use calx::{V2, HexGeom};
+    use world::{action, World};
+    use super::{Console, run};
+
+    #[test]
+    fn test_run_errors() {
+        for &command in ["frobnicate", "spawn", "teleport 1", "teleport x 2", "teleport 1 999",
+                         "depth 0", "depth deep", "kill", "kill some", "save", "load a b",
+                         "replay"].iter() {
+            assert!(run(command).is_err(), "'{}' should fail", command);
+        }
+    }
+
+    #[test]
+    fn test_teleport_blocked() {
+        World::new(Some(1)).enter(|| {
+            let player = action::player().unwrap();
+            let origin = player.location().unwrap();
+            let wall = (-8..9).flat_map(|y| (-8..9).map(move |x| V2(x, y)))
+                .filter(|v| v.hex_dist() <= 8)
+                .map(|v| origin + v)
+                .find(|loc| loc.terrain().blocks_walk())
+                .expect("No wall near the player");
+
+            let command = format!("teleport {} {}", wall.x, wall.y);
+            assert!(run(&command).is_err());
+            assert_eq!(player.location(), Some(origin));
+        });
+    }
+
+    #[test]
+    fn test_tab_complete() {
+        let mut console = Console::new();
+        console.command = "te".to_string();
+        console.tab_complete();
+        assert_eq!(console.command, "teleport ");
+
+        // Ambiguous prefixes complete as far as they can.
+        console.command = "h".to_string();
+        console.tab_complete();
+        assert_eq!(console.command, "he");
+        console.tab_complete();
+        assert!(console.text.ends_with("heal  help\n"));
+
+        console.command = "xyzzy".to_string();
+        console.tab_complete();
+        assert_eq!(console.command, "xyzzy");
+    }
+
+    #[test]
+    fn test_history() {
+        let mut console = Console::new();
+        for &command in ["help", "help", "seed"].iter() {
+            console.command = command.to_string();
+            console.process_command();
+        }
+        // Repeated commands are only stored once.
+        assert_eq!(console.history, vec!["help".to_string(), "seed".to_string()]);
+
+        console.history_prev();
+        assert_eq!(console.command, "seed");
+        console.history_prev();
+        console.history_prev();
+        assert_eq!(console.command, "help");
+        console.history_next();
+        assert_eq!(console.command, "seed");
+        console.history_next();
+        assert_eq!(console.command, "");
     }
 }
@@ -1,22 +1,27 @@
 use std::collections::HashMap;
+use std::path::Path;
+use calx;
 use calx::{color, V2, Anchor};
-use calx::backend::{Canvas, CanvasUtil, Event, Key, Fonter, Align};
+use calx::backend::{Canvas, CanvasUtil, Event, Key, Fonter, Align, Recorder};
 use world;
 use world::action;
 use world::action::Input::*;
 use world::action::ControlState::*;
-use world::{Msg, FovStatus};
-use calx::Dir6;
+use world::{Msg, FovStatus, Replay};
 use calx::Dir6::*;
 use world::{Entity};
 use world::item::{Slot};
 use worldview;
 use sprite::{WorldSprites, GibSprite, BeamSprite};
+use particle::{ParticleSprite, Effect};
 use tilecache;
 use tilecache::icon;
 use msg_queue::MsgQueue;
+use tween::Tweens;
 use ::{State, Transition};
 use console::Console;
+use keymap::{Keymap, Command};
+use settings::Settings;
 
 /// Type of effect signaled by making a visible entity blink for a moment.
 #[derive(Copy, Clone)]
@@ -32,19 +37,31 @@ pub struct GameState {
     world_spr: WorldSprites,
     /// Counters for entities with flashing damage animation.
     damage_timers: HashMap<Entity, (Blink, u32)>,
+    /// Animated movement of the entity sprites.
+    tweens: Tweens,
 
     /// Flag for autoexploration.
     // TODO: Probably going to need a general "ongoing activity" system at
     // some point.
     exploring: bool,
+    /// Inputs left to play back from a replay, last input first.
+    replay: Vec<action::Input>,
 
     msg: MsgQueue,
     ui_state: UiState,
 
     // Hacky thing to wait for next time Canvas reference is available.
     screenshot_requested: bool,
+    record_toggle_requested: bool,
+    recorder: Option<Recorder>,
 
     console: Console,
+
+    /// Debug overlays on the world view.
+    overlays: worldview::Overlays,
+
+    keymap: Keymap,
+    settings: Settings,
 }
 
 enum UiState {
@@ -54,13 +71,64 @@ enum UiState {
 }
 
 impl GameState {
-    pub fn new(seed: Option<u32>) -> GameState {
+    /// Start a new game or continue a saved one. Fails if there is a save
+    /// file that can't be loaded.
+    pub fn new(seed: Option<u32>) -> Result<GameState, String> {
         world::init_world(seed);
-        let mut msg = MsgQueue::new();
-        action::load_game();
+        try!(action::load_game());
+        Ok(GameState::start())
+    }
+
+    /// Start a new game with a fixed seed without looking at the save.
+    pub fn new_seeded(seed: u32) -> GameState {
+        world::init_world(Some(seed));
+        GameState::start()
+    }
+
+    /// Start from a save file. Fails if the file can't be loaded.
+    pub fn load(path: &Path) -> Result<GameState, String> {
+        world::init_world(None);
+        try!(action::load_game_from(path));
+        Ok(GameState::start())
+    }
+
+    /// Start a new game that plays back the inputs of a replay.
+    pub fn replay(replay: Replay) -> GameState {
+        world::init_world(Some(replay.seed));
+        let mut ret = GameState::start();
+        ret.replay = replay.inputs.into_iter().rev().collect();
+        ret.msg.msg("Replaying, press any key to take over\n".to_string());
+        ret
+    }
 
-        msg.msg("Move with Q,W,E, A,S,D, wait with SPACE\n".to_string());
-        msg.msg("ESC to save and return to title screen\n".to_string());
+    /// Set up the game interface for the current world.
+    fn start() -> GameState {
+        // Settings are shown as an error on the title screen, just fall back
+        // to defaults here.
+        let settings = Settings::load().unwrap_or(Default::default());
+        let mut msg = MsgQueue::new(settings.letter_read_duration);
+
+        // A broken key config shouldn't keep the player out of the game.
+        let keymap = match Keymap::load() {
+            Ok(keymap) => keymap,
+            Err(e) => {
+                msg.msg(format!("Using default keys, {}\n", e));
+                Keymap::default()
+            }
+        };
+
+        {
+            let k = |c| keymap.key_name(c);
+            msg.msg(format!("Move with {},{},{}, {},{},{}, wait with {}\n",
+                            k(Command::NorthWest), k(Command::North), k(Command::NorthEast),
+                            k(Command::SouthWest), k(Command::South), k(Command::SouthEast),
+                            k(Command::Pass)));
+        }
+        if action::saving() {
+            msg.msg("ESC to save and return to title screen\n".to_string());
+        } else {
+            msg.msg("Saving is off, ESC to return to title screen\n".to_string());
+        }
         msg.msg("Exposed phage is weak, find stronger hosts.\n".to_string());
         msg.caption("Phage deployed".to_string());
         msg.caption("Clear zone of terran life".to_string());
@@ -68,11 +136,18 @@ impl GameState {
         GameState {
             world_spr: WorldSprites::new(),
             damage_timers: HashMap::new(),
+            tweens: Tweens::new(),
             exploring: false,
+            replay: Vec::new(),
             msg: msg,
             ui_state: UiState::Gameplay,
             screenshot_requested: false,
+            record_toggle_requested: false,
+            recorder: None,
             console: Console::new(),
+            overlays: Default::default(),
+            keymap: keymap,
+            settings: settings,
         }
     }
 
@@ -92,7 +167,8 @@ impl GameState {
 
     fn base_paint(&mut self, ctx: &mut Canvas) {
         let camera = world::camera();
-        worldview::draw_world(&camera, ctx, &self.damage_timers);
+        worldview::draw_world(&camera, ctx, &self.damage_timers, &self.tweens, &self.overlays,
+                              self.settings.mob_status);
 
         self.world_spr.draw(|x| (camera + x).fov_status() == Some(FovStatus::Seen), &camera, ctx);
         self.world_spr.update();
@@ -107,6 +183,12 @@ impl GameState {
             .text(format!("{} terran{} in zone", count, if count != 1 { "s" } else { "" }))
             .draw(V2(638.0, 0.0));
 
+        Fonter::new(ctx)
+            .color(color::LIGHTGRAY).border(color::BLACK)
+            .anchor(Anchor::TopRight).align(Align::Right)
+            .text(location_name)
+            .draw(V2(638.0, 8.0));
+
         self.msg.draw(ctx);
         if let Some(player) = action::player() {
             self.draw_player_ui(ctx, player);
@@ -131,6 +213,9 @@ impl GameState {
                 Some(Msg::Damage(entity)) => {
                     self.damage_timers.insert(entity, (Blink::Damaged, 2));
                 }
+                Some(Msg::Melee(entity, dir)) => {
+                    self.tweens.melee(entity, dir);
+                }
                 Some(Msg::Text(txt)) => {
                     self.msg.msg(txt)
                 }
@@ -140,8 +225,20 @@ impl GameState {
                 Some(Msg::Beam(loc1, loc2)) => {
                     self.world_spr.add(Box::new(BeamSprite::new(loc1, loc2, 10)));
                 }
-                Some(Msg::Sparks(_loc)) => {
-                    // TODO
+                Some(Msg::Sparks(loc)) => {
+                    self.world_spr.add(Box::new(ParticleSprite::new(Effect::Sparks, loc, None)));
+                }
+                Some(Msg::Smoke(loc)) => {
+                    self.world_spr.add(Box::new(ParticleSprite::new(Effect::Smoke, loc, None)));
+                }
+                Some(Msg::Blood(loc, dir)) => {
+                    self.world_spr.add(Box::new(ParticleSprite::new(Effect::Blood, loc, Some(dir))));
+                }
+                Some(Msg::Acid(loc, dir)) => {
+                    self.world_spr.add(Box::new(ParticleSprite::new(Effect::Acid, loc, Some(dir))));
+                }
+                Some(Msg::Explosion(loc)) => {
+                    self.world_spr.add(Box::new(ParticleSprite::new(Effect::Explosion, loc, None)));
                 }
                 Some(_) => {
                     //println!("Unhandled Msg type {:?}", x);
@@ -155,6 +252,7 @@ impl GameState {
         if action::control_state() == ReadyToUpdate {
             action::update();
         }
+        self.tweens.update();
 
         if self.exploring {
             if action::control_state() == AwaitingInput {
@@ -162,10 +260,17 @@ impl GameState {
             }
         }
 
-        // Decrement damage timers.
+        if !self.replay.is_empty() && action::control_state() == AwaitingInput {
+            action::input(self.replay.pop().unwrap());
+            if self.replay.is_empty() {
+                self.msg.msg("Replay finished\n".to_string());
+            }
+        }
+
+        // Decrement damage timers, drop the ones for deleted entities.
         // XXX: Can we do mutable contents iter without the cloning?
         self.damage_timers = self.damage_timers.clone().into_iter()
-            .filter(|&(_, (_, t))| t > 0)
+            .filter(|&(e, (_, t))| t > 0 && e.is_alive())
             .map(|(e, (b, t))| (e, (b, t - 1)))
             .collect();
 
@@ -204,14 +309,23 @@ impl GameState {
 
     pub fn inventory_process(&mut self, ctx: &mut Canvas, event: Event) -> bool {
         let player = action::player().unwrap();
+        match self.keymap.command(event) {
+            Some(Command::Inventory) => { self.ui_state = UiState::Gameplay; return true; }
+            Some(Command::Screenshot) => { self.screenshot_requested = true; return true; }
+            Some(Command::Record) => { self.record_toggle_requested = true; return true; }
+            _ => {}
+        }
+
         match event {
             Event::RenderFrame => { self.update(ctx); }
-            Event::KeyPressed(Key::Escape) | Event::KeyPressed(Key::Tab) => {
+            Event::KeyPressed(Key::Escape) => {
                 self.ui_state = UiState::Gameplay
             }
-            Event::KeyPressed(Key::F12) => { self.screenshot_requested = true; }
             Event::KeyPressed(_) => {}
 
+            // Inventory changes go through the player input so that they get
+            // recorded in the replay at the same point of the turn order.
+            Event::Char(_) if action::control_state() != AwaitingInput => {}
             Event::Char(ch) => {
                 for slot_data in SLOT_DATA.iter() {
                     if ch == slot_data.key {
@@ -224,7 +338,7 @@ impl GameState {
                                     // TODO: Message about full bag.
                                 }
                                 Some(swap_slot) => {
-                                    player.swap_equipped(slot_data.slot, swap_slot);
+                                    action::input(SwapItems(slot_data.slot, swap_slot));
                                 }
                             }
                         }
@@ -235,7 +349,7 @@ impl GameState {
                                 let equip_slots = item.equip_slots();
                                 for &swap_slot in equip_slots.iter() {
                                     if player.equipped(swap_slot).is_none() {
-                                        player.swap_equipped(slot_data.slot, swap_slot);
+                                        action::input(SwapItems(slot_data.slot, swap_slot));
                                         break;
                                     }
                                 }
@@ -244,8 +358,8 @@ impl GameState {
                     }
                     if ch == slot_data.key.to_uppercase().next().unwrap() {
                         // Drop item in slot.
-                        if let Some(item) = player.equipped(slot_data.slot) {
-                            item.place(player.location().unwrap());
+                        if player.equipped(slot_data.slot).is_some() {
+                            action::input(DropItem(slot_data.slot));
                         }
                         break;
                     }
@@ -264,6 +378,11 @@ impl GameState {
             ctx.save_screenshot(&"phage");
             self.screenshot_requested = false;
         }
+        if self.record_toggle_requested {
+            self.toggle_recording();
+            self.record_toggle_requested = false;
+        }
+        self.record_frame(ctx);
 
         ctx.clear();
 
@@ -277,31 +396,51 @@ impl GameState {
         }
     }
 
-    fn smart_move(&mut self, dir: Dir6) {
-        let player = action::player().unwrap();
-        let loc = player.location().unwrap();
-
-        if !(loc + dir.to_v2()).has_mobs() {
-            // Shoot instead of moving if you'd hit an enemy and there's no
-            // melee target.
-            let shoot_range = player.stats().ranged_range as usize;
-            if let Some(e) = action::find_target(player, dir, shoot_range) {
-                if player.is_hostile_to(e) {
-                    action::input(Shoot(dir));
-                    return;
-                }
-            }
+    fn toggle_recording(&mut self) {
+        if self.recorder.is_some() {
+            self.stop_recording();
+            return;
         }
 
-        for &d in vec![dir, dir + 1, dir - 1].iter() {
-            let target_loc = loc + d.to_v2();
-            if target_loc.has_mobs() {
-                action::input(Melee(d));
-                return;
-            } else if player.can_step(d) {
-                action::input(Step(d));
-                return;
+        let dir = calx::app_data_path("phage").join("screenshots");
+        match Recorder::new(self.settings.record_format, &dir, "phage", self.settings.frame_interval) {
+            Ok(recorder) => {
+                self.recorder = Some(recorder
+                    .frame_skip(self.settings.record_frame_skip)
+                    .max_bytes(self.settings.record_max_size as u64 * 1024 * 1024));
+                self.msg.msg("Recording started\n".to_string());
             }
+            Err(e) => self.msg.msg(format!("Recording failed: {}\n", e)),
+        }
+    }
+
+    /// Finish the recording if one is running.
+    fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            self.finish_recording(recorder);
+        }
+    }
+
+    fn finish_recording(&mut self, recorder: Recorder) {
+        match recorder.finish() {
+            Ok(path) => self.msg.msg(format!("Recording saved to {}\n", path.to_string_lossy())),
+            Err(e) => self.msg.msg(format!("Recording failed: {}\n", e)),
+        }
+    }
+
+    /// Add the last rendered frame to the recording if one is running.
+    fn record_frame(&mut self, ctx: &mut Canvas) {
+        let (result, full) = match self.recorder {
+            Some(ref mut recorder) => (recorder.add_frame(|| ctx.screenshot()), recorder.is_full()),
+            None => return,
+        };
+        if let Err(e) = result {
+            // Finish to keep the frames recorded so far.
+            self.msg.msg(format!("Recording failed: {}\n", e));
+            self.stop_recording();
+        } else if full {
+            self.msg.msg("Recording size limit reached\n".to_string());
+            self.stop_recording();
         }
     }
 
@@ -330,63 +469,93 @@ impl GameState {
         false
     }
 
-    /// Process a player control keypress.
-    pub fn gameplay_process_key(&mut self, key: Key) -> bool {
+    /// Run a player command from a bound key.
+    pub fn gameplay_command(&mut self, command: Command) -> bool {
+        match command {
+            Command::Inventory => { self.ui_state = UiState::Inventory; return true; }
+            Command::Console if !cfg!(ndebug) => { self.ui_state = UiState::Console; return true; }
+            Command::Screenshot => { self.screenshot_requested = true; return true; }
+            Command::Record => { self.record_toggle_requested = true; return true; }
+            _ => {}
+        }
+
         if action::control_state() != AwaitingInput {
             return false;
         }
 
-        if self.exploring {
-            self.exploring = false;
-        }
+        match command {
+            Command::NorthWest => { action::smart_move(NorthWest); }
+            Command::North => { action::smart_move(North); }
+            Command::NorthEast => { action::smart_move(NorthEast); }
+            Command::SouthWest => { action::smart_move(SouthWest); }
+            Command::South => { action::smart_move(South); }
+            Command::SouthEast => { action::smart_move(SouthEast); }
 
-        match key {
-            Key::Q | Key::Pad7 => { self.smart_move(NorthWest); }
-            Key::W | Key::Pad8 | Key::Up => { self.smart_move(North); }
-            Key::E | Key::Pad9 => { self.smart_move(NorthEast); }
-            Key::A | Key::Pad1 => { self.smart_move(SouthWest); }
-            Key::S | Key::Pad2 | Key::Down => { self.smart_move(South); }
-            Key::D | Key::Pad3 => { self.smart_move(SouthEast); }
-
-            Key::Space | Key::Pad5 => { action::input(Pass); }
-            Key::X => { self.exploring = true; }
-
-            Key::F5 if !cfg!(ndebug) => { action::save_game(); }
-            Key::F9 if !cfg!(ndebug) => { action::load_game(); }
-            Key::F12 => { self.screenshot_requested = true; }
+            Command::Pass => { action::input(Pass); }
+            Command::Explore => { self.exploring = true; }
+
+            Command::QuickSave if !cfg!(ndebug) && action::saving() => {
+                if let Err(e) = action::save_game(&::version()) {
+                    self.msg.msg(format!("Save failed: {}\n", e));
+                }
+            }
+            Command::QuickLoad if !cfg!(ndebug) => {
+                if let Err(e) = action::load_game() {
+                    self.msg.msg(format!("Load failed: {}\n", e));
+                }
+            }
             _ => { return false; }
         }
         return true;
     }
 
+    /// Process a debug keypress that isn't bound to a command.
+    fn debug_process_key(&mut self, key: Key) {
+        if cfg!(ndebug) { return; }
+        match key {
+            // Debug overlays.
+            Key::F1 => { self.overlays.explore_map = !self.overlays.explore_map; }
+            Key::F2 => { self.overlays.hunting_map = !self.overlays.hunting_map; }
+            Key::F3 => { self.overlays.brains = !self.overlays.brains; }
+            Key::F4 => { self.overlays.mob_fov = !self.overlays.mob_fov; }
+            Key::F6 => { self.overlays.spawns = !self.overlays.spawns; }
+            _ => {}
+        }
+    }
+
     pub fn gameplay_process(&mut self, ctx: &mut Canvas, event: Event) -> bool {
         match event {
             Event::RenderFrame => {
                 self.update(ctx);
+                return true;
             }
             // TODO: Better quit confirmation than just pressing esc.
             Event::KeyPressed(Key::Escape) => {
-                action::save_game();
+                // Stay in the game if saving fails so the run isn't lost.
+                if let Err(e) = action::save_game(&::version()) {
+                    self.msg.msg(format!("Save failed: {}\n", e));
+                    return true;
+                }
                 return false;
             }
-            Event::KeyPressed(k) => {
-                self.gameplay_process_key(k);
-            }
-
-            Event::Char(ch) => {
-                // TODO: Chars and keypresses in same lookup (use variants?)
-                match ch {
-                    // Open console
-                    // (Make this be a typed-key instead of a pressed-key
-                    // event so that the event will have been consumed and
-                    // console won't start with an inputted '`'.)
-                    '`' if !cfg!(ndebug) => { self.ui_state = UiState::Console; }
-
-                    _ => ()
+            Event::KeyPressed(_) => {
+                // Any keypress stops autoexplore and replays.
+                if action::control_state() == AwaitingInput {
+                    self.exploring = false;
+                    if !self.replay.is_empty() {
+                        self.replay.clear();
+                        self.msg.msg("Replay stopped\n".to_string());
+                    }
                 }
             }
+            _ => {}
+        }
 
-            _ => ()
+        match self.keymap.command(event) {
+            Some(command) => { self.gameplay_command(command); }
+            None => {
+                if let Event::KeyPressed(k) = event { self.debug_process_key(k); }
+            }
         }
         true
     }
@@ -394,7 +563,10 @@ impl GameState {
 
 impl State for GameState {
     fn process(&mut self, ctx: &mut Canvas, event: Event) -> Option<Transition> {
-        if event == Event::Quit { return Some(Transition::Exit); }
+        if event == Event::Quit {
+            self.stop_recording();
+            return Some(Transition::Exit);
+        }
         let running = match self.ui_state {
             UiState::Gameplay => self.gameplay_process(ctx, event),
             UiState::Inventory => self.inventory_process(ctx, event),
@@ -405,6 +577,7 @@ impl State for GameState {
         };
 
         if !running {
+            self.stop_recording();
             Some(Transition::Title)
         } else {
             None
@@ -0,0 +1,376 @@
+/*! Player command key bindings. */
+
+use std::fmt;
+use calx::backend::{Event, Key};
+use settings::{read_config, write_config};
+
+static FILENAME: &'static str = "keys.txt";
+
+/// A player command that can be bound to keys.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Command {
+    North,
+    NorthEast,
+    SouthEast,
+    South,
+    SouthWest,
+    NorthWest,
+    Pass,
+    Explore,
+    Inventory,
+    Console,
+    Screenshot,
+    Record,
+    QuickSave,
+    QuickLoad,
+}
+
+/// Commands with their config file names and descriptions, in the order
+/// they're listed in.
+pub static COMMANDS: [(Command, &'static str, &'static str); 14] = [
+    (Command::North, "north", "Move north"),
+    (Command::NorthEast, "northeast", "Move northeast"),
+    (Command::SouthEast, "southeast", "Move southeast"),
+    (Command::South, "south", "Move south"),
+    (Command::SouthWest, "southwest", "Move southwest"),
+    (Command::NorthWest, "northwest", "Move northwest"),
+    (Command::Pass, "pass", "Wait a turn"),
+    (Command::Explore, "explore", "Autoexplore"),
+    (Command::Inventory, "inventory", "Inventory"),
+    (Command::Console, "console", "Debug console"),
+    (Command::Screenshot, "screenshot", "Take screenshot"),
+    (Command::Record, "record", "Start/stop recording"),
+    (Command::QuickSave, "quicksave", "Quick save (debug)"),
+    (Command::QuickLoad, "quickload", "Quick load (debug)"),
+];
+
+impl Command {
+    pub fn name(self) -> &'static str {
+        COMMANDS.iter().find(|x| x.0 == self).unwrap().1
+    }
+
+    pub fn description(self) -> &'static str {
+        COMMANDS.iter().find(|x| x.0 == self).unwrap().2
+    }
+
+    pub fn from_name(name: &str) -> Option<Command> {
+        COMMANDS.iter().find(|x| x.1 == name).map(|x| x.0)
+    }
+}
+
+/// An input that can trigger a command.
+///
+/// Key bindings are for physical keys, character bindings are for the
+/// character the key types with the current keyboard layout.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Binding {
+    Key(Key),
+    Char(char),
+}
+
+impl Binding {
+    /// Return the binding an input event would trigger.
+    pub fn from_event(event: Event) -> Option<Binding> {
+        match event {
+            Event::KeyPressed(k) => Some(Binding::Key(k)),
+            Event::Char(c) if !c.is_control() => Some(Binding::Char(c)),
+            _ => None
+        }
+    }
+
+    /// Return whether a single keypress can trigger both bindings.
+    ///
+    /// The characters keys type depend on the keyboard layout, keys and
+    /// characters are matched using the US layout.
+    pub fn overlaps(self, other: Binding) -> bool {
+        fn key_types(k: Key, c: char) -> bool {
+            let c = c.to_lowercase().next().unwrap_or(c);
+            US_LAYOUT.iter().any(|&(key, ch)| key == k && ch == c)
+        }
+
+        match (self, other) {
+            (Binding::Key(k), Binding::Char(c)) | (Binding::Char(c), Binding::Key(k)) => key_types(k, c),
+            (a, b) => a == b,
+        }
+    }
+}
+
+/// Characters typed by the keys of the US keyboard layout.
+static US_LAYOUT: [(Key, char); 48] = [
+    (Key::Space, ' '), (Key::Apostrophe, '\''), (Key::Comma, ','), (Key::Minus, '-'),
+    (Key::Period, '.'), (Key::Slash, '/'), (Key::Semicolon, ';'), (Key::Equals, '='),
+    (Key::LeftBracket, '['), (Key::Backslash, '\\'), (Key::RightBracket, ']'),
+    (Key::Grave, '`'),
+    (Key::Num0, '0'), (Key::Num1, '1'), (Key::Num2, '2'), (Key::Num3, '3'), (Key::Num4, '4'),
+    (Key::Num5, '5'), (Key::Num6, '6'), (Key::Num7, '7'), (Key::Num8, '8'), (Key::Num9, '9'),
+    (Key::A, 'a'), (Key::B, 'b'), (Key::C, 'c'), (Key::D, 'd'), (Key::E, 'e'), (Key::F, 'f'),
+    (Key::G, 'g'), (Key::H, 'h'), (Key::I, 'i'), (Key::J, 'j'), (Key::K, 'k'), (Key::L, 'l'),
+    (Key::M, 'm'), (Key::N, 'n'), (Key::O, 'o'), (Key::P, 'p'), (Key::Q, 'q'), (Key::R, 'r'),
+    (Key::S, 's'), (Key::T, 't'), (Key::U, 'u'), (Key::V, 'v'), (Key::W, 'w'), (Key::X, 'x'),
+    (Key::Y, 'y'), (Key::Z, 'z'),
+];
+
+/// Keys are written with their names, characters in single quotes.
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Binding::Key(k) => write!(f, "{}", k),
+            Binding::Char(c) => write!(f, "'{}'", c),
+        }
+    }
+}
+
+/// Mapping from input events to player commands.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Keymap {
+    bindings: Vec<(Binding, Command)>,
+}
+
+/// The bindings used when there is no key config file.
+impl Default for Keymap {
+    fn default() -> Keymap {
+        use self::Command::*;
+        use self::Binding::{Key as K, Char as C};
+        Keymap {
+            bindings: vec![
+                (K(Key::Q), NorthWest), (K(Key::Pad7), NorthWest),
+                (K(Key::W), North), (K(Key::Pad8), North), (K(Key::Up), North),
+                (K(Key::E), NorthEast), (K(Key::Pad9), NorthEast),
+                (K(Key::A), SouthWest), (K(Key::Pad1), SouthWest),
+                (K(Key::S), South), (K(Key::Pad2), South), (K(Key::Down), South),
+                (K(Key::D), SouthEast), (K(Key::Pad3), SouthEast),
+                (K(Key::Space), Pass), (K(Key::Pad5), Pass),
+                (K(Key::X), Explore),
+                (K(Key::Tab), Inventory),
+                // Typed character so that the keypress is consumed before
+                // the console opens and won't show up in the console input.
+                (C('`'), Console),
+                (K(Key::F12), Screenshot),
+                (K(Key::F10), Record),
+                (K(Key::F5), QuickSave),
+                (K(Key::F9), QuickLoad),
+            ],
+        }
+    }
+}
+
+impl Keymap {
+    /// Return the command an input event is bound to.
+    pub fn command(&self, event: Event) -> Option<Command> {
+        Binding::from_event(event).and_then(|b| self.bound_to(b))
+    }
+
+    /// Return the command a binding is bound to.
+    pub fn bound_to(&self, binding: Binding) -> Option<Command> {
+        self.bindings.iter().find(|x| x.0 == binding).map(|x| x.1)
+    }
+
+    /// List the bindings of a command.
+    pub fn bindings(&self, command: Command) -> Vec<Binding> {
+        self.bindings.iter().filter(|x| x.1 == command).map(|x| x.0).collect()
+    }
+
+    /// Return the name of the first binding of a command for help texts.
+    pub fn key_name(&self, command: Command) -> String {
+        match self.bindings(command).first() {
+            Some(b) => format!("{}", b),
+            None => "(unbound)".to_string(),
+        }
+    }
+
+    /// Add a binding to a command. Fails if a keypress that triggers the
+    /// binding would also trigger another binding, or if the binding is
+    /// Escape, which always leaves the current screen.
+    pub fn bind(&mut self, binding: Binding, command: Command) -> Result<(), String> {
+        if binding == Binding::Key(Key::Escape) {
+            return Err(format!("{} can't be bound", binding));
+        }
+        // A keypress that triggers the command twice is as bad as one that
+        // triggers two commands.
+        if let Some(&(b, c)) = self.bindings.iter()
+            .find(|&&x| x.0.overlaps(binding) && x != (binding, command)) {
+            return Err(format!("{} is already bound to {}", b, c.name()));
+        }
+        if self.bound_to(binding).is_none() {
+            self.bindings.push((binding, command));
+        }
+        Ok(())
+    }
+
+    /// Remove all the bindings of a command.
+    pub fn clear(&mut self, command: Command) {
+        self.bindings.retain(|x| x.1 != command);
+    }
+
+    /// Parse a keymap from the text of a key config file. The filename is
+    /// only used in error messages.
+    ///
+    /// Each line is a command name followed by a colon and the bindings,
+    /// eg. `north: W Pad8 'k'`. Commands missing from the file keep their
+    /// default bindings.
+    pub fn parse(filename: &str, text: &str) -> Result<Keymap, String> {
+        let mut ret = Keymap::default();
+        let mut seen: Vec<Command> = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_num = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("#") { continue; }
+
+            let idx = match line.find(':') {
+                Some(idx) => idx,
+                None => return Err(format!("{}:{}: Expected 'command: keys', got '{}'", filename, line_num, line)),
+            };
+            let name = line[..idx].trim();
+            let command = match Command::from_name(name) {
+                Some(c) => c,
+                None => return Err(format!("{}:{}: Unknown command '{}'", filename, line_num, name)),
+            };
+            if seen.contains(&command) {
+                return Err(format!("{}:{}: Command '{}' is listed twice", filename, line_num, name));
+            }
+            seen.push(command);
+
+            let bindings = try!(parse_bindings(line[idx + 1..].trim())
+                                .map_err(|e| format!("{}:{}: {}", filename, line_num, e)));
+            if bindings.contains(&Binding::Key(Key::Escape)) {
+                return Err(format!("{}:{}: Escape can't be bound", filename, line_num));
+            }
+            ret.clear(command);
+            for &b in bindings.iter() {
+                ret.bindings.push((b, command));
+            }
+        }
+
+        // A command from the file can take a binding that's still in use by
+        // a default binding of a command not in the file. Drop the defaults
+        // then, conflicts within the file are errors.
+        let mut bindings: Vec<(Binding, Command)> = Vec::new();
+        for &(b, c) in ret.bindings.iter() {
+            match bindings.iter().position(|x| x.0 == b) {
+                Some(i) if bindings[i].1 == c => {}
+                Some(i) if seen.contains(&bindings[i].1) && seen.contains(&c) => {
+                    return Err(format!("{}: {} is bound to both {} and {}",
+                                       filename, b, bindings[i].1.name(), c.name()));
+                }
+                Some(i) => {
+                    if seen.contains(&c) { bindings[i] = (b, c); }
+                }
+                None => bindings.push((b, c)),
+            }
+        }
+        ret.bindings = bindings;
+
+        Ok(ret)
+    }
+
+    /// Write the keymap in the key config file format.
+    pub fn to_text(&self) -> String {
+        let mut ret = String::new();
+        ret.push_str("# Phage key bindings\n");
+        ret.push_str("# Keys are named like in calx::backend::Key, typed characters are in single quotes.\n");
+        for &(c, name, _) in COMMANDS.iter() {
+            let keys: Vec<String> = self.bindings(c).iter().map(|b| format!("{}", b)).collect();
+            ret.push_str(&format!("{}: {}\n", name, keys.join(" ")));
+        }
+        ret
+    }
+
+    /// Load the keymap from the config file. Returns the default keymap if
+    /// there is no config file.
+    pub fn load() -> Result<Keymap, String> {
+        match try!(read_config(FILENAME)) {
+            Some(text) => Keymap::parse(FILENAME, &text),
+            None => Ok(Keymap::default()),
+        }
+    }
+
+    /// Save the keymap to the config file.
+    pub fn save(&self) -> Result<(), String> {
+        write_config(FILENAME, &self.to_text())
+    }
+}
+
+/// Parse a whitespace-separated list of key names and quoted characters.
+fn parse_bindings(text: &str) -> Result<Vec<Binding>, String> {
+    let mut ret = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        match chars.peek() {
+            None => break,
+            Some(&c) if c.is_whitespace() => { chars.next(); }
+            Some(&'\'') => {
+                chars.next();
+                // Read the quoted character before checking for the closing
+                // quote so that ''' and ' ' work.
+                match (chars.next(), chars.next()) {
+                    (Some(c), Some('\'')) => ret.push(Binding::Char(c)),
+                    _ => return Err("Bad quoted character".to_string()),
+                }
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() { break; }
+                    word.push(c);
+                    chars.next();
+                }
+                ret.push(Binding::Key(try!(word.parse())));
+            }
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use calx::backend::{Event, Key};
+    use super::{Keymap, Binding, Command};
+
+    #[test]
+    fn test_roundtrip() {
+        let mut keymap = Keymap::default();
+        keymap.clear(Command::Pass);
+        keymap.bind(Binding::Char(' '), Command::Pass).unwrap();
+        keymap.bind(Binding::Char('\''), Command::Explore).unwrap();
+        let text = keymap.to_text();
+        assert_eq!(Keymap::parse("test", &text).unwrap().to_text(), text);
+    }
+
+    #[test]
+    fn test_parse() {
+        let keymap = Keymap::parse("test", "# Dvorak\nnorth: Comma 'k'\n").unwrap();
+        assert_eq!(keymap.command(Event::KeyPressed(Key::Comma)), Some(Command::North));
+        assert_eq!(keymap.command(Event::Char('k')), Some(Command::North));
+        assert_eq!(keymap.command(Event::KeyPressed(Key::W)), None);
+        // Unlisted commands keep their defaults.
+        assert_eq!(keymap.command(Event::KeyPressed(Key::X)), Some(Command::Explore));
+
+        // Listed commands take over default bindings of unlisted ones.
+        let keymap = Keymap::parse("test", "south: O W\n").unwrap();
+        assert_eq!(keymap.command(Event::KeyPressed(Key::W)), Some(Command::South));
+        assert_eq!(keymap.command(Event::KeyPressed(Key::Pad8)), Some(Command::North));
+
+        assert!(Keymap::parse("test", "north: W\nsouth: W\n").is_err());
+        assert!(Keymap::parse("test", "north: Foo\n").is_err());
+        assert!(Keymap::parse("test", "fly: W\n").is_err());
+        assert!(Keymap::parse("test", "pass: Escape\n").is_err());
+    }
+
+    #[test]
+    fn test_conflict() {
+        let mut keymap = Keymap::default();
+        assert!(keymap.bind(Binding::Key(Key::W), Command::South).is_err());
+        assert!(keymap.bind(Binding::Key(Key::W), Command::North).is_ok());
+
+        // Keys conflict with the characters they type.
+        assert!(keymap.bind(Binding::Char('w'), Command::South).is_err());
+        assert!(keymap.bind(Binding::Char('W'), Command::South).is_err());
+        assert!(keymap.bind(Binding::Key(Key::Grave), Command::Pass).is_err());
+        assert!(keymap.bind(Binding::Char('w'), Command::North).is_err());
+        assert!(keymap.bind(Binding::Char('j'), Command::North).is_ok());
+
+        assert!(keymap.bind(Binding::Key(Key::Escape), Command::Pass).is_err());
+        assert_eq!(keymap.command(Event::KeyPressed(Key::Escape)), None);
+    }
+}
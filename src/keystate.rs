@@ -0,0 +1,170 @@
+use calx::{V2, color, Anchor};
+use calx::backend::{Key, Event};
+use calx::backend::{Canvas, Fonter};
+use keymap::{Keymap, Binding, COMMANDS};
+use ::{Transition, State};
+
+/// Key rebinding screen.
+pub struct KeyState {
+    keymap: Keymap,
+    /// Index of the selected command in COMMANDS.
+    cursor: usize,
+    /// Waiting for the input to bind to the selected command.
+    capture: Option<Capture>,
+    /// A key was just captured, ignore the character event it sends next.
+    swallow_char: bool,
+    /// The keymap has unsaved changes.
+    changed: bool,
+    message: Option<String>,
+}
+
+#[derive(Copy, Clone)]
+enum Capture {
+    Key,
+    Char,
+}
+
+impl KeyState {
+    pub fn new() -> KeyState {
+        let (keymap, message) = match Keymap::load() {
+            Ok(keymap) => (keymap, None),
+            Err(e) => (Keymap::default(), Some(format!("{}\nUsing default keys", e))),
+        };
+        KeyState {
+            keymap: keymap,
+            cursor: 0,
+            capture: None,
+            swallow_char: false,
+            changed: false,
+            message: message,
+        }
+    }
+
+    fn draw(&self, ctx: &mut Canvas) {
+        Fonter::new(ctx)
+            .color(color::DARKCYAN)
+            .text("Key bindings".to_string())
+            .draw(V2(160.0, 16.0));
+
+        for (i, &(command, _, desc)) in COMMANDS.iter().enumerate() {
+            let y = 40.0 + 8.0 * i as f32;
+            let selected = i == self.cursor;
+            let col = if selected { color::WHITE } else { color::LIGHTGRAY };
+            Fonter::new(ctx)
+                .color(col)
+                .text(format!("{}{}", if selected { "> " } else { "  " }, desc))
+                .draw(V2(160.0, y));
+
+            let keys: Vec<String> = self.keymap.bindings(command).iter()
+                .map(|b| format!("{}", b)).collect();
+            let keys = if selected && self.capture.is_some() {
+                "Press key...".to_string()
+            } else {
+                keys.join(" ")
+            };
+            Fonter::new(ctx)
+                .color(if selected { color::YELLOW } else { color::DARKCYAN })
+                .text(keys)
+                .draw(V2(320.0, y));
+        }
+
+        Fonter::new(ctx)
+            .color(color::DARKCYAN)
+            .anchor(Anchor::BottomLeft)
+            .text("ENTER) bind key  C) bind typed character  BACKSPACE) clear\nR)eset all to defaults  ESC) save and exit\nTyped characters follow the keyboard layout, keys don't.".to_string())
+            .draw(V2(160.0, 340.0));
+
+        if let Some(ref text) = self.message {
+            Fonter::new(ctx)
+                .color(color::ORANGERED).border(color::BLACK)
+                .anchor(Anchor::Top)
+                .text(text.clone())
+                .draw(V2(320.0, 160.0));
+        }
+    }
+
+    fn bind(&mut self, binding: Binding) {
+        let command = COMMANDS[self.cursor].0;
+        self.capture = None;
+        match self.keymap.bind(binding, command) {
+            Ok(_) => { self.changed = true; }
+            Err(e) => { self.message = Some(e); }
+        }
+    }
+}
+
+impl State for KeyState {
+    fn process(&mut self, ctx: &mut Canvas, event: Event) -> Option<Transition> {
+        if let Event::RenderFrame = event {
+            self.draw(ctx);
+            return None;
+        }
+        if event == Event::Quit { return Some(Transition::Exit); }
+
+        // The character from a captured key press mustn't reach the
+        // character commands below. Keys that don't type anything send no
+        // character, so the next key press ends the wait.
+        if self.swallow_char {
+            match event {
+                Event::Char(_) => {
+                    self.swallow_char = false;
+                    return None;
+                }
+                Event::KeyPressed(_) => { self.swallow_char = false; }
+                _ => {}
+            }
+        }
+
+        match (self.capture, event) {
+            (Some(_), Event::KeyPressed(Key::Escape)) => { self.capture = None; }
+            (Some(Capture::Key), Event::KeyPressed(k)) => {
+                self.bind(Binding::Key(k));
+                self.swallow_char = true;
+            }
+            (Some(Capture::Char), Event::Char(c)) if !c.is_control() => {
+                self.bind(Binding::Char(c));
+            }
+            (Some(_), _) => {}
+
+            (None, Event::KeyPressed(Key::Escape)) => {
+                if !self.changed { return Some(Transition::Title); }
+                match self.keymap.save() {
+                    Ok(_) => return Some(Transition::Title),
+                    Err(e) => {
+                        // Let the next ESC leave without saving.
+                        self.message = Some(format!("Saving keys failed: {}", e));
+                        self.changed = false;
+                    }
+                }
+            }
+            (None, Event::KeyPressed(Key::Up)) => {
+                self.cursor = (self.cursor + COMMANDS.len() - 1) % COMMANDS.len();
+                self.message = None;
+            }
+            (None, Event::KeyPressed(Key::Down)) => {
+                self.cursor = (self.cursor + 1) % COMMANDS.len();
+                self.message = None;
+            }
+            (None, Event::KeyPressed(Key::Enter)) => {
+                self.capture = Some(Capture::Key);
+                self.message = None;
+            }
+            (None, Event::KeyPressed(Key::Backspace)) | (None, Event::KeyPressed(Key::Delete)) => {
+                self.keymap.clear(COMMANDS[self.cursor].0);
+                self.changed = true;
+            }
+            // Start char capture from the typed character so that the
+            // character event of the keypress won't get captured itself.
+            (None, Event::Char('c')) => {
+                self.capture = Some(Capture::Char);
+                self.message = None;
+            }
+            (None, Event::Char('r')) => {
+                self.keymap = Keymap::default();
+                self.changed = true;
+            }
+            _ => {}
+        }
+        None
+    }
+}
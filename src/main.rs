@@ -1,6 +1,7 @@
 #![crate_name="phage"]
 
 extern crate image;
+extern crate rand;
 
 #[macro_use]
 extern crate calx;
@@ -8,11 +9,23 @@ extern crate calx;
 extern crate world;
 extern crate time;
 
+use std::env;
+use std::io::{self, Write};
+use std::process;
+use calx::V2;
 use calx::backend::{Canvas, Event, CanvasBuilder};
+use world::Replay;
+use world::action;
 
 use gamestate::GameState;
 use titlestate::TitleState;
+use keystate::KeyState;
+use optionstate::OptionState;
+use settings::Settings;
+use cli::Options;
 
+/// Size of the logical canvas. The canvas is scaled to the window size set
+/// in the user settings.
 pub static SCREEN_W: u32 = 640;
 pub static SCREEN_H: u32 = 360;
 
@@ -20,11 +33,20 @@ pub mod drawable;
 pub mod tilecache;
 pub mod viewutil;
 pub mod worldview;
+mod cli;
 mod gamestate;
 mod titlestate;
 mod sprite;
+mod particle;
 mod msg_queue;
 mod console;
+mod keymap;
+mod keystate;
+mod optionstate;
+mod seed;
+mod settings;
+mod tween;
+mod version;
 
 pub trait State {
     fn process(&mut self, ctx: &mut Canvas, event: Event) -> Option<Transition>;
@@ -33,37 +55,83 @@ pub trait State {
 pub enum Transition {
     Game(Option<u32>),
     Title,
+    Keys,
+    Options,
     Exit,
 }
 
-pub fn version() -> String {
-    let next_release = "0.1.0";
-    // Set is_release to true for one commit to make a release.
-    let is_release = false;
+pub use version::version;
 
-    if is_release {
-        format!("{}", next_release)
-    } else {
-        format!("{}-alpha", next_release)
+pub fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match Options::parse(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "{}\n\n{}", e, cli::USAGE);
+            process::exit(1);
+        }
+    };
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    if options.version {
+        println!("phage {}", version());
+        return;
+    }
+    if options.no_save {
+        action::set_saving(false);
     }
-}
 
-pub fn main() {
-    let mut builder = CanvasBuilder::new()
+    let (settings, settings_error) = match Settings::load() {
+        Ok(settings) => (settings, None),
+        Err(e) => (Default::default(), Some(e)),
+    };
+
+    let mut builder = settings.apply(CanvasBuilder::new()
         .set_size(SCREEN_W, SCREEN_H)
-        .set_title("Phage")
-        .set_frame_interval(0.030f64);
+        .set_title("Phage"));
+    if let Some(V2(w, h)) = options.window_size {
+        builder = builder.set_window_size(w, h);
+    }
+    if options.fullscreen {
+        builder = builder.set_fullscreen(true);
+    }
+    if let Some(magnify) = options.magnify {
+        builder = builder.set_magnify(magnify);
+    }
     tilecache::init(&mut builder);
 
-    let mut state: Box<State> = Box::new(TitleState::new());
+    let game = |game: Result<GameState, String>| -> Box<State> {
+        match game {
+            Ok(game) => Box::new(game),
+            // Back to title screen with an error if the save is bad.
+            Err(e) => Box::new(TitleState::new().message(e)),
+        }
+    };
+
+    let mut state: Box<State> = if let Some(ref path) = options.replay {
+        game(Replay::load(path).map(GameState::replay))
+    } else if let Some(ref path) = options.load {
+        game(GameState::load(path))
+    } else if let Some(seed) = options.seed {
+        game(Ok(GameState::new_seeded(seed)))
+    } else {
+        match settings_error {
+            Some(e) => Box::new(TitleState::new().message(format!("{}\nUsing default settings", e))),
+            None => Box::new(TitleState::new()),
+        }
+    };
 
     let mut canvas = builder.build();
     loop {
         let event = canvas.next_event();
         match state.process(&mut canvas, event) {
             Some(Transition::Title) => { state = Box::new(TitleState::new()); }
-            Some(Transition::Game(seed)) => {
-                state = Box::new(GameState::new(seed)); }
+            Some(Transition::Keys) => { state = Box::new(KeyState::new()); }
+            Some(Transition::Options) => { state = Box::new(OptionState::new()); }
+            Some(Transition::Game(Some(seed))) => { state = game(Ok(GameState::new_seeded(seed))); }
+            Some(Transition::Game(None)) => { state = game(GameState::new(None)); }
             Some(Transition::Exit) => { break; }
             _ => ()
         }
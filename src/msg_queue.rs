@@ -25,26 +25,30 @@ pub struct MsgQueue {
     /// Special messages that show up one by one at the center of the screen.
     captions: Vec<Msg>,
     caption_done_time: Option<f64>,
+
+    /// Estimated time it takes the user to read one character in seconds.
+    letter_read_duration: f64,
 }
 
 impl MsgQueue {
-    pub fn new() -> MsgQueue {
+    pub fn new(letter_read_duration: f64) -> MsgQueue {
         MsgQueue {
             msgs: Vec::new(),
             msg_done_time: None,
             captions: Vec::new(),
             caption_done_time: None,
+            letter_read_duration: letter_read_duration,
         }
     }
 
     pub fn msg(&mut self, text: String) {
-        let timeout = add_time_to_read(self.msg_done_time, &text[..]);
+        let timeout = add_time_to_read(self.msg_done_time, &text[..], self.letter_read_duration);
         self.msgs.push(Msg::new(text, timeout));
         self.msg_done_time = Some(timeout);
     }
 
     pub fn caption(&mut self, text: String) {
-        let timeout = add_time_to_read(self.caption_done_time, &text[..]);
+        let timeout = add_time_to_read(self.caption_done_time, &text[..], self.letter_read_duration);
         self.captions.push(Msg::new(text, timeout));
         self.caption_done_time = Some(timeout);
     }
@@ -97,9 +101,7 @@ impl MsgQueue {
     }
 }
 
-fn add_time_to_read(old_time: Option<f64>, text: &str) -> f64 {
-    // Estimated time it takes the user to read one character in seconds.
-    let letter_read_duration = 0.2;
+fn add_time_to_read(old_time: Option<f64>, text: &str, letter_read_duration: f64) -> f64 {
     let read_time = text.trim().len() as f64 * letter_read_duration;
 
     return match old_time {
@@ -0,0 +1,204 @@
+use calx::{V2, color, Anchor};
+use calx::backend::{Key, Event, WidgetId, CanvasMagnify, RecordFormat};
+use calx::backend::{Canvas, CanvasUtil, Fonter, Align};
+use settings::{Settings, magnify_name, record_format_name};
+use ::{Transition, State};
+
+/// Window sizes to cycle through, None is fit to screen.
+static WINDOW_SIZES: [Option<(u32, u32)>; 6] = [
+    None,
+    Some((640, 360)),
+    Some((1280, 720)),
+    Some((1920, 1080)),
+    Some((2560, 1440)),
+    Some((3840, 2160)),
+];
+
+static MAGNIFY_MODES: [CanvasMagnify; 3] = [
+    CanvasMagnify::PixelPerfect,
+    CanvasMagnify::Nearest,
+    CanvasMagnify::Smooth,
+];
+
+/// Options screen.
+pub struct OptionState {
+    settings: Settings,
+    /// The settings have unsaved changes.
+    changed: bool,
+    message: Option<String>,
+}
+
+impl OptionState {
+    pub fn new() -> OptionState {
+        let (settings, message) = match Settings::load() {
+            Ok(settings) => (settings, None),
+            Err(e) => (Default::default(), Some(format!("{}\nUsing default settings", e))),
+        };
+        OptionState {
+            settings: settings,
+            changed: false,
+            message: message,
+        }
+    }
+
+    /// Draw a settings row with decrease and increase buttons. Return -1 or
+    /// 1 if one of the buttons was clicked, 0 otherwise.
+    fn row(&self, ctx: &mut Canvas, dec_id: WidgetId, inc_id: WidgetId,
+           y: f32, label: &str, value: String) -> i32 {
+        Fonter::new(ctx)
+            .color(color::LIGHTGRAY)
+            .anchor(Anchor::Left)
+            .text(label.to_string())
+            .draw(V2(96.0, y + 8.0));
+
+        let dec = ctx.button(dec_id, V2(256.0, y), 0.2);
+        button_label(ctx, "<", V2(256.0, y));
+
+        Fonter::new(ctx)
+            .color(color::WHITE)
+            .anchor(Anchor::Center).align(Align::Center)
+            .text(value)
+            .draw(V2(368.0, y + 8.0));
+
+        let inc = ctx.button(inc_id, V2(416.0, y), 0.2);
+        button_label(ctx, ">", V2(416.0, y));
+
+        if dec { -1 } else if inc { 1 } else { 0 }
+    }
+
+    /// Draw the screen and apply the changes from clicked buttons.
+    fn update(&mut self, ctx: &mut Canvas) -> Option<Transition> {
+        let old = self.settings.clone();
+
+        Fonter::new(ctx)
+            .color(color::DARKCYAN)
+            .text("Options".to_string())
+            .draw(V2(96.0, 16.0));
+
+        let y0 = 48.0;
+        let dy = 24.0;
+
+        let value = match self.settings.window_size {
+            Some(V2(w, h)) => format!("{}x{}", w, h),
+            None => "Fit screen".to_string(),
+        };
+        let d = self.row(ctx, widget_id!(), widget_id!(), y0, "Window size", value);
+        if d != 0 {
+            let current = self.settings.window_size.map(|V2(w, h)| (w, h));
+            let i = cycle(WINDOW_SIZES.iter().position(|&x| x == current), WINDOW_SIZES.len(), d);
+            self.settings.window_size = WINDOW_SIZES[i].map(|(w, h)| V2(w, h));
+            if let Some(V2(w, h)) = self.settings.window_size {
+                ctx.set_window_size(w, h);
+            }
+        }
+
+        let value = magnify_name(self.settings.magnify).to_string();
+        let d = self.row(ctx, widget_id!(), widget_id!(), y0 + dy, "Scaling", value);
+        if d != 0 {
+            let i = cycle(MAGNIFY_MODES.iter().position(|&x| x == self.settings.magnify), MAGNIFY_MODES.len(), d);
+            self.settings.magnify = MAGNIFY_MODES[i];
+            ctx.set_magnify(self.settings.magnify);
+        }
+
+        let value = (if self.settings.fullscreen { "On" } else { "Off" }).to_string();
+        let d = self.row(ctx, widget_id!(), widget_id!(), y0 + 2.0 * dy, "Fullscreen", value);
+        if d != 0 {
+            self.settings.fullscreen = !self.settings.fullscreen;
+            self.message = Some("Fullscreen changes take effect when the game is restarted".to_string());
+        }
+
+        let value = format!("{:.0} fps", 1.0 / self.settings.frame_interval);
+        let d = self.row(ctx, widget_id!(), widget_id!(), y0 + 3.0 * dy, "Frame rate", value);
+        if d != 0 {
+            // Step the frame rate instead of the interval so the steps are
+            // even.
+            let fps = (1.0 / self.settings.frame_interval).round() + 5.0 * d as f64;
+            let fps = fps.max(10.0).min(120.0);
+            self.settings.frame_interval = 1.0 / fps;
+            ctx.set_frame_interval(self.settings.frame_interval);
+        }
+
+        let value = format!("{:.2} s/letter", self.settings.letter_read_duration);
+        let d = self.row(ctx, widget_id!(), widget_id!(), y0 + 4.0 * dy, "Message time", value);
+        if d != 0 {
+            let t = self.settings.letter_read_duration + 0.05 * d as f64;
+            self.settings.letter_read_duration = t.max(0.05).min(1.0);
+        }
+
+        let value = (if self.settings.mob_status { "On" } else { "Off" }).to_string();
+        let d = self.row(ctx, widget_id!(), widget_id!(), y0 + 5.0 * dy, "Mob health", value);
+        if d != 0 {
+            self.settings.mob_status = !self.settings.mob_status;
+        }
+
+        let value = record_format_name(self.settings.record_format).to_string();
+        let d = self.row(ctx, widget_id!(), widget_id!(), y0 + 6.0 * dy, "Recording", value);
+        if d != 0 {
+            self.settings.record_format = match self.settings.record_format {
+                RecordFormat::Gif => RecordFormat::PngSequence,
+                RecordFormat::PngSequence => RecordFormat::Gif,
+            };
+        }
+
+        if self.settings != old { self.changed = true; }
+
+        if let Some(ref text) = self.message {
+            Fonter::new(ctx)
+                .color(color::ORANGERED).border(color::BLACK)
+                .anchor(Anchor::Top).align(Align::Center)
+                .text(text.clone())
+                .draw(V2(320.0, y0 + 8.0 * dy));
+        }
+
+        Fonter::new(ctx)
+            .color(color::DARKCYAN)
+            .anchor(Anchor::BottomLeft)
+            .text("Click the arrows to change settings\nESC) save and exit".to_string())
+            .draw(V2(96.0, 340.0));
+
+        let done = ctx.button(widget_id!(), V2(416.0, 320.0), 0.2);
+        button_label(ctx, "Done", V2(416.0, 320.0));
+        if done { self.leave() } else { None }
+    }
+
+    fn leave(&mut self) -> Option<Transition> {
+        if !self.changed { return Some(Transition::Title); }
+        match self.settings.save() {
+            Ok(_) => Some(Transition::Title),
+            Err(e) => {
+                // Let the next try leave without saving.
+                self.message = Some(format!("Saving settings failed: {}", e));
+                self.changed = false;
+                None
+            }
+        }
+    }
+}
+
+impl State for OptionState {
+    fn process(&mut self, ctx: &mut Canvas, event: Event) -> Option<Transition> {
+        match event {
+            Event::RenderFrame => self.update(ctx),
+            Event::KeyPressed(Key::Escape) => self.leave(),
+            Event::Quit => Some(Transition::Exit),
+            _ => None
+        }
+    }
+}
+
+fn button_label(ctx: &mut Canvas, text: &str, pos: V2<f32>) {
+    Fonter::new(ctx)
+        .color(color::BLACK)
+        .anchor(Anchor::Center).align(Align::Center)
+        .text(text.to_string())
+        .draw(pos + V2(32.0, 8.0));
+}
+
+/// Step an index into a list of n options, wrapping around. An unknown
+/// current value starts from the first option.
+fn cycle(current: Option<usize>, n: usize, d: i32) -> usize {
+    match current {
+        Some(i) => ((i as i32 + d + n as i32) % n as i32) as usize,
+        None => 0,
+    }
+}
@@ -0,0 +1,176 @@
+/*! Particle effects for the world view. */
+
+use std::slice::Iter;
+use std::f32::consts::PI;
+use rand::{self, Rng};
+use calx::{color, V2, Rect, Rgba, Dir6};
+use calx::backend::{Canvas, CanvasUtil};
+use world::{Location, Unchart, FovStatus};
+use viewutil::{FX_Z, chart_to_screen, chart_to_view, view_to_chart};
+use sprite::WorldSprite;
+
+/// Kinds of particle bursts.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Effect {
+    /// Shot hitting a wall or a robot taking damage.
+    Sparks,
+    Smoke,
+    /// Terran taking damage.
+    Blood,
+    /// Alien taking damage.
+    Acid,
+    Explosion,
+}
+
+struct Particle {
+    /// Position in pixels relative to the origin cell.
+    pos: V2<f32>,
+    /// Velocity in pixels per frame.
+    vel: V2<f32>,
+    life: i32,
+    max_life: i32,
+    color: Rgba,
+    size: f32,
+}
+
+/// Parameters for a set of particles.
+struct Emitter {
+    count: usize,
+    /// Min and max initial speed in pixels per frame.
+    speed: (f32, f32),
+    /// Min and max life in frames.
+    life: (i32, i32),
+    colors: &'static [&'static Rgba],
+    size: f32,
+    /// Spread of the spray in radians, centered on the aim direction.
+    spread: f32,
+}
+
+/// A burst of particles sprayed from a single cell.
+pub struct ParticleSprite {
+    origin: Location,
+    particles: Vec<Particle>,
+    /// Added to particle y velocity every frame, negative floats up.
+    gravity: f32,
+    /// Velocity multiplier per frame.
+    drag: f32,
+    footprint: Vec<Location>,
+}
+
+static SPARK_COLORS: [&'static Rgba; 3] = [&color::YELLOW, &color::ORANGE, &color::WHITE];
+static SMOKE_COLORS: [&'static Rgba; 3] = [&color::DIMGRAY, &color::GRAY, &color::DARKSLATEGRAY];
+static BLOOD_COLORS: [&'static Rgba; 2] = [&color::RED, &color::DARKRED];
+static ACID_COLORS: [&'static Rgba; 3] = [&color::LAWNGREEN, &color::YELLOWGREEN, &color::GREENYELLOW];
+static FIRE_COLORS: [&'static Rgba; 4] = [&color::YELLOW, &color::ORANGE, &color::ORANGERED, &color::RED];
+
+impl ParticleSprite {
+    /// Create a particle burst at a location. Dir aims sprays in a
+    /// direction, with None the particles fly all around.
+    pub fn new(effect: Effect, origin: Location, dir: Option<Dir6>) -> ParticleSprite {
+        let mut ret = ParticleSprite {
+            origin: origin,
+            particles: Vec::new(),
+            gravity: 0.15,
+            drag: 0.95,
+            // Particles don't get much further than the neighboring cells.
+            footprint: Some(origin).into_iter()
+                .chain(Dir6::iter().map(|d| origin + d.to_v2()))
+                .collect(),
+        };
+
+        let aim = dir.map(|d| {
+            let v = chart_to_view(d.to_v2());
+            (v.1 as f32).atan2(v.0 as f32)
+        });
+
+        match effect {
+            Effect::Sparks => {
+                ret.emit(aim, &Emitter { count: 12, speed: (1.0, 3.0), life: (4, 10),
+                    colors: &SPARK_COLORS, size: 1.0, spread: PI / 2.0 });
+            }
+            Effect::Smoke => {
+                ret.gravity = -0.03;
+                ret.drag = 0.9;
+                ret.emit(aim, &Emitter { count: 8, speed: (0.2, 0.6), life: (20, 40),
+                    colors: &SMOKE_COLORS, size: 2.0, spread: 2.0 * PI });
+            }
+            Effect::Blood => {
+                ret.emit(aim, &Emitter { count: 10, speed: (0.8, 2.0), life: (8, 16),
+                    colors: &BLOOD_COLORS, size: 1.0, spread: PI / 3.0 });
+            }
+            Effect::Acid => {
+                ret.emit(aim, &Emitter { count: 10, speed: (0.8, 2.0), life: (10, 20),
+                    colors: &ACID_COLORS, size: 1.0, spread: PI / 2.0 });
+            }
+            Effect::Explosion => {
+                ret.gravity = 0.05;
+                ret.emit(None, &Emitter { count: 30, speed: (1.0, 4.0), life: (6, 14),
+                    colors: &FIRE_COLORS, size: 2.0, spread: 2.0 * PI });
+                ret.emit(None, &Emitter { count: 10, speed: (0.1, 0.5), life: (20, 40),
+                    colors: &SMOKE_COLORS, size: 2.0, spread: 2.0 * PI });
+            }
+        }
+        ret
+    }
+
+    fn emit(&mut self, aim: Option<f32>, e: &Emitter) {
+        let mut rng = rand::thread_rng();
+        // Random direction if not aimed.
+        let (center, spread) = match aim {
+            Some(a) => (a, e.spread),
+            None => (0.0, 2.0 * PI),
+        };
+        for _ in 0..e.count {
+            let angle = center + (rng.gen::<f32>() - 0.5) * spread;
+            let speed = e.speed.0 + rng.gen::<f32>() * (e.speed.1 - e.speed.0);
+            let life = rng.gen_range(e.life.0, e.life.1 + 1);
+            self.particles.push(Particle {
+                // Start from the middle of the mob sprite.
+                pos: V2(0.0, -4.0),
+                vel: V2(angle.cos(), angle.sin()) * speed,
+                life: life,
+                max_life: life,
+                color: *e.colors[rng.gen_range(0, e.colors.len())],
+                size: e.size,
+            });
+        }
+    }
+}
+
+impl WorldSprite for ParticleSprite {
+    fn update(&mut self) {
+        for p in self.particles.iter_mut() {
+            p.pos = p.pos + p.vel;
+            p.vel = p.vel * self.drag + V2(0.0, self.gravity);
+            p.life -= 1;
+        }
+        self.particles.retain(|p| p.life > 0);
+    }
+
+    fn is_alive(&self) -> bool { !self.particles.is_empty() }
+
+    fn footprint<'a>(&'a self) -> Iter<'a, Location> { self.footprint.iter() }
+
+    fn draw(&self, chart: &Location, ctx: &mut Canvas) {
+        let origin = match chart.chart_pos(self.origin) {
+            Some(p) => p,
+            None => return,
+        };
+        let origin_view = chart_to_view(origin);
+        let origin_screen = chart_to_screen(origin);
+
+        for p in self.particles.iter() {
+            // Don't show particles that fly into cells the player can't see.
+            let view_pos = origin_view + p.pos.map(|x| x.round() as i32);
+            if (*chart + view_to_chart(view_pos)).fov_status() != Some(FovStatus::Seen) {
+                continue;
+            }
+
+            let mut col = p.color;
+            // Fade out over the last half of the life.
+            col.a = (2.0 * p.life as f32 / p.max_life as f32).min(1.0);
+            let pos = (origin_screen + p.pos).map(|x| x.round());
+            ctx.fill_rect(&Rect(pos, V2(p.size, p.size)), FX_Z, col);
+        }
+    }
+}
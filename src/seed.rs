@@ -0,0 +1,46 @@
+/*! World seeds as shareable word codes. */
+
+use time;
+use calx::vorud::{Vorud, ToVorud, FromVorud};
+
+/// Format a seed as a vorud word code, eg. "kazor-tumab".
+pub fn seed_code(seed: u32) -> String {
+    seed.to_vorud().to_string()
+}
+
+/// Parse a seed from a vorud word code or a plain number.
+pub fn parse_seed(s: &str) -> Result<u32, String> {
+    let s = s.trim().to_lowercase();
+    if let Ok(n) = s.parse::<u32>() { return Ok(n); }
+    Vorud::new(s.clone())
+        .and_then(|v| FromVorud::from_vorud(&v))
+        .map_err(|_| format!("Bad seed '{}', use a number or a code like kazor-tumab", s))
+}
+
+/// Seed shared by everyone playing on the same day, UTC.
+pub fn daily_seed() -> u32 {
+    let day = time::get_time().sec / (24 * 60 * 60);
+    day_seed(day as u32)
+}
+
+fn day_seed(day: u32) -> u32 {
+    // Scramble the day number so consecutive days don't get similar seeds.
+    let mut x = day.wrapping_add(0x9E3779B9);
+    x = (x ^ (x >> 16)).wrapping_mul(0x85EBCA6B);
+    x = (x ^ (x >> 13)).wrapping_mul(0xC2B2AE35);
+    x ^ (x >> 16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{seed_code, parse_seed, day_seed};
+
+    #[test]
+    fn test_seed_code() {
+        assert_eq!(parse_seed(&seed_code(123456789)), Ok(123456789));
+        assert_eq!(parse_seed(" 42 "), Ok(42));
+        assert_eq!(parse_seed(&seed_code(7).to_uppercase()), Ok(7));
+        assert!(parse_seed("xyzzy").is_err());
+        assert!(day_seed(1) != day_seed(2));
+    }
+}
@@ -0,0 +1,266 @@
+/*! User settings stored in a config file. */
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::str::FromStr;
+use calx;
+use calx::V2;
+use calx::backend::{CanvasBuilder, CanvasMagnify, RecordFormat};
+
+static FILENAME: &'static str = "settings.txt";
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Settings {
+    /// Window size in pixels, None to fit the window to the screen.
+    pub window_size: Option<V2<u32>>,
+    pub magnify: CanvasMagnify,
+    /// Only takes effect when the game is restarted.
+    pub fullscreen: bool,
+    /// Seconds between rendered frames.
+    pub frame_interval: f64,
+    /// Estimated time it takes the user to read one character of a message
+    /// in seconds.
+    pub letter_read_duration: f64,
+    /// Show health bars and status icons over mobs.
+    pub mob_status: bool,
+    pub record_format: RecordFormat,
+    /// Number of frames to skip between recorded frames.
+    pub record_frame_skip: u32,
+    /// Recordings stop when they get larger than this many megabytes.
+    pub record_max_size: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            window_size: None,
+            magnify: CanvasMagnify::PixelPerfect,
+            fullscreen: false,
+            frame_interval: 0.030,
+            letter_read_duration: 0.2,
+            mob_status: true,
+            record_format: RecordFormat::Gif,
+            record_frame_skip: 1,
+            record_max_size: 20,
+        }
+    }
+}
+
+impl Settings {
+    /// Load the settings from the config file. Returns the default settings
+    /// if there is no config file.
+    pub fn load() -> Result<Settings, String> {
+        match try!(read_config(FILENAME)) {
+            Some(text) => Settings::parse(FILENAME, &text),
+            None => Ok(Default::default()),
+        }
+    }
+
+    /// Save the settings to the config file.
+    pub fn save(&self) -> Result<(), String> {
+        write_config(FILENAME, &self.to_text())
+    }
+
+    /// Parse settings from the text of a config file. The filename is only
+    /// used in error messages.
+    ///
+    /// Each line is a setting name followed by a colon and the value.
+    /// Settings missing from the file get the default values.
+    pub fn parse(filename: &str, text: &str) -> Result<Settings, String> {
+        let mut ret: Settings = Default::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("#") { continue; }
+
+            let result = match line.find(':') {
+                Some(idx) => ret.set(line[..idx].trim(), line[idx + 1..].trim()),
+                None => Err(format!("Expected 'setting: value', got '{}'", line)),
+            };
+            if let Err(e) = result {
+                return Err(format!("{}:{}: {}", filename, i + 1, e));
+            }
+        }
+        Ok(ret)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "window_size" => {
+                self.window_size = match value {
+                    "auto" => None,
+                    _ => Some(try!(parse_size(value))),
+                };
+            }
+            "magnify" => { self.magnify = try!(parse_magnify(value)); }
+            "fullscreen" => { self.fullscreen = try!(parse_bool(key, value)); }
+            "frame_interval" => {
+                self.frame_interval = try!(parse_num(key, value));
+                // Written this way so that NaN fails the check too.
+                if !(self.frame_interval >= 0.001) || !self.frame_interval.is_finite() {
+                    return Err("Frame interval must be at least 0.001".to_string());
+                }
+            }
+            "letter_read_duration" => {
+                self.letter_read_duration = try!(parse_num(key, value));
+                if !(self.letter_read_duration > 0.0) || !self.letter_read_duration.is_finite() {
+                    return Err("Letter read duration must be positive".to_string());
+                }
+            }
+            "mob_status" => { self.mob_status = try!(parse_bool(key, value)); }
+            "record_format" => { self.record_format = try!(parse_record_format(value)); }
+            "record_frame_skip" => { self.record_frame_skip = try!(parse_num(key, value)); }
+            "record_max_size" => { self.record_max_size = try!(parse_num(key, value)); }
+            _ => return Err(format!("Unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+
+    /// Write the settings in the config file format.
+    pub fn to_text(&self) -> String {
+        let mut ret = String::new();
+        ret.push_str("# Phage settings\n");
+        ret.push_str(&format!("window_size: {}\n", match self.window_size {
+            Some(V2(w, h)) => format!("{}x{}", w, h),
+            None => "auto".to_string(),
+        }));
+        ret.push_str(&format!("magnify: {}\n", magnify_name(self.magnify)));
+        ret.push_str(&format!("fullscreen: {}\n", self.fullscreen));
+        ret.push_str(&format!("frame_interval: {}\n", self.frame_interval));
+        ret.push_str(&format!("letter_read_duration: {}\n", self.letter_read_duration));
+        ret.push_str(&format!("mob_status: {}\n", self.mob_status));
+        ret.push_str(&format!("record_format: {}\n", record_format_name(self.record_format)));
+        ret.push_str(&format!("record_frame_skip: {}\n", self.record_frame_skip));
+        ret.push_str(&format!("record_max_size: {}\n", self.record_max_size));
+        ret
+    }
+
+    /// Apply the settings to a canvas that's being built.
+    pub fn apply(&self, mut builder: CanvasBuilder) -> CanvasBuilder {
+        builder = builder
+            .set_frame_interval(self.frame_interval)
+            .set_magnify(self.magnify)
+            .set_fullscreen(self.fullscreen);
+        if let Some(V2(w, h)) = self.window_size {
+            builder = builder.set_window_size(w, h);
+        }
+        builder
+    }
+}
+
+pub fn magnify_name(magnify: CanvasMagnify) -> &'static str {
+    match magnify {
+        CanvasMagnify::PixelPerfect => "pixelperfect",
+        CanvasMagnify::Nearest => "nearest",
+        CanvasMagnify::Smooth => "smooth",
+    }
+}
+
+pub fn parse_magnify(value: &str) -> Result<CanvasMagnify, String> {
+    match value {
+        "pixelperfect" => Ok(CanvasMagnify::PixelPerfect),
+        "nearest" => Ok(CanvasMagnify::Nearest),
+        "smooth" => Ok(CanvasMagnify::Smooth),
+        _ => Err(format!("Expected pixelperfect, nearest or smooth for magnify, got '{}'", value)),
+    }
+}
+
+pub fn record_format_name(format: RecordFormat) -> &'static str {
+    match format {
+        RecordFormat::Gif => "gif",
+        RecordFormat::PngSequence => "png",
+    }
+}
+
+fn parse_record_format(value: &str) -> Result<RecordFormat, String> {
+    match value {
+        "gif" => Ok(RecordFormat::Gif),
+        "png" => Ok(RecordFormat::PngSequence),
+        _ => Err(format!("Expected gif or png for record_format, got '{}'", value)),
+    }
+}
+
+/// Parse a size in the WxH format.
+pub fn parse_size(value: &str) -> Result<V2<u32>, String> {
+    let err = || format!("Expected size like 1280x720, got '{}'", value);
+    let parts: Vec<&str> = value.split('x').collect();
+    if parts.len() != 2 { return Err(err()); }
+    match (parts[0].parse::<u32>(), parts[1].parse::<u32>()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Ok(V2(w, h)),
+        _ => Err(err()),
+    }
+}
+
+fn parse_num<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    T::from_str(value).map_err(|_| format!("Bad number '{}' for {}", value, key))
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "yes" | "true" => Ok(true),
+        "no" | "false" => Ok(false),
+        _ => Err(format!("Expected yes or no for {}, got '{}'", key, value)),
+    }
+}
+
+/// Read a file from the application data directory. Returns None if the
+/// file doesn't exist.
+pub fn read_config(filename: &str) -> Result<Option<String>, String> {
+    let path = calx::app_data_path("phage").join(filename);
+    if fs::metadata(&path).is_err() { return Ok(None); }
+
+    let mut text = String::new();
+    try!(File::open(&path)
+         .and_then(|mut f| f.read_to_string(&mut text))
+         .map_err(|e| format!("{}: {}", path.to_string_lossy(), e)));
+    Ok(Some(text))
+}
+
+/// Write a file to the application data directory.
+pub fn write_config(filename: &str, text: &str) -> Result<(), String> {
+    let path = calx::app_data_path("phage").join(filename);
+    if let Some(dir) = path.parent() {
+        try!(fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.to_string_lossy(), e)));
+    }
+    File::create(&path)
+        .and_then(|mut f| f.write_all(text.as_bytes()))
+        .map_err(|e| format!("{}: {}", path.to_string_lossy(), e))
+}
+
+#[cfg(test)]
+mod test {
+    use calx::V2;
+    use calx::backend::{CanvasMagnify, RecordFormat};
+    use super::Settings;
+
+    #[test]
+    fn test_roundtrip() {
+        let settings = Settings {
+            window_size: Some(V2(1920, 1080)),
+            magnify: CanvasMagnify::Smooth,
+            fullscreen: true,
+            frame_interval: 0.015,
+            letter_read_duration: 0.1,
+            mob_status: false,
+            record_format: RecordFormat::PngSequence,
+            record_frame_skip: 0,
+            record_max_size: 100,
+        };
+        assert_eq!(Settings::parse("test", &settings.to_text()), Ok(settings));
+    }
+
+    #[test]
+    fn test_parse() {
+        let settings = Settings::parse("test", "# Comment\nmagnify: nearest\n").unwrap();
+        assert_eq!(settings.magnify, CanvasMagnify::Nearest);
+        assert_eq!(settings.window_size, None);
+
+        assert!(Settings::parse("test", "window_size: 640\n").is_err());
+        assert!(Settings::parse("test", "frame_interval: 0\n").is_err());
+        assert!(Settings::parse("test", "frame_interval: NaN\n").is_err());
+        assert!(Settings::parse("test", "frame_interval: inf\n").is_err());
+        assert!(Settings::parse("test", "letter_read_duration: 0\n").is_err());
+        assert!(Settings::parse("test", "letter_read_duration: NaN\n").is_err());
+        assert!(Settings::parse("test", "volume: 11\n").is_err());
+    }
+}
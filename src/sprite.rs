@@ -1,5 +1,5 @@
 use std::slice::Iter;
-use calx::{color, V2, Dir6, clamp};
+use calx::{color, V2, Dir6, clamp, hex_line};
 use calx::backend::{Canvas, CanvasUtil};
 use world::{Location, Unchart};
 use viewutil::{FX_Z, chart_to_screen};
@@ -67,10 +67,12 @@ impl BeamSprite {
             p1: p1,
             p2: p2,
             life: life,
-            // TODO: Generate intervening points into the footprint. With this
-            // footprint you can't see the beam unless either the start or the
-            // end point is visible.
-            footprint: vec![p1, p2],
+            // Include the cells in between so that the beam is seen even if
+            // neither end is visible.
+            footprint: match p1.v2_at(p2) {
+                Some(v) => hex_line(V2(0, 0), v).into_iter().map(|x| p1 + x).collect(),
+                None => vec![p1, p2],
+            },
         }
     }
 }
@@ -1,22 +1,39 @@
 use std::convert::{Into};
+use rand;
 use calx::{V2, color, Rgba, Anchor};
 use calx::backend::{Key, Event};
 use calx::backend::{Canvas, CanvasUtil, Fonter, Align};
 use tilecache;
 use world::action;
+use seed;
 use ::{Transition, State};
 
 pub struct TitleState {
     tick: usize,
+    /// Error message to show to the player, such as a failed game load.
+    message: Option<String>,
+    /// Seed of the game that can be continued.
+    saved_seed: Option<u32>,
+    /// Seed code being typed in by the player.
+    seed_entry: Option<String>,
 }
 
 impl TitleState {
     pub fn new() -> TitleState {
         TitleState {
             tick: 0,
+            message: None,
+            saved_seed: action::saved_seed(),
+            seed_entry: None,
         }
     }
 
+    /// Show a message on the title screen.
+    pub fn message(mut self, text: String) -> TitleState {
+        self.message = Some(text);
+        self
+    }
+
 }
 
 static FADE_TIME: usize = 64;
@@ -31,11 +48,51 @@ impl TitleState {
     fn when_faded<C: Into<Rgba>>(&self, col: C) -> Rgba {
         if self.tick < FADE_TIME { color::BLACK } else { col.into() }
     }
+
+    /// Start a new game, replacing any saved game.
+    fn new_game(&self, seed: Option<u32>) -> Option<Transition> {
+        if action::saving() {
+            action::delete_save();
+            Some(Transition::Game(seed))
+        } else {
+            // The save is still there, so the game must get a seed to not
+            // be mistaken for continuing the save.
+            Some(Transition::Game(Some(seed.unwrap_or_else(rand::random))))
+        }
+    }
+
+    fn process_seed_entry(&mut self, event: Event) -> Option<Transition> {
+        match event {
+            Event::KeyPressed(Key::Escape) => { self.seed_entry = None; }
+            Event::KeyPressed(Key::Backspace) => {
+                if let Some(ref mut text) = self.seed_entry { text.pop(); }
+            }
+            Event::KeyPressed(Key::Enter) => {
+                let parsed = seed::parse_seed(self.seed_entry.as_ref().map_or("", |x| &x[..]));
+                self.seed_entry = None;
+                match parsed {
+                    Ok(seed) => return self.new_game(Some(seed)),
+                    Err(e) => { self.message = Some(e); }
+                }
+            }
+            Event::Char(ch) if ch.is_alphanumeric() || ch == '-' => {
+                if let Some(ref mut text) = self.seed_entry {
+                    // Codes for u32 seeds are 11 characters.
+                    if text.len() < 16 { text.push(ch); }
+                }
+            }
+            _ => ()
+        }
+        None
+    }
 }
 
 impl State for TitleState {
     fn process(&mut self, ctx: &mut Canvas, event: Event) -> Option<Transition> {
         self.tick += 1;
+        if self.seed_entry.is_some() && event != Event::RenderFrame && event != Event::Quit {
+            return self.process_seed_entry(event);
+        }
         match event {
             Event::RenderFrame => {
                 ctx.draw_image(tilecache::get(tilecache::LOGO), V2(282.0, 180.0), 0.0, self.fade_in(color::MEDIUMAQUAMARINE), color::BLACK);
@@ -50,8 +107,33 @@ impl State for TitleState {
                     .color(self.when_faded(color::DARKCYAN))
                     .anchor(Anchor::TopLeft)
                     .align(Align::Left)
-                    .text("N)ew game\nQ)uit".to_string())
+                    .text("N)ew game\nS)eeded game\nD)aily game\nO)ptions\nK)eys\nQ)uit".to_string())
                     .draw(V2(280.0, 240.0));
+
+                let seed_text = match self.seed_entry {
+                    Some(ref text) => format!("Seed: {}_\nEnter to start, Esc to cancel", text),
+                    None => {
+                        let mut ret = format!("Daily seed {}", seed::seed_code(seed::daily_seed()));
+                        if let Some(s) = self.saved_seed {
+                            ret = format!("Saved game seed {}\n{}", seed::seed_code(s), ret);
+                        }
+                        ret
+                    }
+                };
+                Fonter::new(ctx)
+                    .color(self.when_faded(color::DARKCYAN))
+                    .anchor(Anchor::Bottom)
+                    .align(Align::Center)
+                    .text(seed_text)
+                    .draw(V2(320.0, 320.0));
+                if let Some(ref text) = self.message {
+                    Fonter::new(ctx)
+                        .color(color::ORANGERED).border(color::BLACK)
+                        .anchor(Anchor::Top)
+                        .align(Align::Center)
+                        .text(text.clone())
+                        .draw(V2(320.0, 8.0));
+                }
                 if action::save_exists() {
                     Fonter::new(ctx)
                         .color(self.when_faded(color::DARKCYAN))
@@ -67,9 +149,24 @@ impl State for TitleState {
             Event::KeyPressed(Key::Q) => {
                 return Some(Transition::Exit);
             }
+            Event::KeyPressed(Key::O) => {
+                return Some(Transition::Options);
+            }
+            Event::KeyPressed(Key::K) => {
+                return Some(Transition::Keys);
+            }
             Event::KeyPressed(Key::N) => {
-                action::delete_save();
-                return Some(Transition::Game(None));
+                return self.new_game(None);
+            }
+            Event::KeyPressed(Key::D) => {
+                return self.new_game(Some(seed::daily_seed()));
+            }
+            // Open the seed entry on the char event so that the key doesn't
+            // get typed into it.
+            Event::KeyPressed(Key::S) => {}
+            Event::Char('s') | Event::Char('S') => {
+                self.message = None;
+                self.seed_entry = Some(String::new());
             }
             Event::Quit => {
                 return Some(Transition::Exit);
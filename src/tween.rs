@@ -0,0 +1,169 @@
+/*! Visual interpolation of mob movement and attacks.
+
+The world moves mobs from cell to cell instantly. The tweens remember where
+each mob was drawn and slide the sprites between the cells over a few
+frames. Game logic timing is unaffected, the tweens are only added to the
+draw positions.
+*/
+
+use std::collections::HashMap;
+use calx::{V2, Dir6, ease};
+use world::{Entity, Location};
+use world::action;
+use viewutil::chart_to_view;
+
+/// Frames it takes to slide from one cell to the next.
+static MOVE_FRAMES: i32 = 6;
+/// Frames for an attack lunge to go out and back.
+static LUNGE_FRAMES: i32 = 8;
+/// Frames for a hit recoil to settle.
+static RECOIL_FRAMES: i32 = 8;
+
+/// How far a lunge reaches, as a fraction of the distance to the next cell.
+static LUNGE_REACH: f32 = 0.5;
+/// How far a hit knocks the target, as a fraction of the distance to the
+/// next cell.
+static RECOIL_REACH: f32 = 0.25;
+
+#[derive(Copy, Clone)]
+enum Motion {
+    /// Slide from a pixel offset to the entity's cell.
+    Move(V2<f32>),
+    /// Jab toward a pixel offset and come back.
+    Lunge(V2<f32>),
+    /// Get knocked toward a pixel offset and settle back.
+    Recoil(V2<f32>),
+}
+
+#[derive(Copy, Clone)]
+struct Tween {
+    motion: Motion,
+    /// Current frame, negative for tweens that haven't started yet.
+    frame: i32,
+    duration: i32,
+}
+
+impl Tween {
+    fn new(motion: Motion, duration: i32) -> Tween {
+        Tween {
+            motion: motion,
+            frame: 0,
+            duration: duration,
+        }
+    }
+
+    fn is_move(&self) -> bool {
+        match self.motion { Motion::Move(_) => true, _ => false }
+    }
+
+    fn offset(&self) -> V2<f32> {
+        if self.frame < 0 { return V2(0.0, 0.0); }
+        let t = self.frame as f32 / self.duration as f32;
+        match self.motion {
+            Motion::Move(v) => v * (1.0 - ease::quadratic_out(t)),
+            Motion::Lunge(v) => v * bump(t, 0.3),
+            Motion::Recoil(v) => v * bump(t, 0.2),
+        }
+    }
+}
+
+/// Go from 0 to 1 quickly at `peak` and then ease back to 0 at t = 1.
+fn bump(t: f32, peak: f32) -> f32 {
+    if t < peak {
+        ease::quadratic_out(t / peak)
+    } else {
+        1.0 - ease::quadratic_in_out((t - peak) / (1.0 - peak))
+    }
+}
+
+/// Pixel vector from a cell to its neighbor in a direction.
+fn dir_to_view(dir: Dir6) -> V2<f32> {
+    chart_to_view(dir.to_v2()).map(|x| x as f32)
+}
+
+pub struct Tweens {
+    /// Where the mobs were during the last update.
+    locations: HashMap<Entity, Location>,
+    tweens: HashMap<Entity, Vec<Tween>>,
+}
+
+impl Tweens {
+    pub fn new() -> Tweens {
+        Tweens {
+            locations: HashMap::new(),
+            tweens: HashMap::new(),
+        }
+    }
+
+    /// Advance the tweens by one frame and start movement tweens for mobs
+    /// that have moved since the last update. Call once per frame after
+    /// updating the world.
+    pub fn update(&mut self) {
+        for (_, ts) in self.tweens.iter_mut() {
+            for t in ts.iter_mut() { t.frame += 1; }
+            ts.retain(|t| t.frame < t.duration);
+        }
+        self.tweens = self.tweens.drain().filter(|&(e, ref ts)| !ts.is_empty() && e.is_alive()).collect();
+
+        let mut locations = HashMap::new();
+        for e in action::mobs().into_iter() {
+            if let Some(loc) = e.location() {
+                // Only slide single steps, anything further away is a
+                // teleport or a level change.
+                let step = match self.locations.get(&e) {
+                    Some(&prev) if loc.distance_from(prev) == Some(1) => loc.v2_at(prev),
+                    _ => None,
+                };
+                if let Some(v) = step {
+                    self.start_move(e, chart_to_view(v).map(|x| x as f32));
+                }
+                locations.insert(e, loc);
+            }
+        }
+        self.locations = locations;
+    }
+
+    fn start_move(&mut self, e: Entity, from: V2<f32>) {
+        let ts = self.tweens.entry(e).or_insert(Vec::new());
+        // Continue from wherever an unfinished move has got to.
+        let mut from = from;
+        for t in ts.iter().filter(|t| t.is_move()) { from = from + t.offset(); }
+        ts.retain(|t| !t.is_move());
+        ts.push(Tween::new(Motion::Move(from), MOVE_FRAMES));
+    }
+
+    /// Show a mob attacking in melee. The mob in the attacked cell recoils
+    /// from the hit.
+    pub fn melee(&mut self, attacker: Entity, dir: Dir6) {
+        let v = dir_to_view(dir);
+        self.tweens.entry(attacker).or_insert(Vec::new())
+            .push(Tween::new(Motion::Lunge(v * LUNGE_REACH), LUNGE_FRAMES));
+
+        if let Some(target) = attacker.location().and_then(|loc| (loc + dir.to_v2()).mob_at()) {
+            // Recoil when the lunge connects.
+            let mut recoil = Tween::new(Motion::Recoil(v * RECOIL_REACH), RECOIL_FRAMES);
+            recoil.frame = -(LUNGE_FRAMES * 3 / 10);
+            self.tweens.entry(target).or_insert(Vec::new()).push(recoil);
+        }
+    }
+
+    /// Pixel offset to draw an entity at relative to its cell.
+    pub fn offset(&self, e: Entity) -> V2<f32> {
+        let mut ret = V2(0.0, 0.0);
+        if let Some(ts) = self.tweens.get(&e) {
+            for t in ts.iter() { ret = ret + t.offset(); }
+        }
+        // Stay on whole pixels so the pixel art doesn't get smeared.
+        ret.map(|x| x.round())
+    }
+
+    /// Pixel offset for drawing the world so that the view follows the
+    /// player's movement tween instead of jumping to the new cell.
+    pub fn camera_offset(&self) -> V2<f32> {
+        let mut ret = V2(0.0, 0.0);
+        if let Some(ts) = action::player().and_then(|p| self.tweens.get(&p)) {
+            for t in ts.iter().filter(|t| t.is_move()) { ret = ret - t.offset(); }
+        }
+        ret.map(|x| x.round())
+    }
+}
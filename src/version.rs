@@ -0,0 +1,13 @@
+/*! Game version, shared by the frontends. */
+
+pub fn version() -> String {
+    let next_release = "0.1.0";
+    // Set is_release to true for one commit to make a release.
+    let is_release = false;
+
+    if is_release {
+        format!("{}", next_release)
+    } else {
+        format!("{}-alpha", next_release)
+    }
+}
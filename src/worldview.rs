@@ -1,28 +1,137 @@
 use std::convert::{From};
-use std::collections::HashMap;
-use calx::{V2, Rgba, timing};
+use std::collections::{HashMap, HashSet};
+use calx::{V2, Rect, Rgba, Anchor, Dijkstra, timing};
 use calx::color::*;
-use calx::backend::{Canvas, CanvasUtil};
+use calx::backend::{Canvas, CanvasUtil, Fonter};
 use world::TerrainType;
 use world::{Location, Chart};
 use world::{FovStatus};
 use world::{Entity};
+use world::{Intrinsic};
 use world::{Light};
+use world::action;
+use world::components::{BrainState};
 use viewutil::{chart_to_screen, cells_on_screen, level_z_to_view};
-use viewutil::{FLOOR_Z, BLOCK_Z, DEPTH_Z_MODIFIER, PIXEL_UNIT};
+use viewutil::{FLOOR_Z, BLOCK_Z, FX_Z, DEPTH_Z_MODIFIER, PIXEL_UNIT};
 use drawable::{Drawable};
 use tilecache;
 use tilecache::tile::*;
 use gamestate::{Blink};
+use tween::Tweens;
 
-pub fn draw_world<C: Chart+Copy>(chart: &C, ctx: &mut Canvas, damage_timers: &HashMap<Entity, (Blink, u32)>) {
+/// Debug information that can be drawn on top of the world view.
+#[derive(Copy, Clone, Default)]
+pub struct Overlays {
+    /// Weights of the autoexplore pathing map.
+    pub explore_map: bool,
+    /// Weights of the pathing maps of hunting mobs.
+    pub hunting_map: bool,
+    /// Brain state and target of each mob.
+    pub brains: bool,
+    /// Cells seen by the mobs.
+    pub mob_fov: bool,
+    /// Spawn locations of the current area.
+    pub spawns: bool,
+}
+
+impl Overlays {
+    pub fn any(&self) -> bool {
+        self.explore_map || self.hunting_map || self.brains || self.mob_fov || self.spawns
+    }
+}
+
+/// Draw the world view. Show_status draws health bars and status icons
+/// over the mobs.
+pub fn draw_world<C: Chart+Copy>(chart: &C, ctx: &mut Canvas, damage_timers: &HashMap<Entity, (Blink, u32)>, tweens: &Tweens, overlays: &Overlays, show_status: bool) {
+    let camera_offset = tweens.camera_offset();
     for pt in cells_on_screen() {
-        let screen_pos = chart_to_screen(pt);
+        let screen_pos = chart_to_screen(pt) + camera_offset;
         let loc = *chart + pt;
-        let cell_drawable = CellDrawable::new(
-            loc, 0, loc.fov_status(), loc.light(), damage_timers);
+        let mut cell_drawable = CellDrawable::new(
+            loc, 0, loc.fov_status(), loc.light(), damage_timers, tweens);
+        cell_drawable.show_status = show_status;
         cell_drawable.draw(ctx, screen_pos);
     }
+
+    if overlays.any() {
+        draw_overlays(chart, ctx, overlays);
+    }
+}
+
+fn draw_overlays<C: Chart+Copy>(chart: &C, ctx: &mut Canvas, overlays: &Overlays) {
+    // Offset from cell draw position to the middle of the floor tile.
+    let center = V2(0.0, 4.0);
+
+    let mobs: Vec<Entity> = action::mobs().into_iter()
+        .filter(|e| !e.is_player() && e.location().is_some())
+        .collect();
+
+    let explore = if overlays.explore_map { action::autoexplore_map(32) } else { None };
+    let hunting: Vec<Dijkstra<Location>> = if overlays.hunting_map {
+        mobs.iter().filter(|e| e.brain_state() == Some(BrainState::Hunting))
+            .filter_map(|e| e.hunting_map())
+            .collect()
+    } else { Vec::new() };
+    let spawns: HashSet<Location> = if overlays.spawns {
+        action::spawn_locations().into_iter().collect()
+    } else { HashSet::new() };
+
+    // Screen positions of locations, for drawing the target lines.
+    let mut screen = HashMap::new();
+
+    for pt in cells_on_screen() {
+        let pos = chart_to_screen(pt) + center;
+        let loc = *chart + pt;
+        screen.insert(loc, pos);
+
+        if overlays.mob_fov && mobs.iter().any(|e| e.can_see_location(loc)) {
+            text(ctx, ".".to_string(), pos, DARKORANGE);
+        }
+
+        if spawns.contains(&loc) {
+            text(ctx, "S".to_string(), pos, YELLOW);
+        }
+
+        if let Some(w) = explore.as_ref().and_then(|m| m.weight(&loc)) {
+            text(ctx, format!("{}", w), pos + V2(0.0, -4.0), CYAN);
+        }
+
+        // Hunting maps overlap, show the nearest distance.
+        if let Some(w) = hunting.iter().filter_map(|m| m.weight(&loc)).min() {
+            text(ctx, format!("{}", w), pos + V2(0.0, 4.0), ORANGERED);
+        }
+    }
+
+    if overlays.brains {
+        for &e in mobs.iter() {
+            let pos = match screen.get(&e.location().unwrap()) {
+                Some(&p) => p,
+                None => continue
+            };
+            let state = match e.brain_state() {
+                Some(BrainState::Asleep) => "Zz",
+                Some(BrainState::Hunting) => "Hunt",
+                Some(BrainState::Roaming) => "Roam",
+                Some(BrainState::PlayerControl) => "Player",
+                None => "?",
+            };
+            text(ctx, state.to_string(), pos + V2(0.0, -16.0), LIGHTGREEN);
+
+            if let Some(target_pos) = e.ai_target()
+                .and_then(|t| t.location())
+                .and_then(|loc| screen.get(&loc)) {
+                ctx.draw_line(1.0, pos, *target_pos, 0.1, RED);
+            }
+        }
+    }
+
+    fn text(ctx: &mut Canvas, txt: String, pos: V2<f32>, color: Rgba) {
+        Fonter::new(ctx)
+            .color(color).border(BLACK)
+            .anchor(Anchor::Center)
+            .text(txt)
+            .draw(pos);
+    }
 }
 
 /// Drawable representation of a single map location.
@@ -31,7 +140,10 @@ pub struct CellDrawable<'a> {
     pub depth: i32,
     pub fov: Option<FovStatus>,
     pub light: Light,
+    /// Draw health bars and status icons over mobs.
+    pub show_status: bool,
     damage_timers: &'a HashMap<Entity, (Blink, u32)>,
+    tweens: &'a Tweens,
 }
 
 impl<'a> Drawable for CellDrawable<'a> {
@@ -74,13 +186,16 @@ impl<'a> CellDrawable<'a> {
         depth: i32,
         fov: Option<FovStatus>,
         light: Light,
-        damage_timers: &'a HashMap<Entity, (Blink, u32)>) -> CellDrawable<'a> {
+        damage_timers: &'a HashMap<Entity, (Blink, u32)>,
+        tweens: &'a Tweens) -> CellDrawable<'a> {
         CellDrawable {
             loc: loc,
             depth: depth,
             fov: fov,
             light: light,
+            show_status: false,
             damage_timers: damage_timers,
+            tweens: tweens,
         }
     }
 
@@ -246,6 +361,10 @@ impl<'a> CellDrawable<'a> {
                 self.draw_floor(ctx, FLOOR, offset, FLOOR_Z, SLATEGRAY);
                 self.draw_floor(ctx, POD, offset, BLOCK_Z, DARKCYAN);
             },
+            TerrainType::Lamp => {
+                self.draw_floor(ctx, FLOOR, offset, FLOOR_Z, SLATEGRAY);
+                self.draw_tile(ctx, MENHIR, offset, BLOCK_Z, LIGHTYELLOW);
+            },
         }
 
         fn blockform(c: &CellDrawable, ctx: &mut Canvas, k: &Kernel<TerrainType>, mut offset: V2<f32>, idx: usize, color: Rgba) {
@@ -323,6 +442,7 @@ impl<'a> CellDrawable<'a> {
         // doesn't bob along with the main body.
         static SERPENT_ICON: usize = 94;
 
+        let offset = offset + self.tweens.offset(*entity);
         let body_pos =
             if entity.is_bobbing() {
                 offset + *(timing::cycle_anim(
@@ -370,6 +490,56 @@ impl<'a> CellDrawable<'a> {
             }
 
             self.draw_tile2(ctx, icon, body_pos, BLOCK_Z, color, back_color);
+
+            // The player has the hearts display instead.
+            if self.show_status && entity.is_mob() && !entity.is_player() {
+                self.draw_status(ctx, body_pos, entity);
+            }
+        }
+    }
+
+    /// Draw a health bar for wounded mobs and icons for the brain state and
+    /// status effects above the mob sprite.
+    fn draw_status(&'a self, ctx: &mut Canvas, offset: V2<f32>, entity: &Entity) {
+        let top = offset + V2(0.0, -14.0);
+
+        let max_hp = entity.max_hp();
+        if entity.is_wounded() && max_hp > 0 {
+            let frac = (entity.hp() as f32 / max_hp as f32).max(0.0).min(1.0);
+            let color = if frac > 0.5 { LIME } else if frac > 0.25 { YELLOW } else { RED };
+            let pos = top + V2(-6.0, 0.0);
+            ctx.fill_rect(&Rect(pos - V2(1.0, 1.0), V2(14.0, 4.0)), FX_Z, BLACK);
+            ctx.fill_rect(&Rect(pos, V2((12.0 * frac).ceil(), 2.0)), FX_Z, color);
+        }
+
+        let brain = match entity.brain_state() {
+            Some(BrainState::Asleep) => Some(("z", LIGHTSKYBLUE)),
+            Some(BrainState::Hunting) => Some(("!", ORANGERED)),
+            Some(BrainState::Roaming) => Some(("?", KHAKI)),
+            _ => None,
+        };
+        if let Some((txt, color)) = brain {
+            status_text(ctx, txt, top + V2(11.0, 0.0), color);
+        }
+
+        // Only passing statuses, the permanent intrinsics of the mob's kind
+        // would be on most of the mobs all the time.
+        let mut statuses = Vec::new();
+        if entity.has_status(Intrinsic::Slow) { statuses.push(("s", CORNFLOWERBLUE)); }
+        if entity.has_status(Intrinsic::Fast) || entity.has_status(Intrinsic::Quick) {
+            statuses.push(("f", GOLD));
+        }
+        for (i, &(txt, color)) in statuses.iter().enumerate() {
+            status_text(ctx, txt, top + V2(-11.0 - 6.0 * i as f32, 0.0), color);
+        }
+
+        fn status_text(ctx: &mut Canvas, txt: &str, pos: V2<f32>, color: Rgba) {
+            Fonter::new(ctx)
+                .color(color).border(BLACK)
+                .layer(FX_Z)
+                .anchor(Anchor::Center)
+                .text(txt.to_string())
+                .draw(pos);
         }
     }
 }
@@ -404,3 +574,51 @@ impl<C: Clone> Kernel<C> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use image;
+    use calx::timing;
+    use calx::backend::{CanvasBuilder, Event};
+    use world::{self, World};
+    use world::action::{self, ControlState};
+    use tilecache;
+    use tween::Tweens;
+    use super::{draw_world, Overlays};
+
+    /// Checked-in render of the start of a fixed-seed game.
+    static REFERENCE: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test/world_view.png");
+
+    #[test]
+    fn test_draw_world() {
+        let mut builder = CanvasBuilder::new().set_size(::SCREEN_W, ::SCREEN_H);
+        tilecache::init(&mut builder);
+        let mut ctx = builder.build_software();
+
+        // Hold the mob bobbing and item blinking still.
+        timing::freeze(Some(0.0));
+        World::new(Some(1)).enter(|| {
+            while action::control_state() != ControlState::AwaitingInput { action::update(); }
+            assert!(ctx.next_event() == Event::RenderFrame);
+            draw_world(&world::camera(), &mut ctx, &HashMap::new(), &Tweens::new(),
+                       &Overlays::default(), true);
+            assert!(ctx.next_event() == Event::RenderFrame);
+        });
+        timing::freeze(None);
+        let shot = ctx.screenshot();
+
+        // Write a missing reference so it can be looked over and checked in.
+        if !Path::new(REFERENCE).exists() {
+            fs::create_dir_all(Path::new(REFERENCE).parent().unwrap()).unwrap();
+            shot.save(REFERENCE).unwrap();
+            panic!("Wrote missing reference image {}", REFERENCE);
+        }
+        let reference = image::open(REFERENCE).unwrap().to_rgb();
+        assert!(reference.dimensions() == shot.dimensions()
+                && reference.into_raw() == shot.into_raw(),
+                "World view differs from {}", REFERENCE);
+    }
+}
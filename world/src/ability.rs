@@ -16,12 +16,14 @@ pub enum Ability {
 }
 
 impl Ability {
-    pub fn apply(&self, agent: Option<Entity>, target: Place) {
+    /// Use the ability on a target. Return whether it had any effect.
+    pub fn apply(&self, agent: Option<Entity>, target: Place) -> bool {
         if let &Multi(ref abls) = self {
+            let mut ret = false;
             for abl in abls.iter() {
-                abl.apply(agent, target);
+                ret = abl.apply(agent, target) || ret;
             }
-            return;
+            return ret;
         }
 
         // Target entity.
@@ -31,15 +33,18 @@ impl Ability {
         };
 
         match (self, te) {
-            (&Damage(n), Some(e)) => { e.damage(n) }
-            (&Heal(n), Some(e))  => { e.heal(n) }
+            (&Damage(n), Some(e)) => { e.damage(n); true }
+            (&Heal(n), Some(e))  => { e.heal(n); true }
             (&HealInstant(n), Some(e)) => {
                 if e.is_wounded() {
                     e.heal(n);
                     if let Some(a) = agent { a.delete() }
+                    true
+                } else {
+                    false
                 }
             }
-            _ => ()
+            _ => false
         }
     }
 }
@@ -1,19 +1,23 @@
-use std::io::prelude::*;
-use std::path::{Path};
-use std::fs::{self, File};
 use rand::StdRng;
 use rand::SeedableRng;
-use std::iter::Filter;
 use calx::Dijkstra;
 use entity::Entity;
 use ecs::EntityIter;
 use world;
 use flags;
 use calx::Dir6;
+use item::Slot;
 use area::Area;
 use location::Location;
-use ecs::{ComponentAccess};
 use msg;
+use fov;
+use clock;
+use light;
+use replay::Replay;
+
+pub use savegame::{save_game, load_game, save_game_to, load_game_from};
+pub use savegame::{delete_save, save_exists, saved_seed, convert_save};
+pub use savegame::{set_saving, saving};
 
 /// Game update control.
 #[derive(Copy, Clone, PartialEq)]
@@ -33,6 +37,10 @@ pub enum Input {
     Shoot(Dir6),
     /// Do nothing for a turn.
     Pass,
+    /// Swap the items in two inventory slots. Takes no time.
+    SwapItems(Slot, Slot),
+    /// Drop the item in an inventory slot. Takes no time.
+    DropItem(Slot),
 }
 
 /// Return the player entity if one exists.
@@ -40,17 +48,26 @@ pub fn player() -> Option<Entity> {
     world::with(|w| w.flags.player)
 }
 
-/// Find the first entity that has a local (not inherited) Desc component with
-/// the given name.
+/// Find the first prototype that has a local (not inherited) Desc component
+/// with the given name.
 pub fn find_prototype(name: &str) -> Option<Entity> {
-    world::with(|w|
-        entities().find(|&e| {
-            if let Some(d) = w.descs().get_local(e) {
-                if d.name == name { return true; }
-            }
-            false
-        })
-    )
+    world::with(|w| {
+        w.ecs.descs().entities_local().into_iter()
+            .filter(|e| e.is_prototype())
+            .map(Entity)
+            .find(|&e| w.ecs.descs().get_local(e).unwrap().name == name)
+    })
+}
+
+/// Return the names of all the prototypes that can be found with
+/// `find_prototype`.
+pub fn prototype_names() -> Vec<String> {
+    world::with(|w| {
+        w.ecs.descs().entities_local().into_iter()
+            .filter(|e| e.is_prototype())
+            .map(|e| w.ecs.descs().get_local(e).unwrap().name.clone())
+            .collect()
+    })
 }
 
 /// Spawn a specific type of entity
@@ -72,15 +89,20 @@ pub fn control_state() -> ControlState {
 
 /// Top-level game state update function. Only valid to call if
 /// control_state() returned ReadyToUpdate.
+///
+/// Each update is one tick of game time. Mobs that have enough energy act
+/// and every mob then gains more energy based on its speed.
 pub fn update() {
     assert!(control_state() == ControlState::ReadyToUpdate);
 
     ai_main();
 
+    let prev_tick = flags::get_tick();
     world::with_mut(|w| {
         w.flags.tick += 1;
         w.flags.player_acted = false;
     });
+    clock::tick_passed(prev_tick);
 }
 
 /// Give player input. Only valid to call if control_state() returned
@@ -88,6 +110,7 @@ pub fn update() {
 pub fn input(input: Input) {
     assert!(control_state() == ControlState::AwaitingInput);
     let p = player().expect("No player to receive input");
+    world::with_replay(|r| if let Some(ref mut r) = *r { r.inputs.push(input); });
     match input {
         Input::Step(d) => {
             p.step(d);
@@ -99,6 +122,18 @@ pub fn input(input: Input) {
             p.shoot(d);
         }
         Input::Pass => {
+            p.wait();
+        }
+        // Inventory management doesn't end the turn.
+        Input::SwapItems(slot1, slot2) => {
+            p.swap_equipped(slot1, slot2);
+            return;
+        }
+        Input::DropItem(slot) => {
+            if let Some(item) = p.equipped(slot) {
+                item.place(p.location().unwrap());
+            }
+            return;
         }
     }
     world::with_mut(|w| w.flags.player_acted = true);
@@ -115,19 +150,22 @@ pub fn input(input: Input) {
 
 /// Return an iterator of all the world entities.
 pub fn entities() -> EntityIter {
-    world::with(|w| w.ecs.iter())
+    EntityIter::new()
 }
 
-/// Return an iterator of all the world mobs.
-pub fn mobs() -> Filter<EntityIter, fn(&Entity) -> bool> {
-    fn is_mob(e: &Entity) -> bool { e.is_mob() }
-    entities().filter(is_mob as fn(&Entity) -> bool)
+/// Return all the world mobs in entity index order.
+pub fn mobs() -> Vec<Entity> {
+    world::with(|w| w.ecs.brains().entities()).into_iter()
+        .map(Entity)
+        .filter(|e| e.is_mob())
+        .collect()
 }
 
 /// Run AI for all autonomous mobs.
 fn ai_main() {
-    for entity in entities() {
-        entity.update();
+    for &entity in mobs().iter() {
+        // Mobs can get deleted by the actions of other mobs.
+        if entity.is_alive() { entity.update(); }
     }
 }
 
@@ -151,6 +189,7 @@ pub fn start_level(depth: i32) {
     world::with_mut(|w| {
         w.area = new_area.clone();
     });
+    fov::invalidate();
 
     let mut rng: StdRng = SeedableRng::from_seed(&[seed as usize + depth as usize][..]);
     for (spawn, loc) in world::with(|w| w.area.get_spawns()).into_iter() {
@@ -195,13 +234,16 @@ fn clear_nonplayers() {
 pub fn shoot(origin: Location, dir: Dir6, range: u32, power: i32) {
     let mut loc = origin;
     if range == 0 { return; }
+    light::flash(origin);
     for i in 1..(range + 1) {
         loc = origin + dir.to_v2() * (i as i32);
         if loc.terrain().blocks_shot() {
             msg::push(::Msg::Sparks(loc));
+            msg::push(::Msg::Smoke(loc));
             break;
         }
         if let Some(e) = loc.mob_at() {
+            e.hit_effect(dir);
             e.damage(power);
             break;
         }
@@ -229,6 +271,40 @@ pub fn autoexplore_map(pathing_depth: u32) -> Option<Dijkstra<Location>> {
     Some(Dijkstra::new(locs, |&loc| !loc.blocks_walk(), pathing_depth))
 }
 
+/// Move the player in a direction, attacking enemies in the way.
+///
+/// Enemies next to the player are attacked in melee and enemies further
+/// away in the direction are shot at. If the way is blocked, try to
+/// sidestep into the adjacent directions. Only valid to call if
+/// control_state() returned AwaitingInput.
+pub fn smart_move(dir: Dir6) {
+    let player = player().unwrap();
+    let loc = player.location().unwrap();
+
+    if !(loc + dir.to_v2()).has_mobs() {
+        // Shoot instead of moving if you'd hit an enemy and there's no
+        // melee target.
+        let shoot_range = player.stats().ranged_range as usize;
+        if let Some(e) = find_target(player, dir, shoot_range) {
+            if player.is_hostile_to(e) {
+                input(Input::Shoot(dir));
+                return;
+            }
+        }
+    }
+
+    for &d in vec![dir, dir + 1, dir - 1].iter() {
+        let target_loc = loc + d.to_v2();
+        if target_loc.has_mobs() {
+            input(Input::Melee(d));
+            return;
+        } else if player.can_step(d) {
+            input(Input::Step(d));
+            return;
+        }
+    }
+}
+
 /// Look for targets to shoot in a direction.
 pub fn find_target(shooter: Entity, dir: Dir6, range: usize) -> Option<Entity> {
     let origin = shooter.location().unwrap();
@@ -248,34 +324,46 @@ pub fn find_target(shooter: Entity, dir: Dir6, range: usize) -> Option<Entity> {
 
 pub fn terrans_left() -> u32 { world::with(|w| w.flags.terrans_left) }
 
-////////////////////////////////////////////////////////////////////////
+// Developer commands //////////////////////////////////////////////////
+
+/// Return the seed of the current game.
+pub fn seed() -> u32 { world::with(|w| w.flags.seed) }
+
+/// Return the player inputs since the start of the game, or None if the
+/// game was loaded from a save.
+pub fn replay() -> Option<Replay> { world::with_replay(|r| r.clone()) }
 
-static SAVE_FILENAME: &'static str = "phage_save.json";
+pub fn godmode() -> bool { world::godmode() }
 
-pub fn save_game() {
-    // Only save if there's still a living player around.
+/// Toggle player invulnerability.
+pub fn set_godmode(on: bool) { world::set_godmode(on); }
+
+/// Make the player remember the whole current area.
+pub fn reveal_map() {
     if let Some(p) = player() {
-        if p.is_corpse() { return; }
-    } else {
-        return;
+        world::with_mut(|w| {
+            let locs: Vec<Location> = w.area.terrain.keys().cloned().collect();
+            if let Some(mm) = w.ecs.mu().map_memories().get(p) {
+                mm.remembered.extend(locs.into_iter());
+            }
+        });
     }
-
-    let save_data = world::save();
-    File::create(SAVE_FILENAME).unwrap()
-        .write_all(&save_data.into_bytes()).unwrap();
 }
 
-pub fn load_game() {
-    if !save_exists() { return; }
-    let path = Path::new(SAVE_FILENAME);
-    let mut save_data = String::new();
-    File::open(&path).unwrap().read_to_string(&mut save_data).unwrap();
-    // TODO: Handle failed load nicely.
-    world::load(&save_data[..]).unwrap();
+/// Kill all the living mobs except the player. Return the number of mobs
+/// killed.
+pub fn kill_all() -> usize {
+    let mut count = 0;
+    for &e in mobs().iter() {
+        if e.is_alive() && !e.is_player() && !e.is_corpse() {
+            e.kill();
+            count += 1;
+        }
+    }
+    count
 }
 
-pub fn delete_save() {
-    let _ = fs::remove_file(SAVE_FILENAME);
+/// Return the locations where the current area spawned its inhabitants.
+pub fn spawn_locations() -> Vec<Location> {
+    world::with(|w| w.area.get_spawns().into_iter().map(|(_, loc)| loc).collect())
 }
-
-pub fn save_exists() -> bool { fs::metadata(SAVE_FILENAME).is_ok() }
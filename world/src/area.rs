@@ -74,12 +74,16 @@ impl Area {
             .filter(|&(loc, &t)| t.valid_spawn_spot() && biomes.get(loc) == Some(&Biome::Overland))
             .map(|(&loc, _)| loc)
             .collect();
+        // Hash map iteration order varies between runs, sort the slots
+        // before shuffling so that the seed alone decides the spawns.
+        outdoors.sort();
         rng.shuffle(&mut outdoors[..]);
 
         let mut bases: Vec<Location> = terrain.iter()
             .filter(|&(loc, &t)| t.valid_spawn_spot() && biomes.get(loc) == Some(&Biome::Base))
             .map(|(&loc, _)| loc)
             .collect();
+        bases.sort();
         rng.shuffle(&mut bases[..]);
 
         let entrance = outdoors.pop().unwrap();
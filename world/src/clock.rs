@@ -0,0 +1,175 @@
+/*! World clock and the day-night cycle of the overland.
+
+The time of day is derived from the game tick, so it needs no saved state.
+The underground levels have no daylight and count as permanent night.
+*/
+
+use calx::{Rgba, lerp};
+use action;
+use flags;
+use fov;
+use Light;
+
+/// Ticks in a full day.
+pub static DAY_TICKS: u64 = 2000;
+
+/// Time of day when the game starts, as a fraction of the day from
+/// midnight.
+static START_TIME: f32 = 0.3;
+
+/// Daylight at times of day, from midnight to midnight. Light at other times
+/// is interpolated between these.
+static DAYLIGHT: [(f32, [f32; 3]); 7] = [
+    (0.0, [0.10, 0.12, 0.25]),
+    (0.2, [0.10, 0.12, 0.25]),
+    // Dawn, reddish.
+    (0.25, [0.8, 0.45, 0.4]),
+    (0.3, [1.0, 1.0, 1.0]),
+    (0.7, [1.0, 1.0, 1.0]),
+    // Dusk, orange.
+    (0.75, [0.9, 0.5, 0.25]),
+    (0.8, [0.10, 0.12, 0.25]),
+];
+
+/// Parts of the day.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Phase {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Dawn => "dawn",
+            Phase::Day => "day",
+            Phase::Dusk => "dusk",
+            Phase::Night => "night",
+        }
+    }
+}
+
+/// Time of day at a tick as a fraction of the day from midnight.
+fn time_at(tick: u64) -> f32 {
+    let t = START_TIME + (tick % DAY_TICKS) as f32 / DAY_TICKS as f32;
+    t - t.floor()
+}
+
+fn phase_at(time: f32) -> Phase {
+    if time < 0.2 { Phase::Night }
+    else if time < 0.3 { Phase::Dawn }
+    else if time < 0.7 { Phase::Day }
+    else if time < 0.8 { Phase::Dusk }
+    else { Phase::Night }
+}
+
+fn daylight_at(time: f32) -> Light {
+    let mut prev = DAYLIGHT[0];
+    // Wrap around to the midnight value at the end.
+    for &next in DAYLIGHT.iter().skip(1).chain(Some((1.0, DAYLIGHT[0].1)).iter()) {
+        if time < next.0 {
+            let t = (time - prev.0) / (next.0 - prev.0);
+            let (a, b) = (prev.1, next.1);
+            return Light::colored(Rgba::new(lerp(a[0], b[0], t), lerp(a[1], b[1], t),
+                                            lerp(a[2], b[2], t), 1.0), 1.0);
+        }
+        prev = next;
+    }
+    Light::colored(Rgba::new(prev.1[0], prev.1[1], prev.1[2], 1.0), 1.0)
+}
+
+/// Return the current part of the day on the overland.
+pub fn phase() -> Phase {
+    phase_at(time_at(flags::get_tick()))
+}
+
+/// Return the current daylight on the overland.
+pub fn daylight() -> Light {
+    daylight_at(time_at(flags::get_tick()))
+}
+
+/// Return whether the current level is out in the sun during the day.
+pub fn is_daylight() -> bool {
+    action::current_depth() == 1 && phase() == Phase::Day
+}
+
+/// How far observers can see on the current level.
+pub fn sight_range() -> u32 {
+    if action::current_depth() != 1 { return fov::SIGHT_RANGE; }
+    match phase() {
+        Phase::Day => fov::SIGHT_RANGE,
+        Phase::Dawn | Phase::Dusk => fov::SIGHT_RANGE * 3 / 4,
+        Phase::Night => fov::SIGHT_RANGE / 2,
+    }
+}
+
+/// Call after the world clock has advanced. Announces the changes of the
+/// day phase on the overland.
+pub fn tick_passed(prev_tick: u64) {
+    let (prev, now) = (phase_at(time_at(prev_tick)), phase());
+    if prev == now { return; }
+    // Sight ranges change with the phase, update what the player sees
+    // without waiting for them to move.
+    fov::invalidate();
+    if let Some(p) = action::player() { p.do_fov(); }
+    if action::current_depth() != 1 { return; }
+    match now {
+        Phase::Dawn => msgln!("The sun rises."),
+        Phase::Day => {}
+        Phase::Dusk => msgln!("The sun is setting."),
+        Phase::Night => msgln!("Night falls."),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use calx::{V2, HexGeom};
+    use world::{self, World};
+    use action;
+    use fov;
+    use super::{time_at, phase_at, daylight_at, tick_passed, sight_range, Phase, DAY_TICKS};
+
+    #[test]
+    fn test_clock() {
+        assert_eq!(phase_at(time_at(0)), Phase::Day);
+        assert_eq!(phase_at(time_at(DAY_TICKS / 2)), Phase::Night);
+        assert_eq!(phase_at(time_at(DAY_TICKS)), Phase::Day);
+        assert!(daylight_at(0.5).lum() > 0.99);
+        assert!(daylight_at(0.0).lum() < 0.2);
+        assert!(daylight_at(0.99).lum() < 0.2);
+        // Smooth over midnight.
+        assert!((daylight_at(0.999).lum() - daylight_at(0.001).lum()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_nightfall_updates_view() {
+        World::new(Some(1)).enter(|| {
+            let dusk_end = (0..DAY_TICKS)
+                .find(|&t| phase_at(time_at(t)) == Phase::Dusk &&
+                           phase_at(time_at(t + 1)) == Phase::Night)
+                .unwrap();
+            let player = action::player().unwrap();
+            let origin = player.location().unwrap();
+            let r = fov::SIGHT_RANGE as i32;
+            let seen_beyond = |range: u32| {
+                (-r..r + 1).flat_map(|y| (-r..r + 1).map(move |x| V2(x, y)))
+                    .filter(|v| v.hex_dist() > range as i32)
+                    .any(|v| world::with(|w| w.ecs.map_memories().get(player).unwrap()
+                                              .seen.contains(&(origin + v))))
+            };
+
+            world::with_mut(|w| w.flags.tick = dusk_end);
+            fov::invalidate();
+            player.do_fov();
+            let night_range = fov::SIGHT_RANGE / 2;
+            assert!(seen_beyond(night_range));
+
+            world::with_mut(|w| w.flags.tick = dusk_end + 1);
+            tick_passed(dusk_end);
+            assert_eq!(sight_range(), night_range);
+            assert!(!seen_beyond(night_range));
+        });
+    }
+}
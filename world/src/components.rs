@@ -8,16 +8,14 @@ use item::{ItemType};
 use ability::Ability;
 use stats::Stats;
 
-/// Dummy component to mark prototype objects
-#[derive(Copy, Clone, Debug, RustcEncodable, RustcDecodable)]
-pub struct IsPrototype;
-
 /// Entity name and appearance.
 #[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
 pub struct Desc {
     pub name: String,
     pub icon: usize,
     pub color: Rgba,
+    /// Character that stands for the entity in text mode.
+    pub glyph: Option<char>,
 }
 
 impl Desc {
@@ -30,6 +28,7 @@ impl Desc {
             name: name.to_string(),
             icon: icon,
             color: color.into(),
+            glyph: None,
         }
     }
 }
@@ -151,6 +150,14 @@ pub struct Health {
 }
 
 
+/// Accumulated action energy. Mobs gain energy every tick based on their
+/// speed and can act when they have enough of it.
+#[derive(Copy, Clone, Debug, Default, RustcEncodable, RustcDecodable)]
+pub struct Energy {
+    pub amount: i32,
+}
+
+
 /// Items can be picked up and carried and they do stuff.
 #[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
 pub struct Item {
@@ -175,3 +182,22 @@ impl Colonist {
     // Bases will be assigned when the unit is deployed.
     pub fn new() -> Colonist { Colonist { home_base: String::new() } }
 }
+
+
+/// Entity gives off light, like a bioluminescent alien or a robot with a
+/// headlamp.
+#[derive(Copy, Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct LightSource {
+    pub color: Rgba,
+    /// How many cells the light reaches.
+    pub radius: u32,
+}
+
+impl LightSource {
+    pub fn new<C: Into<Rgba>>(color: C, radius: u32) -> LightSource {
+        LightSource {
+            color: color.into(),
+            radius: radius,
+        }
+    }
+}
@@ -0,0 +1,73 @@
+/// Energy a mob needs to have accumulated before it can take an action.
+pub static READY_ENERGY: i32 = 100;
+
+/// Energy a mob of normal speed gains every tick.
+pub static BASE_SPEED: i32 = 60;
+
+/// Speed change from each of the speed intrinsics.
+pub static SPEED_STEP: i32 = 20;
+
+/// Cheapest possible action, cost modifiers can't go below this.
+pub static MIN_COST: i32 = 10;
+
+/// Types of actions that mobs spend energy on.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ActionType {
+    /// Moving to an adjacent cell.
+    Step,
+    /// Hitting an adjacent mob.
+    Melee,
+    /// Firing a ranged attack.
+    Shoot,
+    /// Using an ability, like the one of an item.
+    Ability,
+    /// Trying to bash through a door without hands.
+    ForceDoor,
+    /// Doing nothing for a turn.
+    Wait,
+}
+
+impl ActionType {
+    /// Energy cost of the action before any per-mob modifiers.
+    pub fn base_cost(self) -> i32 {
+        match self {
+            ActionType::Step => 100,
+            ActionType::Melee => 100,
+            ActionType::Shoot => 100,
+            ActionType::Ability => 100,
+            ActionType::ForceDoor => 150,
+            ActionType::Wait => 100,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{READY_ENERGY, BASE_SPEED, SPEED_STEP};
+
+    /// Count how many times a mob gets to act with the given speed during
+    /// the given number of ticks if each action costs the default amount.
+    fn actions_in(ticks: usize, speed: i32) -> usize {
+        let mut energy = 0;
+        let mut ret = 0;
+        for _ in 0..ticks {
+            if energy >= READY_ENERGY {
+                energy -= 100;
+                ret += 1;
+            }
+            energy += speed;
+        }
+        ret
+    }
+
+    #[test]
+    fn test_speeds() {
+        // The speed values should reproduce the old five-phase cycle,
+        // normal speed acts on three ticks out of five and each speed
+        // intrinsic adds or subtracts one action per cycle.
+        assert_eq!(actions_in(101, BASE_SPEED - SPEED_STEP), 40);
+        assert_eq!(actions_in(101, BASE_SPEED), 60);
+        assert_eq!(actions_in(101, BASE_SPEED + SPEED_STEP), 80);
+        assert_eq!(actions_in(101, BASE_SPEED + 2 * SPEED_STEP), 100);
+    }
+}
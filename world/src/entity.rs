@@ -1,27 +1,68 @@
 use std::default::Default;
-use calx::{Dijkstra, Rgba, color, Dir6, HexGeom, HexFov};
+use std::cmp::max;
+use calx::{Dijkstra, Rgba, color, Dir6, HexGeom, lerp};
 use world;
 use location::{Location};
 use flags;
-use components::{BrainState, Alignment, Brain};
+use components::{BrainState, Alignment, Brain, Desc, LightSource};
 use spatial::Place;
 use action;
 use rng;
 use msg;
 use item::{ItemType, Slot};
 use stats::{Stats, Intrinsic};
-use ecs::{ComponentAccess};
 use terrain::TerrainType;
+use energy::{self, ActionType};
+use fov;
+use clock;
+use light;
+use calx_ecs::{self, Component, CompMask};
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
 
 /// Game object handle.
-#[derive(Copy, PartialEq, Eq, Clone, Hash, PartialOrd, Ord, Debug, RustcDecodable, RustcEncodable)]
-pub struct Entity(pub usize);
+///
+/// Wraps the `calx_ecs` handle to provide the game logic methods. The
+/// handle has a unique identifier, so a handle that is kept around after its
+/// entity was deleted won't alias a new entity that reuses the slot. Use
+/// `is_alive` to check whether a handle is still valid, accessing the
+/// components of a dead entity will panic.
+#[derive(Copy, PartialEq, Eq, Clone, Hash, PartialOrd, Ord, Debug)]
+pub struct Entity(pub calx_ecs::Entity);
+
+impl From<Entity> for calx_ecs::Entity {
+    fn from(e: Entity) -> calx_ecs::Entity { e.0 }
+}
+
+// Serialize as the plain inner handle.
+impl Decodable for Entity {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Entity, D::Error> {
+        Ok(Entity(try!(Decodable::decode(d))))
+    }
+}
+
+impl Encodable for Entity {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        self.0.encode(s)
+    }
+}
 
 impl Entity {
+    /// Index of the entity slot.
+    pub fn idx(self) -> usize { self.0.idx as usize }
+
+    /// Return whether the entity exists in the current world. Handles to
+    /// deleted entities aren't alive even if a new entity reuses the slot.
+    pub fn is_alive(self) -> bool {
+        world::with(|w| w.ecs.contains(self.0))
+    }
+
     /// Place the entity in a location in the game world.
     pub fn place(self, loc: Location) {
         assert!(!self.is_prototype(), "Tried to field a prototype");
+        let old_loc = self.location();
         world::with_mut(|w| w.spatial.insert_at(self, loc));
+        if let Some(old_loc) = old_loc { fov::occupancy_changed(old_loc); }
+        fov::occupancy_changed(loc);
         self.on_move_to(loc);
     }
 
@@ -32,16 +73,18 @@ impl Entity {
     pub fn delete(self) {
         world::with_mut(|w|
             if w.flags.player == Some(self) { w.flags.player = None; });
-        world::with_mut(|w| w.comps.remove(self));
+        let loc = self.location();
         world::with_mut(|w| w.spatial.remove(self));
-        world::with_mut(|w| w.ecs.delete(self));
+        world::with_mut(|w| w.ecs.remove(self.0));
+        world::with_fov(|c| c.forget(self));
+        if let Some(loc) = loc { fov::occupancy_changed(loc); }
     }
 
     pub fn blocks_walk(self) -> bool { self.is_mob() }
 
     pub fn name(self) -> String {
         world::with(|w|
-            match w.descs().get(self) {
+            match w.ecs.descs().get(self) {
                 Some(desc) => desc.name.clone(),
                 None => "".to_string()
             }
@@ -50,7 +93,7 @@ impl Entity {
 
     pub fn get_icon(self) -> Option<(usize, Rgba)> {
         world::with(|w|
-            if let Some(desc) = w.descs().get(self) {
+            if let Some(desc) = w.ecs.descs().get(self) {
                 Some((desc.icon, desc.color))
             } else {
                 None
@@ -58,11 +101,16 @@ impl Entity {
         )
     }
 
+    /// Return the character that stands for the entity in text mode.
+    pub fn glyph(self) -> Option<char> {
+        world::with(|w| w.ecs.descs().get(self).and_then(|d| d.glyph))
+    }
+
     /// Create a cloned entity that uses the current entity as a parent
     /// prototype. Components not defined in the clone entity will be read
     /// from the current entity.
     pub fn clone_at(self, loc: Location) -> Entity {
-        let ret = world::with_mut(|w| { w.ecs.new_entity(Some(self)) });
+        let ret = world::with_mut(|w| Entity(w.ecs.make(Some(self.0))));
         ret.place(loc);
 
         if ret.is_terran() {
@@ -73,16 +121,16 @@ impl Entity {
     }
 
     pub fn is_prototype(self) -> bool {
-        world::with(|w| w.prototypes().get_local(self).is_some())
+        self.0.is_prototype()
     }
 
     pub fn parent(self) -> Option<Entity> {
-        world::with(|w| w.ecs.parent(self))
+        world::with(|w| w.ecs.get_parent(self.0).map(Entity))
     }
 
     pub fn reparent(self, new_parent: Entity) {
         assert!(new_parent.is_prototype());
-        world::with_mut(|w| w.ecs.reparent(self, new_parent));
+        world::with_mut(|w| w.ecs.set_parent(self.0, Some(new_parent.0)));
     }
 
 // Spatial methods /////////////////////////////////////////////////////
@@ -114,18 +162,21 @@ impl Entity {
         if let Some(Place::At(loc)) = place {
             let new_loc = loc + dir.to_v2();
             if self.can_enter(new_loc) {
-                world::with_mut(|w| w.spatial.insert_at(self, new_loc));
-                self.on_move_to(new_loc);
+                self.spend_energy(ActionType::Step);
+                self.place(new_loc);
             } else if new_loc.terrain() == TerrainType::Door && self.is_player() {
                 // Player can force doors even in unsuitable form.
+                self.spend_energy(ActionType::ForceDoor);
                 let force_difficulty = 5 - self.stats().power / 2;
                 if force_difficulty <= 1 || rng::one_chance_in(force_difficulty as u32) {
-                    world::with_mut(|w| w.spatial.insert_at(self, new_loc));
-                    self.on_move_to(new_loc);
+                    self.place(new_loc);
                     msgln!("Door forced.");
                 } else {
                     msgln!("Morph has trouble with doors.");
                 }
+            } else {
+                // Bumped into something, the turn is lost.
+                self.spend_energy(ActionType::Step);
             }
         }
     }
@@ -173,10 +224,11 @@ impl Entity {
     /// damage method.
     fn apply_damage(self, amount: i32) {
         if amount <= 0 { return; }
+        if self.is_player() && world::godmode() { return; }
         let max_hp = self.max_hp();
 
         let (_amount, kill) = world::with_mut(|w| {
-            let health = w.healths_mut().get(self).expect("no health");
+            let health = w.ecs.mu().healths().get(self).expect("no health");
             health.wounds += amount;
             (amount, health.wounds >= max_hp)
         });
@@ -187,10 +239,25 @@ impl Entity {
         }
     }
 
+    /// Show the effect of the entity getting hit from a direction.
+    pub fn hit_effect(self, dir: Dir6) {
+        let loc = match self.location() {
+            Some(loc) => loc,
+            None => return,
+        };
+        if self.has_intrinsic(Intrinsic::Robotic) {
+            msg::push(::Msg::Sparks(loc));
+        } else if self.is_terran() {
+            msg::push(::Msg::Blood(loc, dir));
+        } else {
+            msg::push(::Msg::Acid(loc, dir));
+        }
+    }
+
     pub fn heal(self, amount: i32) {
         if amount <= 0 { return; }
         world::with_mut(|w| {
-            let health = w.healths_mut().get(self).expect("no health");
+            let health = w.ecs.mu().healths().get(self).expect("no health");
             health.wounds -= amount;
             if health.wounds < 0 {
                 health.wounds = 0;
@@ -229,14 +296,20 @@ impl Entity {
             }
         }
 
-        msg::push(::Msg::Gib(loc));
+        if self.has_intrinsic(Intrinsic::Robotic) {
+            msg::push(::Msg::Explosion(loc));
+        } else {
+            msg::push(::Msg::Gib(loc));
+        }
 
         // Turn into corpse.
         world::with_mut(|w| {
-            w.brains_mut().hide(self);
+            w.ecs.mu().brains().hide(self);
             // Corpses are always icon + 1.
-            w.descs_mut().get(self).expect("no desc").icon += 1;
+            w.ecs.mu().descs().get(self).expect("no desc").icon += 1;
         });
+        // No longer a mob holding a door open.
+        fov::occupancy_changed(loc);
 
         // Try to have one corpse per cell, spill out if dying on top of
         // another corpse. (If there's no room left around, the corpses will
@@ -256,7 +329,7 @@ impl Entity {
     pub fn is_corpse(self) -> bool { self.has_intrinsic(Intrinsic::Dead) }
 
     pub fn is_mob(self) -> bool {
-        world::with(|w| w.brains().get(self).is_some()) && self.location().is_some()
+        world::with(|w| w.ecs.brains().get(self).is_some()) && self.location().is_some()
     }
 
     /// Return whether this mob is the player avatar.
@@ -277,30 +350,64 @@ impl Entity {
         }
     }
 
-    /// Return if the entity is a mob that should get an update this frame
-    /// based on its speed properties. Does not check for status effects like
-    /// sleep that might prevent actual action.
-    pub fn ticks_this_frame(self) -> bool {
+    /// Return if the entity is a mob that has accumulated enough energy to
+    /// take an action. Does not check for status effects like sleep that
+    /// might prevent actual action.
+    pub fn is_ready(self) -> bool {
         if !self.is_mob() { return false; }
-
-        let tick = flags::get_tick();
-        // Go through a cycle of 5 phases to get 4 possible speeds.
-        // System idea from Jeff Lait.
-        let phase = tick % 5;
-        match phase {
-            0 => return true,
-            1 => return self.has_intrinsic(Intrinsic::Fast),
-            2 => return true,
-            3 => return self.has_intrinsic(Intrinsic::Quick),
-            4 => return !self.has_intrinsic(Intrinsic::Slow),
-            _ => panic!("Invalid action phase"),
-        }
+        self.energy() >= energy::READY_ENERGY
     }
 
     /// Return whether the entity is a mob that will act this frame.
     pub fn acts_this_frame(self) -> bool {
         if !self.is_active() { return false; }
-        return self.ticks_this_frame();
+        return self.is_ready();
+    }
+
+    /// Return the amount of action energy the entity has accumulated.
+    pub fn energy(self) -> i32 {
+        world::with(|w| w.ecs.energies().get(self).map_or(0, |e| e.amount))
+    }
+
+    /// Return the energy the entity gains every tick.
+    pub fn speed(self) -> i32 {
+        let mut speed = energy::BASE_SPEED + self.stats().speed;
+        if self.has_intrinsic(Intrinsic::Slow) { speed -= energy::SPEED_STEP; }
+        if self.has_intrinsic(Intrinsic::Fast) { speed += energy::SPEED_STEP; }
+        if self.has_intrinsic(Intrinsic::Quick) { speed += energy::SPEED_STEP; }
+        // Everything gets to act eventually.
+        max(speed, 1)
+    }
+
+    /// Return how much energy performing an action costs for this entity.
+    pub fn action_cost(self, action: ActionType) -> i32 {
+        let stats = self.stats();
+        let modifier = match action {
+            ActionType::Step | ActionType::ForceDoor => stats.move_cost,
+            ActionType::Melee | ActionType::Shoot => stats.attack_cost,
+            _ => 0,
+        };
+        max(action.base_cost() + modifier, energy::MIN_COST)
+    }
+
+    /// Accumulate energy for one tick.
+    fn gain_energy(self) {
+        let speed = self.speed();
+        world::with_mut(|w|
+            if let Some(e) = w.ecs.mu().energies().get(self) {
+                e.amount += speed;
+            }
+        );
+    }
+
+    /// Pay the energy cost of an action.
+    fn spend_energy(self, action: ActionType) {
+        let cost = self.action_cost(action);
+        world::with_mut(|w|
+            if let Some(e) = w.ecs.mu().energies().get(self) {
+                e.amount -= cost;
+            }
+        );
     }
 
     /// Return whether the entity is an awake non-player mob and should be
@@ -310,9 +417,12 @@ impl Entity {
     }
 
     pub fn melee(self, dir: Dir6) {
+        self.spend_energy(ActionType::Melee);
         let loc = self.location().expect("no location") + dir.to_v2();
+        msg::push(::Msg::Melee(self, dir));
         if let Some(e) = loc.mob_at() {
             let us = self.stats();
+            e.hit_effect(dir);
             e.damage(us.power + us.attack);
         }
     }
@@ -321,13 +431,19 @@ impl Entity {
         let stats = self.stats();
 
         if stats.ranged_range > 0 {
+            self.spend_energy(ActionType::Shoot);
             action::shoot(self.location().unwrap(), dir, stats.ranged_range, stats.ranged_power);
         }
     }
 
+    /// Spend a turn doing nothing.
+    pub fn wait(self) {
+        self.spend_energy(ActionType::Wait);
+    }
+
     pub fn hp(self) -> i32 {
         self.max_hp() - world::with(|w|
-            if let Some(health) = w.healths().get(self) {
+            if let Some(health) = w.ecs.healths().get(self) {
                 health.wounds
             } else {
                 0
@@ -340,7 +456,7 @@ impl Entity {
 
     pub fn is_wounded(self) -> bool {
         world::with(|w|
-            if let Some(health) = w.healths().get(self) {
+            if let Some(health) = w.ecs.healths().get(self) {
                 health.wounds > 0
             } else {
                 false
@@ -438,16 +554,23 @@ impl Entity {
     pub fn has_intrinsic(self, intrinsic: Intrinsic) -> bool {
         self.refresh_stats_cache();
         world::with(|w|
-            if let Some(&Some(ref stat)) = w.stats_caches().get(self) {
+            if let Some(&Some(ref stat)) = w.ecs.stats_caches().get(self) {
                 stat.intrinsics & intrinsic as u32 != 0
             } else {
                 false
             })
     }
 
+    /// Return whether the entity has the intrinsic as a passing status, like
+    /// one from equipment, instead of as part of its kind.
+    pub fn has_status(self, intrinsic: Intrinsic) -> bool {
+        self.has_intrinsic(intrinsic) &&
+            !self.parent().map_or(false, |p| p.base_stats().intrinsics & intrinsic as u32 != 0)
+    }
+
     pub fn set_intrinsic(self, intrinsic: Intrinsic) {
         world::with_mut(|w|
-            if let Some(x) = w.stats_mut().get(self) {
+            if let Some(x) = w.ecs.mu().stats().get(self) {
                 x.intrinsics |= intrinsic as u32;
             }
         );
@@ -456,7 +579,7 @@ impl Entity {
     pub fn power_level(self) -> i32 {
         self.refresh_stats_cache();
         world::with(|w|
-            if let Some(&Some(ref stat)) = w.stats_caches().get(self) { stat.power }
+            if let Some(&Some(ref stat)) = w.ecs.stats_caches().get(self) { stat.power }
             else { 0 })
     }
 
@@ -465,7 +588,7 @@ impl Entity {
     pub fn stats(self) -> Stats {
         self.refresh_stats_cache();
         world::with(|w|
-            if let Some(&Some(composite_stats)) = w.stats_caches().get(self) {
+            if let Some(&Some(composite_stats)) = w.ecs.stats_caches().get(self) {
                 composite_stats
             } else {
                 self.base_stats()
@@ -477,7 +600,7 @@ impl Entity {
     /// or fetch composite stats.
     fn base_stats(self) -> Stats {
         world::with(|w|
-            if let Some(s) = w.stats().get(self) {
+            if let Some(s) = w.ecs.stats().get(self) {
                 *s
             } else {
                 Default::default()
@@ -490,10 +613,10 @@ impl Entity {
     /// component.
     fn refresh_stats_cache(self) {
         // If stats cache doesn't exist, do nothing.
-        world::with(|w| if w.stats_caches().get(self).is_none() { return; });
+        world::with(|w| if w.ecs.stats_caches().get(self).is_none() { return; });
 
         // If cache is good, do nothing.
-        world::with(|w| if let Some(&Some(_)) = w.stats_caches().get(self) { return; });
+        world::with(|w| if let Some(&Some(_)) = w.ecs.stats_caches().get(self) { return; });
 
         let mut stats = self.base_stats();
         for &slot in [
@@ -511,24 +634,24 @@ impl Entity {
             }
         }
 
-        world::with_mut(|w| w.stats_caches_mut().insert(self, Some(stats)));
+        world::with_mut(|w| w.ecs.mu().stats_caches().insert(self, Some(stats)));
     }
 
     /// Mark cached stats dirty after changing base stats.
     fn dirty_stats_cache(self) {
         world::with_mut(|w|
-            w.stats_caches_mut().insert(self, None)
+            w.ecs.mu().stats_caches().insert(self, None)
         );
     }
 
 // Item methods ////////////////////////////////////////////////////////
 
-    pub fn is_item(self) -> bool { world::with(|w| w.items().get(self).is_some()) }
+    pub fn is_item(self) -> bool { world::with(|w| w.ecs.items().get(self).is_some()) }
 
     /// Is this an item that has an instant effect when stepped on.
     pub fn is_instant_item(self) -> bool {
         world::with(|w|
-            if let Some(item) = w.items().get(self) {
+            if let Some(item) = w.ecs.items().get(self) {
                 item.item_type == ItemType::Instant
             } else {
                 false
@@ -538,7 +661,7 @@ impl Entity {
 
     pub fn can_be_picked_up(self) -> bool {
         world::with(|w|
-            if let Some(item) = w.items().get(self) {
+            if let Some(item) = w.ecs.items().get(self) {
                 item.item_type != ItemType::Instant
             } else {
                 false
@@ -549,7 +672,7 @@ impl Entity {
     /// Preferred equipment slot for equippable items.
     pub fn equip_slots(self) -> Vec<Slot> {
         world::with(|w|
-            if let Some(item) = w.items().get(self) {
+            if let Some(item) = w.ecs.items().get(self) {
                 match item.item_type {
                     ItemType::MeleeWeapon => vec![Slot::Melee],
                     ItemType::RangedWeapon => vec![Slot::Ranged],
@@ -575,7 +698,7 @@ impl Entity {
 
     /// Top-level method called each frame to update the entity.
     pub fn update(self) {
-        if self.is_mob() && !self.is_player() && self.ticks_this_frame() {
+        if self.is_mob() && !self.is_player() && self.is_ready() {
             self.mob_ai();
         }
 
@@ -592,18 +715,23 @@ impl Entity {
                 }
             }
         }
+
+        if self.is_mob() {
+            self.gain_energy();
+        }
     }
 
-    fn brain_state(self) -> Option<BrainState> {
-        world::with(|w| w.brains().get(self).map(|b| b.state))
+    /// Return the AI state of a mob.
+    pub fn brain_state(self) -> Option<BrainState> {
+        world::with(|w| w.ecs.brains().get(self).map(|b| b.state))
     }
 
     fn set_brain_state(self, brain_state: BrainState) {
-        world::with_mut(|w| w.brains_mut().get(self).expect("no brains").state = brain_state );
+        world::with_mut(|w| w.ecs.mu().brains().get(self).expect("no brains").state = brain_state );
     }
 
     fn alignment(self) -> Option<Alignment> {
-        world::with(|w| w.brains().get(self).map(|b| b.alignment))
+        world::with(|w| w.ecs.brains().get(self).map(|b| b.alignment))
     }
 
     fn wake_up(self) {
@@ -612,46 +740,84 @@ impl Entity {
         }
     }
 
+    /// Return whether the mob spots another mob that is less than range
+    /// cells away. Mobs in the dark are only spotted from closer up, except
+    /// by night hunters.
+    fn notices(self, other: Entity, range: i32) -> bool {
+        match (self.distance_from(other), other.location()) {
+            (Some(d), Some(loc)) => {
+                let brightness = if self.has_intrinsic(Intrinsic::Nocturnal) { 1.0 }
+                    else { loc.brightness() };
+                (d as f32) < notice_range(range, brightness) && self.can_see(other)
+            }
+            _ => false
+        }
+    }
+
+    /// Return whether the mob is a night hunter that keeps out of the way
+    /// in daylight.
+    fn is_resting(self) -> bool {
+        self.has_intrinsic(Intrinsic::Nocturnal) && clock::is_daylight()
+    }
+
     /// AI routine for autonomous mobs.
     fn mob_ai(self) {
         assert!(self.is_mob());
         assert!(!self.is_player());
-        assert!(self.ticks_this_frame());
+        assert!(self.is_ready());
 
         if self.brain_state() == Some(BrainState::Asleep) {
+            if self.is_resting() {
+                self.wait();
+                return;
+            }
+
             if let Some(p) = action::player() {
-                // TODO: Line-of-sight, stealth concerns, other enemies than
-                // player etc.
+                // TODO: Stealth concerns, other enemies than player etc.
                 if let Some(d) = p.distance_from(self) {
-                    if d < 8 && rng::one_chance_in((d / 2) as u32 + 1) {
+                    if self.notices(p, 8) && rng::one_chance_in((d / 2) as u32 + 1) {
                         self.wake_up();
                     }
                 }
             }
 
+            // Night hunters go prowling in the dark.
+            if self.brain_state() == Some(BrainState::Asleep)
+                && self.has_intrinsic(Intrinsic::Nocturnal) && rng::one_chance_in(64) {
+                self.set_brain_state(BrainState::Roaming);
+            }
+
+            self.wait();
             return;
         }
 
         // Start hunting nearby enemy.
-        if self.brain_state() == Some(BrainState::Roaming) {
+        if self.brain_state() == Some(BrainState::Roaming) && !self.is_resting() {
             if let Some(p) = action::player() {
-                if !p.is_corpse() {
-                    if let Some(d) = p.distance_from(self) {
-                        // TODO: Line-of-sight
-                        if d < 6 {
-                            self.set_brain_state(BrainState::Hunting);
-                        }
-                    }
+                if !p.is_corpse() && self.notices(p, 6) {
+                    self.set_brain_state(BrainState::Hunting);
                 }
             }
         }
 
         if self.brain_state() == Some(BrainState::Roaming) {
             self.step(rng::gen());
-            if rng::one_chance_in(32) { self.set_brain_state(BrainState::Asleep); }
+            let sleep_chance = if self.is_resting() { 4 } else { 32 };
+            if rng::one_chance_in(sleep_chance) { self.set_brain_state(BrainState::Asleep); }
             return;
         }
 
+        // Night hunters give up the chase in daylight unless the enemy is
+        // right next to them.
+        if self.brain_state() == Some(BrainState::Hunting) && self.is_resting() {
+            let adjacent = action::player().and_then(|p| p.distance_from(self)) == Some(1);
+            if !adjacent && rng::one_chance_in(8) {
+                self.set_brain_state(BrainState::Roaming);
+                self.wait();
+                return;
+            }
+        }
+
         if self.brain_state() == Some(BrainState::Hunting) {
             // TODO: Fight other mobs than player.
             if let Some(p) = action::player() {
@@ -666,12 +832,10 @@ impl Entity {
                     if v.hex_dist() == 1 {
                         // Melee range, hit.
                         self.melee(Dir6::from_v2(v));
+                        return;
                     } else {
                         // Walk towards.
-                        let pathing_depth = 16;
-                        let pathing = Dijkstra::new(
-                            vec![p.location().expect("no location")], |&loc| !loc.blocks_walk(),
-                            pathing_depth);
+                        let pathing = self.hunting_map().expect("no hunting map");
 
                         let steps = pathing.sorted_neighbors(&loc);
                         if steps.len() > 0 {
@@ -679,10 +843,34 @@ impl Entity {
                         } else {
                             self.step(rng::gen());
                         }
+                        return;
                     }
                 }
             }
         }
+
+        // Didn't find anything to do.
+        self.wait();
+    }
+
+    /// Return the entity a hunting AI mob is going after.
+    pub fn ai_target(self) -> Option<Entity> {
+        if self.brain_state() != Some(BrainState::Hunting) { return None; }
+        // TODO: Fight other mobs than player.
+        match action::player() {
+            Some(p) if !p.is_corpse() && p.location().is_some() => Some(p),
+            _ => None
+        }
+    }
+
+    /// Build the pathing map a hunting AI mob uses to approach its target.
+    pub fn hunting_map(self) -> Option<Dijkstra<Location>> {
+        let pathing_depth = 16;
+        // XXX: Not using ai_target, mob_ai keeps going after a dead player
+        // for the turn it notices the death.
+        action::player().and_then(|p| p.location()).map(|target| {
+            Dijkstra::new(vec![target], |&loc| !loc.blocks_walk(), pathing_depth)
+        })
     }
 
     /// Return whether this thing wants to fight the other thing.
@@ -694,22 +882,35 @@ impl Entity {
     }
 
     /// Return any hostiles a mob has in its field of view up to sight_range.
+    /// Uses the cached field of view of the mob, so the range is capped by
+    /// the sight range of the current level and the view has the same shape
+    /// as the player's map memory view.
     pub fn is_threatened(self, sight_range: u32) -> Vec<Entity> {
-        let mut ret = Vec::new();
-        // XXX: Expensive.
         let loc = self.location().expect("no location");
-        let seen: Vec<Location> = HexFov::new(
-            |pt| (loc + pt).blocks_sight(), sight_range)
-            .map(|pt| loc + pt)
-            .collect();
-        for loc in seen.iter() {
-            if let Some(m) = loc.mob_at() {
-                if m.is_hostile_to(self) {
-                    ret.push(m);
-                }
-            }
+        // Only look up FOV if there are mobs around.
+        let nearby = world::with(|w| w.spatial.mobs_within(&w.ecs, loc, sight_range));
+        if nearby.iter().all(|&m| m == self) { return Vec::new(); }
+
+        let fov = fov::fov(self, loc);
+        world::with(|w| w.spatial.mobs_in_fov(&w.ecs, loc, sight_range, &fov)).into_iter()
+            .filter(|&m| m.is_hostile_to(self))
+            .collect()
+    }
+
+    /// Return whether the other entity is in this entity's field of view.
+    pub fn can_see(self, other: Entity) -> bool {
+        match (self.location(), other.location()) {
+            (Some(a), Some(b)) => fov::fov(self, a).contains(&b),
+            _ => false
+        }
+    }
+
+    /// Return whether a location is in this entity's field of view.
+    pub fn can_see_location(self, loc: Location) -> bool {
+        match self.location() {
+            Some(a) => fov::fov(self, a).contains(&loc),
+            None => false
         }
-        return ret;
     }
 
 // Callbacks ///////////////////////////////////////////////////////////
@@ -733,8 +934,11 @@ impl Entity {
     /// instaeffect items.
     pub fn on_step_on(self, collider: Entity) {
         if self.is_instant_item() {
-            let ability = world::with(|w| w.items().get(self).expect("no item").ability.clone());
-            ability.apply(Some(self), Place::In(collider, None));
+            let ability = world::with(|w| w.ecs.items().get(self).expect("no item").ability.clone());
+            if ability.apply(Some(self), Place::In(collider, None)) {
+                // Using the item takes time on top of stepping onto it.
+                collider.spend_energy(ActionType::Ability);
+            }
         }
 
         if collider.is_player() && self.is_corpse() && !self.has_intrinsic(Intrinsic::Robotic) {
@@ -746,23 +950,18 @@ impl Entity {
 // FOV and map memory //////////////////////////////////////////////////
 
     fn has_map_memory(self) -> bool {
-        world::with(|w| w.map_memories().get(self).is_some())
+        world::with(|w| w.ecs.map_memories().get(self).is_some())
     }
 
-    fn do_fov(self) {
-        let range = 12;
+    /// Recompute what the entity sees and add it to its map memory.
+    pub fn do_fov(self) {
         if let Some(loc) = self.location() {
             if self.has_map_memory() {
-                let seen: Vec<Location> = HexFov::new(
-                    |pt| (loc + pt).blocks_sight(), range)
-                    .fake_isometric()
-                    .map(|pt| loc + pt)
-                    .collect();
+                let seen = fov::fov(self, loc);
                 world::with_mut(|w| {
-                    if let Some(ref mut mm) = w.map_memories_mut().get(self) {
-                        mm.seen.clear();
-                        mm.seen.extend(seen.clone().into_iter());
-                        mm.remembered.extend(seen.iter().map(|&x| x));
+                    if let Some(ref mut mm) = w.ecs.mu().map_memories().get(self) {
+                        mm.seen = (*seen).clone();
+                        mm.remembered.union_with(&seen);
                     } else {
                         panic!("Couldn't bind map memory");
                     }
@@ -774,7 +973,7 @@ impl Entity {
     pub fn forget_map(self) {
         if self.has_map_memory() {
             world::with_mut(|w| {
-                if let Some(ref mut mm) = w.map_memories_mut().get(self) {
+                if let Some(ref mut mm) = w.ecs.mu().map_memories().get(self) {
                     mm.seen.clear();
                     mm.remembered.clear();
                 } else {
@@ -786,12 +985,12 @@ impl Entity {
 
 // Phage stuff /////////////////////////////////////////////////////////
 
-    pub fn is_terran(self) -> bool { world::with(|w| w.colonists().get(self).is_some()) }
+    pub fn is_terran(self) -> bool { world::with(|w| w.ecs.colonists().get(self).is_some()) }
 
     /// Self is the exposed phage form, not possessing a host.
     pub fn is_exposed_phage(self) -> bool {
         // Hacky, just check the icon index.
-        world::with(|w| w.descs().get(self).map_or(false, |d| d.icon == 40))
+        world::with(|w| w.ecs.descs().get(self).map_or(false, |d| d.icon == 40))
     }
 
     /// Make the phage possess the target host.
@@ -799,19 +998,29 @@ impl Entity {
         // Hairy ECS trickery to do polymorph.
         self.reparent(target.parent().unwrap());
 
+        let glow = action::find_prototype("phage")
+            .and_then(|p| world::with(|w| w.ecs.light_sources().get(p).map(|&x| x)));
+
         world::with_mut(|w| {
-            // Remove the local description for the previous form.
-            w.descs_mut().clear(self);
+            // Remove the local description, stats and light for the previous
+            // form.
+            w.ecs.clear_components(self.0, host_mask());
+            // The phage keeps glowing in hosts that give off no light.
+            if let Some(glow) = glow {
+                if w.ecs.light_sources().get(self).is_none() {
+                    w.ecs.mu().light_sources().insert(self, glow);
+                }
+            }
             // Get the prototype description from new parent, with
             // copy-on-write. Modify it for phage look.
             {
-                let desc = w.descs_mut().get(self).expect("No prototype desc");
+                let desc = w.ecs.mu().descs().get(self).expect("No prototype desc");
                 desc.name = "phage".to_string();
                 desc.color = color::CYAN;
             }
 
             // Central nervous system bypass.
-            w.brains_mut().insert(
+            w.ecs.mu().brains().insert(
                 self,
                 Brain {
                     state: BrainState::PlayerControl,
@@ -819,11 +1028,10 @@ impl Entity {
                 });
 
             // Tissue regeneration.
-            w.healths_mut().get(self).expect("no health").wounds = 0;
+            w.ecs.mu().healths().get(self).expect("no health").wounds = 0;
 
             // Adrenal overload.
-            w.stats_mut().clear(self);
-            w.stats_mut().get(self).expect("no stats").attack += 3;
+            w.ecs.mu().stats().get(self).expect("no stats").attack += 3;
         });
 
         self.dirty_stats_cache();
@@ -845,12 +1053,10 @@ impl Entity {
         self.reparent(action::find_prototype("phage").expect("No player prototype"));
 
         world::with_mut(|w| {
-            // Remove custom desc.
-            w.descs_mut().clear(self);
-            // Remove custom stats.
-            w.stats_mut().clear(self);
+            // Remove custom desc and stats.
+            w.ecs.clear_components(self.0, host_mask());
             // Go full health.
-            w.healths_mut().get(self).expect("no health").wounds = 0;
+            w.ecs.mu().healths().get(self).expect("no health").wounds = 0;
         });
         self.dirty_stats_cache();
 
@@ -860,9 +1066,117 @@ impl Entity {
     }
 }
 
+/// Components that are customized for the current phage host.
+fn host_mask() -> CompMask {
+    CompMask::new().with(Desc::id()).with(Stats::id()).with(LightSource::id())
+}
+
 // TODO: Put in library
 fn capitalize(string: &str) -> String {
     string.chars().enumerate()
         .map(|(i, c)| if i == 0 { c.to_uppercase().next().unwrap() } else { c })
         .collect::<String>()
 }
+
+/// Scale the distance things are noticed from by the brightness of their
+/// location. Things in unlit places are noticed from half the full range.
+fn notice_range(range: i32, brightness: f32) -> f32 {
+    let t = (brightness - light::AMBIENT) / (1.0 - light::AMBIENT);
+    range as f32 * lerp(0.5, 1.0, t.max(0.0).min(1.0))
+}
+
+#[cfg(test)]
+mod test {
+    use calx::{V2, HexGeom};
+    use world::{self, World};
+    use action;
+    use clock;
+    use fov;
+    use light;
+    use ability::Ability;
+    use components::Item;
+    use energy::ActionType;
+    use item::ItemType;
+    use prototype::Prototype;
+    use stats::Intrinsic;
+    use super::notice_range;
+
+    #[test]
+    fn test_notice_range() {
+        // Sleeping mobs wake up to things 8 cells away in full light.
+        assert_eq!(notice_range(8, 1.0), 8.0);
+        assert_eq!(notice_range(8, 2.0), 8.0);
+        // And from half of that in an unlit cave.
+        assert_eq!(notice_range(8, light::AMBIENT), 4.0);
+        assert_eq!(notice_range(8, 0.0), 4.0);
+        let dim = notice_range(8, 0.5);
+        assert!(dim > 4.0 && dim < 8.0);
+    }
+
+    #[test]
+    fn test_status() {
+        World::new(Some(1)).enter(|| {
+            let player = action::player().unwrap();
+            // The phage is fast by nature.
+            assert!(player.has_intrinsic(Intrinsic::Fast));
+            assert!(!player.has_status(Intrinsic::Fast));
+
+            player.set_intrinsic(Intrinsic::Slow);
+            player.dirty_stats_cache();
+            assert!(player.has_status(Intrinsic::Slow));
+        });
+    }
+
+    #[test]
+    fn test_nocturnal_notices_at_night() {
+        World::new(Some(1)).enter(|| {
+            world::with_mut(|w| w.flags.tick = clock::DAY_TICKS / 2);
+            fov::invalidate();
+            assert_eq!(clock::phase(), clock::Phase::Night);
+
+            // Too far to be seen in the dark by a day creature.
+            let player = action::player().unwrap();
+            let origin = player.location().unwrap();
+            assert!(5.0 >= notice_range(6, origin.brightness()));
+
+            let seen = fov::fov(player, origin);
+            let spots: Vec<_> = (-5..6).flat_map(|y| (-5..6).map(move |x| V2(x, y)))
+                .filter(|v| v.hex_dist() == 5)
+                .map(|v| origin + v)
+                .filter(|loc| seen.contains(loc) && !loc.blocks_walk())
+                .collect();
+            for loc in spots.into_iter() {
+                action::spawn_named("stalker", loc);
+                let stalker = loc.mob_at().unwrap();
+                if stalker.can_see(player) {
+                    assert!(stalker.notices(player, 6));
+                    return;
+                }
+                stalker.delete();
+            }
+            panic!("No spot to test with");
+        });
+    }
+
+    #[test]
+    fn test_instant_item_costs_energy() {
+        World::new(Some(1)).enter(|| {
+            let player = action::player().unwrap();
+            let medkit = Prototype::new(None)
+                .c(Item { item_type: ItemType::Instant, ability: Ability::HealInstant(5) })
+                .target
+                .clone_at(player.location().unwrap());
+
+            // Nothing happens to an unhurt mob.
+            let energy = player.energy();
+            medkit.on_step_on(player);
+            assert_eq!(player.energy(), energy);
+            assert!(medkit.is_alive());
+
+            world::with_mut(|w| w.ecs.mu().healths().get(player).unwrap().wounds = 2);
+            medkit.on_step_on(player);
+            assert_eq!(player.energy(), energy - player.action_cost(ActionType::Ability));
+            assert!(!medkit.is_alive());
+        });
+    }
+}
@@ -1,10 +1,10 @@
-use rand::{XorShiftRng, SeedableRng};
+use rand::SeedableRng;
 use location::Location;
-use calx::EncodeRng;
+use calx::XorShift128;
 use entity::Entity;
 use world;
 
-#[derive(RustcEncodable, RustcDecodable)]
+#[derive(Clone, RustcEncodable, RustcDecodable)]
 pub struct Flags {
     pub seed: u32,
     pub camera: Location,
@@ -12,7 +12,7 @@ pub struct Flags {
     pub player_acted: bool,
     /// Store the player entity here for fast access.
     pub player: Option<Entity>,
-    pub rng: EncodeRng<XorShiftRng>,
+    pub rng: XorShift128,
     pub terrans_left: u32,
 }
 
@@ -24,7 +24,9 @@ impl Flags {
             tick: 0,
             player_acted: false,
             player: None,
-            rng: SeedableRng::from_seed([seed, seed, seed, seed]),
+            // The generator state must not be all zero, mix a constant into
+            // one word so that seed 0 works too.
+            rng: SeedableRng::from_seed([seed, seed, seed, seed ^ 0x9E3779B9]),
             terrans_left: 0,
         }
     }
@@ -0,0 +1,146 @@
+/*! Cached fields of view for observer entities. */
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use calx::HexFov;
+use clock;
+use entity::Entity;
+use location::Location;
+use location_set::LocationSet;
+use terrain::TerrainType;
+use world;
+
+/// How far all observers can see in full light.
+pub static SIGHT_RANGE: u32 = 12;
+
+/// Field of view cache for the observers of a world.
+///
+/// Sight is blocked by terrain, and doors are opened by mobs standing in
+/// them. Whatever changes either of those must bump the occluder revision
+/// with `invalidate`, cache entries from older revisions are recomputed.
+///
+/// The cache is runtime data and is not saved.
+pub struct FovCache {
+    revision: u64,
+    entries: HashMap<Entity, Entry>,
+}
+
+struct Entry {
+    origin: Location,
+    revision: u64,
+    fov: Rc<LocationSet>,
+}
+
+impl FovCache {
+    pub fn new() -> FovCache {
+        FovCache {
+            revision: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Mark all the cached fields of view as out of date.
+    pub fn invalidate(&mut self) {
+        self.revision += 1;
+    }
+
+    /// Return the current occluder revision.
+    pub fn revision(&self) -> u64 { self.revision }
+
+    /// Drop the cache entry of an observer.
+    pub fn forget(&mut self, e: Entity) {
+        self.entries.remove(&e);
+    }
+
+    fn get(&self, e: Entity, origin: Location) -> Option<Rc<LocationSet>> {
+        match self.entries.get(&e) {
+            Some(x) if x.origin == origin && x.revision == self.revision => Some(x.fov.clone()),
+            _ => None
+        }
+    }
+
+    fn insert(&mut self, e: Entity, origin: Location, fov: Rc<LocationSet>) {
+        let revision = self.revision;
+        self.entries.insert(e, Entry {
+            origin: origin,
+            revision: revision,
+            fov: fov,
+        });
+    }
+}
+
+/// Return the field of view of an observer at a location.
+pub fn fov(e: Entity, origin: Location) -> Rc<LocationSet> {
+    if let Some(ret) = world::with_fov(|c| c.get(e, origin)) { return ret; }
+
+    let mut seen = LocationSet::new();
+    seen.extend(HexFov::new(|pt| (origin + pt).blocks_sight(), clock::sight_range())
+                .fake_isometric()
+                .map(|pt| origin + pt));
+    let ret = Rc::new(seen);
+    world::with_fov(|c| c.insert(e, origin, ret.clone()));
+    ret
+}
+
+/// Invalidate the fields of view of the current world.
+pub fn invalidate() {
+    world::with_fov(|c| c.invalidate());
+}
+
+/// Return the occluder revision of the current world. Other caches that
+/// depend on what blocks sight can use this to tell when they are stale.
+pub fn revision() -> u64 {
+    world::with_fov(|c| c.revision())
+}
+
+/// Call when a mob enters or leaves a location or stops being a mob there.
+/// Doors open and close depending on whether there is a mob in them.
+pub fn occupancy_changed(loc: Location) {
+    if world::with(|w| w.area.terrain(loc)) == TerrainType::Door {
+        invalidate();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use calx::Dir6;
+    use world::World;
+    use terrain::TerrainType;
+    use action;
+    use super::fov;
+
+    #[test]
+    fn test_door_occupancy() {
+        World::new(Some(1)).enter(|| {
+            let observer = action::player().unwrap();
+            // Find a closed door with free cells on both sides.
+            let doors: Vec<_> = ::world::with(|w| w.area.terrain.iter()
+                .filter(|&(_, &t)| t == TerrainType::Door)
+                .map(|(&loc, _)| loc)
+                .collect());
+            let (door, near, far, dir) = doors.into_iter()
+                .flat_map(|door| (0..6).map(move |i| {
+                    let dir = Dir6::from_int(i);
+                    (door, door + dir.to_v2(), door + Dir6::from_int(i + 3).to_v2(), dir)
+                }))
+                .find(|&(door, near, far, _)| {
+                    !door.has_mobs() && !near.has_mobs() && !far.has_mobs() &&
+                        !near.blocks_walk() && !far.blocks_walk()
+                })
+                .expect("No door to test with");
+
+            assert!(!fov(observer, near).contains(&far));
+
+            // A mob stepping into the door opens it.
+            action::spawn_named("colonist", far);
+            let mob = far.mob_at().unwrap();
+            mob.step(dir);
+            assert_eq!(mob.location(), Some(door));
+            assert!(fov(observer, near).contains(&far));
+
+            // And stepping out closes it again.
+            mob.step(Dir6::from_int(dir as i32 + 3));
+            assert!(!fov(observer, near).contains(&far));
+        });
+    }
+}
@@ -7,6 +7,8 @@ use {AreaSpec, Biome};
 use geomorph_data;
 use calx::Dir6;
 
+// The chunk templates are immutable data parsed once from geomorph_data, so
+// all worlds in the thread share the same cache.
 thread_local!(static CHUNK_CACHE: RefCell<Vec<Chunk>> = RefCell::new(vec![]));
 
 pub type Cells = HashMap<(i32, i32), TerrainType>;
@@ -58,6 +60,7 @@ fn legend(glyph: char) -> Option<TerrainType> {
         'o' => Some(Stone),
         'b' => Some(Barrel),
         'T' => Some(Table),
+        'L' => Some(Lamp),
         'I' => Some(Wall),
         '!' => Some(Stalagmite),
         ';' => Some(TallGrass),
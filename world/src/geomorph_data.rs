@@ -196,7 +196,7 @@ pub fn init_geomorphs() {
 .....#.....
 .....#.....
 .....#.....
-.....#.....
+....L#L....
 .....#.....
 .....#.....
 .....#.....
@@ -209,7 +209,7 @@ pub fn init_geomorphs() {
 ...........
 .#########.
 .#.......#.
-.#.......#.
+.#...L...#.
 ...........
 .#.......#.
 .#.......#.
@@ -220,7 +220,7 @@ pub fn init_geomorphs() {
 .#T...#.##.
 .###.##....
 .|....|....
-.#...b#....
+.#L..b#....
 .#....+....
 .#...b#....
 .|....|....
@@ -242,7 +242,7 @@ pub fn init_geomorphs() {
 ..###+###..
 ..#.....#..
 ..#.....#..
-..|.....|..
+..|..L..|..
 ..#.....#..
 ..###|###..
 ...........
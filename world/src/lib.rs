@@ -5,13 +5,19 @@ extern crate rustc_serialize;
 extern crate num;
 extern crate vec_map;
 extern crate calx;
+#[macro_use] extern crate calx_ecs;
+extern crate bincode;
+extern crate flate2;
 
+pub use energy::{ActionType};
 pub use entity::{Entity};
 pub use flags::{camera, set_camera, get_tick};
 pub use location::{Location, Chart, Unchart};
 pub use msg::{pop_msg};
+pub use replay::{Replay};
+pub use stats::{Intrinsic};
 pub use terrain::{TerrainType};
-pub use world::{init_world, load, save};
+pub use world::{init_world, load, save, World};
 
 macro_rules! msg(
     ($($arg:tt)*) => ( ::msg::push(::Msg::Text(format!($($arg)*))))
@@ -34,18 +40,23 @@ pub mod item;
 
 mod ability;
 mod area;
-mod component_ref;
+mod clock;
 mod ecs;
+mod energy;
 mod entity;
 mod flags;
+mod fov;
 mod geomorph;
 mod geomorph_data;
+mod light;
 mod location;
 mod location_set;
 mod mapgen;
 mod msg;
 mod prototype;
+mod replay;
 mod rng;
+mod savegame;
 mod spatial;
 mod spawn;
 mod stats;
@@ -106,26 +117,65 @@ pub enum Msg {
     // TODO: Type of effect.
     Explosion(Location),
     Damage(Entity),
+    /// Mob attacked the cell in the given direction in melee.
+    Melee(Entity, calx::Dir6),
     Gib(Location),
     Beam(Location, Location),
-    /// Beam hitting a wall.
+    /// Beam hitting a wall or a robot.
     Sparks(Location),
+    /// Smoke rising from a location.
+    Smoke(Location),
+    /// Terran hit from the given direction.
+    Blood(Location, calx::Dir6),
+    /// Alien hit from the given direction.
+    Acid(Location, calx::Dir6),
 }
 
-/// Light level value.
+/// Light level value. Colored light has separate levels for the red, green
+/// and blue channels, 1.0 is full light.
 #[derive(Copy, Clone, Debug, RustcEncodable, RustcDecodable)]
 pub struct Light {
-    lum: f32,
+    r: f32,
+    g: f32,
+    b: f32,
 }
 
 impl Light {
+    /// White light.
     pub fn new(lum: f32) -> Light {
         assert!(lum >= 0.0 && lum <= 2.0);
-        Light { lum: lum }
+        Light { r: lum, g: lum, b: lum }
+    }
+
+    /// Light with the hue of a color.
+    pub fn colored(color: calx::Rgba, lum: f32) -> Light {
+        assert!(lum >= 0.0 && lum <= 2.0);
+        Light { r: color.r * lum, g: color.g * lum, b: color.b * lum }
+    }
+
+    /// Perceived brightness of the light.
+    pub fn lum(&self) -> f32 {
+        self.r * 0.2126 + self.g * 0.7152 + self.b * 0.0722
     }
 
     pub fn apply(&self, color: calx::Rgba) -> calx::Rgba {
-        let darkness_color = calx::Rgba::new(0.05, 0.10, 0.25, color.a);
-        calx::lerp(color * darkness_color, color, self.lum)
+        let dark = color * calx::Rgba::new(0.05, 0.10, 0.25, color.a);
+        calx::Rgba::new(
+            calx::lerp(dark.r, color.r, self.r),
+            calx::lerp(dark.g, color.g, self.g),
+            calx::lerp(dark.b, color.b, self.b),
+            color.a)
+    }
+}
+
+impl std::ops::Add<Light> for Light {
+    type Output = Light;
+    fn add(self, rhs: Light) -> Light {
+        // Cap at the same maximum as the constructors.
+        Light {
+            r: (self.r + rhs.r).min(2.0),
+            g: (self.g + rhs.g).min(2.0),
+            b: (self.b + rhs.b).min(2.0),
+        }
     }
 }
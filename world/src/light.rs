@@ -0,0 +1,155 @@
+/*! Light sources and the light they cast on the map. */
+
+use std::collections::HashMap;
+use calx::{color, Rgba, HexFov, HexGeom};
+use components::LightSource;
+use entity::Entity;
+use location::Location;
+use fov;
+use flags;
+use world;
+use Light;
+
+/// Light level of the underground cells no light source reaches.
+pub static AMBIENT: f32 = 0.1;
+
+/// How many ticks a muzzle flash stays lit.
+static FLASH_TICKS: u64 = 2;
+
+static FLASH_RADIUS: u32 = 3;
+
+/// Light map cache for the current world.
+///
+/// The terrain lights only change when the occluders do, so they are kept
+/// until the field of view revision changes. Mob lights and flashes move
+/// around and are added on top of the terrain lights once per game tick.
+///
+/// The cache is runtime data and is not saved.
+pub struct LightCache {
+    /// Field of view revision the terrain light map was computed for.
+    terrain_revision: Option<u64>,
+    terrain_map: HashMap<Location, Light>,
+    /// Revision and tick the full light map was computed for.
+    key: Option<(u64, u64)>,
+    map: HashMap<Location, Light>,
+    /// Muzzle flashes and the ticks they went off on.
+    flashes: Vec<(Location, u64)>,
+}
+
+impl LightCache {
+    pub fn new() -> LightCache {
+        LightCache {
+            terrain_revision: None,
+            terrain_map: HashMap::new(),
+            key: None,
+            map: HashMap::new(),
+            flashes: Vec::new(),
+        }
+    }
+}
+
+/// Return the light cast on a location by the light sources. Doesn't
+/// include the ambient light.
+pub fn light_at(loc: Location) -> Option<Light> {
+    let revision = fov::revision();
+    let tick = flags::get_tick();
+
+    if world::with_lights(|c| c.terrain_revision) != Some(revision) {
+        let mut map = HashMap::new();
+        for (origin, color, radius) in terrain_lights().into_iter() {
+            cast(&mut map, origin, color, radius);
+        }
+        let mut map = Some(map);
+        world::with_lights(|c| {
+            c.terrain_revision = Some(revision);
+            c.terrain_map = map.take().unwrap();
+        });
+    }
+
+    if world::with_lights(|c| c.key) != Some((revision, tick)) {
+        let mut map = world::with_lights(|c| c.terrain_map.clone());
+        for (origin, s) in mob_lights().into_iter() {
+            cast(&mut map, origin, s.color, s.radius);
+        }
+
+        let flashes = world::with_lights(|c| {
+            c.flashes.retain(|&(_, t)| t + FLASH_TICKS > tick);
+            c.flashes.clone()
+        });
+        for &(origin, _) in flashes.iter() {
+            cast(&mut map, origin, color::LIGHTYELLOW, FLASH_RADIUS);
+        }
+
+        let mut map = Some(map);
+        world::with_lights(|c| {
+            c.key = Some((revision, tick));
+            c.map = map.take().unwrap();
+        });
+    }
+
+    world::with_lights(|c| c.map.get(&loc).cloned())
+}
+
+/// Light up the surroundings of a location for a moment.
+pub fn flash(loc: Location) {
+    let tick = flags::get_tick();
+    world::with_lights(|c| {
+        c.flashes.push((loc, tick));
+        // Recompute the light map even if the tick hasn't changed.
+        c.key = None;
+    });
+}
+
+/// Spread the light from a source to the cells it can reach.
+fn cast(map: &mut HashMap<Location, Light>, origin: Location, color: Rgba, radius: u32) {
+    // The FOV iterator can give some cells more than once, don't light them
+    // twice.
+    let mut lit = HashMap::new();
+    for pt in HexFov::new(|pt| (origin + pt).blocks_sight(), radius).fake_isometric() {
+        lit.insert(origin + pt, pt.hex_dist());
+    }
+
+    for (loc, dist) in lit.into_iter() {
+        let lum = 1.0 - dist as f32 / (radius + 1) as f32;
+        let light = Light::colored(color, lum);
+        let total = match map.get(&loc) {
+            Some(&x) => x + light,
+            None => light,
+        };
+        map.insert(loc, total);
+    }
+}
+
+fn terrain_lights() -> Vec<(Location, Rgba, u32)> {
+    world::with(|w| w.area.terrain.iter()
+        .filter_map(|(&loc, &t)| t.light_source().map(|(color, radius)| (loc, color, radius)))
+        .collect())
+}
+
+fn mob_lights() -> Vec<(Location, LightSource)> {
+    world::with(|w| w.ecs.light_sources().entities()).into_iter()
+        .map(Entity)
+        // Bioluminescence fades and headlamps break when the mob dies.
+        .filter(|e| !e.is_corpse())
+        .filter_map(|e| e.location().map(|loc| {
+            (loc, world::with(|w| *w.ecs.light_sources().get(e).unwrap()))
+        }))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use world::World;
+    use flags;
+    use super::{light_at, flash};
+
+    #[test]
+    fn test_flash() {
+        World::new(Some(1)).enter(|| {
+            let loc = flags::camera();
+            let before = light_at(loc).map_or(0.0, |x| x.lum());
+            flash(loc);
+            assert!(light_at(loc).unwrap().lum() > before);
+        });
+    }
+}
@@ -4,8 +4,8 @@ use entity::Entity;
 use terrain::TerrainType;
 use world;
 use action;
-use flags;
-use ecs::{ComponentAccess};
+use light;
+use clock;
 use {Light, Biome};
 
 /// Unambiguous location in the game world.
@@ -43,10 +43,8 @@ impl Location {
 
     pub fn blocks_walk(&self) -> bool {
         if self.terrain().blocks_walk() { return true; }
-        if self.entities().iter().any(|e| e.blocks_walk()) {
-            return true;
-        }
-        false
+        // Only mobs block walking.
+        self.has_mobs()
     }
 
     pub fn entities(&self) -> Vec<Entity> {
@@ -66,22 +64,17 @@ impl Location {
         self.entities().iter().find(|e| e.can_be_picked_up()).map(|&x| x)
     }
 
-    pub fn has_entities(&self) -> bool { !self.entities().is_empty() }
-
-    pub fn has_mobs(&self) -> bool {
-        self.entities().iter().any(|e| e.is_mob())
+    pub fn has_entities(&self) -> bool {
+        world::with(|w| !w.spatial.at(*self).is_empty())
     }
 
+    pub fn has_mobs(&self) -> bool { self.mob_at().is_some() }
+
     /// Returns the mob at the given location, if any. The assumption is that
     /// there is always a single primary mob in locations that contain
     /// multiple mobs that can be selected as the return value.
     pub fn mob_at(&self) -> Option<Entity> {
-        for &e in self.entities().iter() {
-            if e.is_mob() {
-                return Some(e);
-            }
-        }
-        None
+        world::with(|w| w.spatial.mob_at(&w.ecs, *self))
     }
 
     /// Vector pointing from this location into the other one if the locations
@@ -106,7 +99,7 @@ impl Location {
     pub fn fov_status(&self) -> Option<::FovStatus> {
         if let Some(p) = action::player() {
             match world::with(|w| {
-                if let Some(ref mm) = w.map_memories().get(p) {
+                if let Some(ref mm) = w.ecs.map_memories().get(p) {
                     Ok (if mm.seen.contains(self) {
                         Some(::FovStatus::Seen)
                     } else if mm.remembered.contains(self) {
@@ -130,26 +123,27 @@ impl Location {
     pub fn name(&self) -> String {
         match action::current_depth() {
             0 => "Limbo".to_string(),
-            1 => "Outside".to_string(),
+            1 => format!("Outside, {}", clock::phase().name()),
             n => format!("Basement {}", n - 1)
         }
     }
 
-    /// Light level for the location.
+    /// Light from the ambient light and the light sources.
     pub fn light(&self) -> Light {
-        if action::current_depth() == 1 {
-            // Topside, full light.
-            return Light::new(1.0);
-        }
-        if self.terrain().is_luminous() {
-            return Light::new(1.0);
+        let ambient = if action::current_depth() == 1 {
+            clock::daylight()
+        } else {
+            Light::new(light::AMBIENT)
+        };
+        match light::light_at(*self) {
+            Some(x) => ambient + x,
+            None => ambient
         }
+    }
 
-        if let Some(d) = self.distance_from(flags::camera()) {
-            let lum = 0.8 - d as f32 / 10.0;
-            return Light::new(if lum >= 0.0 { lum } else { 0.0 });
-        }
-        return Light::new(1.0);
+    /// How well things in the location can be seen, 1.0 is full light.
+    pub fn brightness(&self) -> f32 {
+        self.light().lum()
     }
 
     pub fn biome(&self) -> Biome {
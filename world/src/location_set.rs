@@ -64,4 +64,11 @@ impl LocationSet {
             self.insert(i);
         }
     }
+
+    /// Add all the locations in the other set to this one.
+    pub fn union_with(&mut self, other: &LocationSet) {
+        for (&index, &bits) in other.chunks.iter() {
+            *self.chunks.entry(index).or_insert(0) |= bits;
+        }
+    }
 }
@@ -1,18 +1,15 @@
-use std::cell::RefCell;
+use world;
 
-thread_local!(static MSG_QUEUE: RefCell<Vec<::Msg>> = RefCell::new(vec![]));
-
-/// Pop and return the oldest message left in the message queue.
+/// Pop and return the oldest message left in the message queue of the
+/// current world.
 pub fn pop_msg() -> Option<::Msg> {
-    MSG_QUEUE.with(|q| {
-        let empty = q.borrow().is_empty();
-        if empty { None } else { Some(q.borrow_mut().remove(0)) }
-    })
+    world::with_msgs(|q| if q.is_empty() { None } else { Some(q.remove(0)) })
 }
 
-/// Insert a new message to the back of the message queue.
+/// Insert a new message to the back of the message queue of the current
+/// world.
 pub fn push(msg: ::Msg) {
-    // XXX: Haven't figured out how to move values into Key::with blocks, so
+    // XXX: Haven't figured out how to move values into FnMut closures, so
     // need to use clone here.
-    MSG_QUEUE.with(|q| q.borrow_mut().push(msg.clone()));
+    world::with_msgs(|q| q.push(msg.clone()));
 }
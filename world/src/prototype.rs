@@ -1,13 +1,18 @@
 use std::default::Default;
-use calx::color::*;
-use ecs::{Component, ComponentAccess};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use calx::{self, Rgba};
+use calx_ecs::Component;
+use ecs::Ecs;
 use entity::{Entity};
-use components::{Spawn, Category, IsPrototype};
-use components::{Desc, MapMemory, Health};
-use components::{Brain, BrainState, Alignment, Colonist};
-use stats::{Stats};
-use stats::Intrinsic::*;
-use Biome::*;
+use components::{Spawn, Category};
+use components::{Desc, MapMemory, Health, Energy};
+use components::{Brain, BrainState, Alignment, Colonist, LightSource};
+use stats::{Stats, Intrinsic};
+use Biome;
 use world;
 
 #[derive(Copy, Clone)]
@@ -18,116 +23,384 @@ pub struct Prototype {
 impl Prototype {
     pub fn new(parent: Option<Entity>) -> Prototype {
         Prototype {
-            target: world::with_mut(|w| {
-                let e = w.ecs.new_entity(parent);
-                w.prototypes_mut().insert(e, IsPrototype);
-                e
-            }),
+            target: world::with_mut(|w| Entity(w.ecs.make_prototype(parent.map(|p| p.0)))),
         }
     }
 
     /// Add a component to the prototype.
-    pub fn c<C: Component>(self, component: C) -> Prototype {
-        component.add_to(self.target);
+    pub fn c<C: Component<Ecs>>(self, component: C) -> Prototype {
+        let mut component = Some(component);
+        world::with_mut(|w| component.take().unwrap().add_to(&mut w.ecs, self.target.0));
         self
     }
 }
 
+/// Name of the built-in definition file for error messages.
+static BUILTIN_FILE: &'static str = "prototypes.txt";
 
 /// Only call at world init!
 pub fn init() {
-    let base_mob = Prototype::new(None)
-        .c(Brain { state: BrainState::Asleep, alignment: Alignment::Indigenous })
-        .c({let h: Health = Default::default(); h})
-        .target;
-
-    let colonist = Prototype::new(None)
-        .c(Brain { state: BrainState::Asleep, alignment: Alignment::Colonist })
-        .c({let h: Health = Default::default(); h})
-        .target;
-
-    // Init the prototypes
-
-    // Player
-    Prototype::new(Some(base_mob))
-        .c(Brain { state: BrainState::PlayerControl, alignment: Alignment::Phage })
-        .c(Desc::new("phage", 40, CYAN))
-        .c(Stats::new(2, &[Fast]).attack(3))
-        .c(MapMemory::new())
-        ;
-
-    // Enemies
-
-    // Indigenous
-    Prototype::new(Some(base_mob))
-        .c(Desc::new("hopper", 32, YELLOW))
-        .c(Stats::new(4, &[]).protection(-2))
-        .c(Spawn::new(Category::Mob).commonness(2000))
-        ;
-
-    Prototype::new(Some(base_mob))
-        .c(Desc::new("stalker", 60, ORCHID))
-        .c(Stats::new(4, &[]))
-        .c(Spawn::new(Category::Mob))
-        ;
-
-    Prototype::new(Some(base_mob))
-        .c(Desc::new("metawasp", 58, ORANGERED))
-        // Glass cannon
-        .c(Stats::new(4, &[Fast]).protection(-1).attack(2))
-        .c(Spawn::new(Category::Mob).commonness(600))
-        ;
-
-    // Can open doors, good for base attack.
-    Prototype::new(Some(base_mob))
-        .c(Desc::new("space monkey", 46, LAWNGREEN))
-        .c(Stats::new(6, &[Hands]))
-        .c(Spawn::new(Category::Mob).commonness(600))
-        ;
-
-    Prototype::new(Some(base_mob))
-        .c(Desc::new("rumbler", 38, OLIVE))
-        .c(Stats::new(8, &[Slow]))
-        .c(Spawn::new(Category::Mob).commonness(100))
-        ;
-
-    // Colonist enemies
-
-    Prototype::new(Some(colonist))
-        .c(Desc::new("colonist", 34, DARKORANGE))
-        .c(Stats::new(6, &[Hands]))
-        .c(Spawn::new(Category::Mob).biome(Base))
-        .c(Colonist::new())
-        ;
-
-    // TODO: Ranged attack
-    Prototype::new(Some(colonist))
-        .c(Desc::new("marine", 36, DARKOLIVEGREEN))
-        .c(Stats::new(8, &[Hands]))
-        .c(Spawn::new(Category::Mob).biome(Base).commonness(400))
-        .c(Colonist::new())
-        ;
-
-    // TODO: Ranged attack
-    Prototype::new(Some(colonist))
-        .c(Desc::new("cyber controller", 42, LIGHTSLATEGRAY))
-        .c(Stats::new(12, &[Slow, Hands, Robotic]))
-        .c(Colonist::new())
-        .c(Spawn::new(Category::Mob).biome(Base).commonness(40))
-        ;
-
-    // Dogs count as colonists because of terran DNA
-    Prototype::new(Some(colonist))
-        .c(Desc::new("dog", 44, OLIVE))
-        .c(Stats::new(4, &[]))
-        .c(Spawn::new(Category::Mob).biome(Base))
-        .c(Colonist::new())
-        ;
-
-    // Robots don't count as colonists, being completely inorganic
-    Prototype::new(Some(colonist))
-        .c(Desc::new("robot", 62, SILVER))
-        .c(Stats::new(6, &[Hands, Robotic, Slow]))
-        .c(Spawn::new(Category::Mob).biome(Base).commonness(200))
-        ;
+    let mut named = HashMap::new();
+
+    let defs = parse(BUILTIN_FILE, include_str!("../assets/prototypes.txt"))
+        .and_then(|defs| check(BUILTIN_FILE, &defs, &named).map(|_| defs));
+    match defs {
+        Ok(defs) => build(defs, &mut named),
+        Err(e) => panic!("Bad prototype data: {}", e),
+    }
+
+    // A broken mod shouldn't stop the game, just leave it out and tell the
+    // player in the message log.
+    for path in mod_files().iter() {
+        let filename = path.to_string_lossy().into_owned();
+        let defs = read_file(path)
+            .and_then(|text| parse(&filename, &text))
+            .and_then(|defs| check(&filename, &defs, &named).map(|_| defs));
+        match defs {
+            Ok(defs) => build(defs, &mut named),
+            Err(e) => msgln!("Skipping prototype mod: {}", e),
+        }
+    }
+}
+
+/// List the mod definition files in load order.
+fn mod_files() -> Vec<::std::path::PathBuf> {
+    let mut ret = Vec::new();
+    let dir = calx::app_data_path("phage").join("mods");
+    if let Ok(entries) = fs::read_dir(&dir) {
+        for entry in entries {
+            if let Ok(entry) = entry {
+                let path = entry.path();
+                if path.extension().map_or(false, |x| x == "txt") {
+                    ret.push(path);
+                }
+            }
+        }
+    }
+    ret.sort();
+    ret
+}
+
+fn read_file(path: &Path) -> Result<String, String> {
+    let mut text = String::new();
+    try!(File::open(path)
+         .and_then(|mut f| f.read_to_string(&mut text))
+         .map_err(|e| format!("{}: {}", path.to_string_lossy(), e)));
+    Ok(text)
+}
+
+/// A prototype definition parsed from a data file.
+#[derive(Clone, Debug)]
+pub struct ProtoDef {
+    pub name: String,
+    /// Line of the entry header, for error messages.
+    line: usize,
+    parent: Option<String>,
+    icon: Option<usize>,
+    color: Option<Rgba>,
+    glyph: Option<char>,
+    brain: Option<Brain>,
+    stats: Option<Stats>,
+    category: Option<Category>,
+    biome: Option<Biome>,
+    commonness: Option<u32>,
+    min_depth: Option<i32>,
+    colonist: bool,
+    map_memory: bool,
+    light: Option<LightSource>,
+}
+
+impl ProtoDef {
+    fn new(name: &str, line: usize) -> ProtoDef {
+        ProtoDef {
+            name: name.to_string(),
+            line: line,
+            parent: None,
+            icon: None,
+            color: None,
+            glyph: None,
+            brain: None,
+            stats: None,
+            category: None,
+            biome: None,
+            commonness: None,
+            min_depth: None,
+            colonist: false,
+            map_memory: false,
+            light: None,
+        }
+    }
+
+    fn stats(&mut self) -> &mut Stats {
+        if self.stats.is_none() { self.stats = Some(Default::default()); }
+        self.stats.as_mut().unwrap()
+    }
+
+    /// Set a value from a "key: value" line.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "parent" => { self.parent = Some(value.to_string()); }
+            "icon" => { self.icon = Some(try!(parse_num(key, value))); }
+            "color" => {
+                self.color = Some(try!(Rgba::from_str(value)
+                    .map_err(|_| format!("Unknown color '{}'", value))));
+            }
+            "glyph" => {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => { self.glyph = Some(c); }
+                    _ => return Err(format!("Expected a single character glyph, got '{}'", value)),
+                }
+            }
+            "brain" => {
+                let mut words = value.split_whitespace();
+                let alignment = match words.next() {
+                    Some("berserk") => Alignment::Berserk,
+                    Some("phage") => Alignment::Phage,
+                    Some("indigenous") => Alignment::Indigenous,
+                    Some("colonist") => Alignment::Colonist,
+                    Some(x) => return Err(format!("Unknown alignment '{}'", x)),
+                    None => return Err("Missing alignment".to_string()),
+                };
+                let state = match words.next() {
+                    Some("asleep") | None => BrainState::Asleep,
+                    Some("hunting") => BrainState::Hunting,
+                    Some("roaming") => BrainState::Roaming,
+                    Some("player") => BrainState::PlayerControl,
+                    Some(x) => return Err(format!("Unknown brain state '{}'", x)),
+                };
+                if let Some(x) = words.next() {
+                    return Err(format!("Unexpected '{}' after brain state", x));
+                }
+                self.brain = Some(Brain { state: state, alignment: alignment });
+            }
+            "power" => { self.stats().power = try!(parse_num(key, value)); }
+            "attack" => { self.stats().attack = try!(parse_num(key, value)); }
+            "protection" => { self.stats().protection = try!(parse_num(key, value)); }
+            "mana" => { self.stats().mana = try!(parse_num(key, value)); }
+            "ranged_range" => { self.stats().ranged_range = try!(parse_num(key, value)); }
+            "ranged_power" => { self.stats().ranged_power = try!(parse_num(key, value)); }
+            "speed" => { self.stats().speed = try!(parse_num(key, value)); }
+            "move_cost" => { self.stats().move_cost = try!(parse_num(key, value)); }
+            "attack_cost" => { self.stats().attack_cost = try!(parse_num(key, value)); }
+            "intrinsics" => {
+                let mut intr = 0u32;
+                for word in value.split_whitespace() {
+                    intr = intr | (try!(parse_intrinsic(word)) as u32);
+                }
+                self.stats().intrinsics = intr;
+            }
+            "spawn" => {
+                self.category = Some(match value {
+                    "mob" => Category::Mob,
+                    "consumable" => Category::Consumable,
+                    "equipment" => Category::Equipment,
+                    "item" => Category::Item,
+                    x => return Err(format!("Unknown spawn category '{}'", x)),
+                });
+            }
+            "biome" => {
+                self.biome = Some(match value {
+                    "overland" => Biome::Overland,
+                    "base" => Biome::Base,
+                    "anywhere" => Biome::Anywhere,
+                    x => return Err(format!("Unknown biome '{}'", x)),
+                });
+            }
+            "commonness" => {
+                let n = try!(parse_num(key, value));
+                if n == 0 { return Err("Commonness must be positive".to_string()); }
+                self.commonness = Some(n);
+            }
+            "min_depth" => { self.min_depth = Some(try!(parse_num(key, value))); }
+            "colonist" => { self.colonist = try!(parse_bool(key, value)); }
+            "map_memory" => { self.map_memory = try!(parse_bool(key, value)); }
+            "light" => {
+                let mut words = value.split_whitespace();
+                let radius = try!(parse_num(key, words.next().unwrap_or("")));
+                let color = match words.next() {
+                    Some(x) => try!(Rgba::from_str(x).map_err(|_| format!("Unknown color '{}'", x))),
+                    None => return Err("Missing light color".to_string()),
+                };
+                if let Some(x) = words.next() {
+                    return Err(format!("Unexpected '{}' after light color", x));
+                }
+                self.light = Some(LightSource::new(color, radius));
+            }
+            x => return Err(format!("Unknown key '{}'", x)),
+        }
+        Ok(())
+    }
+
+    /// Check that the values make sense together.
+    fn validate(&self) -> Result<(), String> {
+        if self.icon.is_some() != self.color.is_some() {
+            return Err("Icon and color must be given together".to_string());
+        }
+        // The glyph is stored in the description along with the icon.
+        if self.glyph.is_some() && self.icon.is_none() {
+            return Err("Glyph given without an icon".to_string());
+        }
+        if self.category.is_none() &&
+            (self.biome.is_some() || self.commonness.is_some() || self.min_depth.is_some()) {
+            return Err("Spawn parameters given without a spawn category".to_string());
+        }
+        Ok(())
+    }
+
+    fn spawn(&self) -> Option<Spawn> {
+        self.category.map(|c| {
+            let mut ret = Spawn::new(c);
+            if let Some(b) = self.biome { ret = ret.biome(b); }
+            if let Some(n) = self.commonness { ret = ret.commonness(n); }
+            if let Some(d) = self.min_depth { ret = ret.depth(d); }
+            ret
+        })
+    }
+}
+
+fn parse_num<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    T::from_str(value).map_err(|_| format!("Bad number '{}' for {}", value, key))
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "yes" | "true" => Ok(true),
+        "no" | "false" => Ok(false),
+        _ => Err(format!("Expected yes or no for {}, got '{}'", key, value)),
+    }
+}
+
+fn parse_intrinsic(word: &str) -> Result<Intrinsic, String> {
+    match word {
+        "slow" => Ok(Intrinsic::Slow),
+        "fast" => Ok(Intrinsic::Fast),
+        "quick" => Ok(Intrinsic::Quick),
+        "hands" => Ok(Intrinsic::Hands),
+        "dead" => Ok(Intrinsic::Dead),
+        "robotic" => Ok(Intrinsic::Robotic),
+        "nocturnal" => Ok(Intrinsic::Nocturnal),
+        x => Err(format!("Unknown intrinsic '{}'", x)),
+    }
+}
+
+/// Parse prototype definitions from the text of a data file. The filename is
+/// only used in error messages.
+pub fn parse(filename: &str, text: &str) -> Result<Vec<ProtoDef>, String> {
+    let mut ret: Vec<ProtoDef> = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_num = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("#") { continue; }
+
+        if line.starts_with("[") {
+            if !line.ends_with("]") || line.len() < 3 {
+                return Err(format!("{}:{}: Bad entry header '{}'", filename, line_num, line));
+            }
+            ret.push(ProtoDef::new(line[1..line.len() - 1].trim(), line_num));
+            continue;
+        }
+
+        let def = match ret.last_mut() {
+            Some(def) => def,
+            None => return Err(format!("{}:{}: Value outside an entry", filename, line_num)),
+        };
+
+        let result = match line.find(':') {
+            Some(idx) => def.set(line[..idx].trim(), line[idx + 1..].trim()),
+            None => Err(format!("Expected 'key: value', got '{}'", line)),
+        };
+        if let Err(e) = result {
+            return Err(format!("{}:{}: [{}] {}", filename, line_num, def.name, e));
+        }
+    }
+
+    for def in ret.iter() {
+        if let Err(e) = def.validate() {
+            return Err(format!("{}:{}: [{}] {}", filename, def.line, def.name, e));
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Check names and parent references against the already loaded prototypes.
+/// Parents must be defined before the entries that use them.
+fn check(filename: &str, defs: &[ProtoDef], named: &HashMap<String, Entity>) -> Result<(), String> {
+    for (i, def) in defs.iter().enumerate() {
+        let is_defined = |name: &str| {
+            named.contains_key(name) || defs[..i].iter().any(|d| d.name == name)
+        };
+
+        if is_defined(&def.name[..]) {
+            return Err(format!("{}:{}: [{}] Duplicate entry", filename, def.line, def.name));
+        }
+
+        if let Some(ref parent) = def.parent {
+            if !is_defined(&parent[..]) {
+                return Err(format!("{}:{}: [{}] Unknown parent '{}'",
+                                   filename, def.line, def.name, parent));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Create the prototype entities for checked definitions.
+fn build(defs: Vec<ProtoDef>, named: &mut HashMap<String, Entity>) {
+    for def in defs.into_iter() {
+        let parent = def.parent.as_ref().map(|p| *named.get(p).unwrap());
+        let mut proto = Prototype::new(parent);
+
+        if let (Some(icon), Some(color)) = (def.icon, def.color) {
+            let mut desc = Desc::new(&def.name, icon, color);
+            // Glyphs are inherited like the components.
+            desc.glyph = def.glyph.or_else(|| parent.and_then(|p| p.glyph()));
+            proto = proto.c(desc);
+        }
+        if let Some(brain) = def.brain {
+            proto = proto
+                .c(brain)
+                .c({let h: Health = Default::default(); h})
+                .c({let e: Energy = Default::default(); e});
+        }
+        if let Some(stats) = def.stats { proto = proto.c(stats); }
+        if let Some(spawn) = def.spawn() { proto = proto.c(spawn); }
+        if def.colonist { proto = proto.c(Colonist::new()); }
+        if def.map_memory { proto = proto.c(MapMemory::new()); }
+        if let Some(light) = def.light { proto = proto.c(light); }
+
+        named.insert(def.name, proto.target);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, BUILTIN_FILE};
+
+    #[test]
+    fn test_builtin() {
+        parse(BUILTIN_FILE, include_str!("../assets/prototypes.txt")).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = parse("test.txt", "[a]\npower: 4\n\n[b]\npower: lots\n").unwrap_err();
+        assert_eq!(err, "test.txt:5: [b] Bad number 'lots' for power");
+
+        let err = parse("test.txt", "[a]\nbiome: base\n").unwrap_err();
+        assert_eq!(err, "test.txt:1: [a] Spawn parameters given without a spawn category");
+
+        assert!(parse("test.txt", "power: 4\n").is_err());
+
+        let err = parse("test.txt", "[a]\nlight: 3\n").unwrap_err();
+        assert_eq!(err, "test.txt:2: [a] Missing light color");
+
+        assert!(parse("test.txt", "[a]\nicon: 1\ncolor: red\nglyph: x\n").is_ok());
+        let err = parse("test.txt", "[a]\nglyph: x\n").unwrap_err();
+        assert_eq!(err, "test.txt:1: [a] Glyph given without an icon");
+        let err = parse("test.txt", "[a]\nglyph: xy\n").unwrap_err();
+        assert_eq!(err, "test.txt:2: [a] Expected a single character glyph, got 'xy'");
+    }
 }
@@ -0,0 +1,205 @@
+/*! Recorded player inputs for replaying a game from its start.
+
+A replay file has the world seed on the first line and one player input
+per line after it:
+
+```text
+seed 1234
+step n
+melee ne
+shoot s
+pass
+swap inventoryj melee
+drop inventoryk
+```
+
+Inventory slots are written with their lowercase names.
+
+Replays only stay in sync as long as nothing but the player inputs changes
+the world, debug console commands will make them diverge.
+*/
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use calx::Dir6;
+use action::Input;
+use item::Slot;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Replay {
+    /// Seed of the world the inputs were given in.
+    pub seed: u32,
+    /// Player inputs, oldest first.
+    pub inputs: Vec<Input>,
+}
+
+impl Replay {
+    pub fn new(seed: u32) -> Replay {
+        Replay { seed: seed, inputs: Vec::new() }
+    }
+
+    /// Parse a replay from the text format. Empty lines and lines starting
+    /// with '#' are skipped.
+    pub fn parse(text: &str) -> Result<Replay, String> {
+        let mut lines = text.lines().enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|&(_, line)| !line.is_empty() && !line.starts_with("#"));
+
+        let mut ret = match lines.next() {
+            Some((i, line)) => {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                match (words.len(), words.get(0).map(|x| *x)) {
+                    (2, Some("seed")) => match words[1].parse() {
+                        Ok(seed) => Replay::new(seed),
+                        Err(_) => return Err(format!("Line {}: Bad seed '{}'", i, words[1])),
+                    },
+                    _ => return Err(format!("Line {}: Expected 'seed <n>', got '{}'", i, line)),
+                }
+            }
+            None => return Err("Empty replay".to_string()),
+        };
+
+        for (i, line) in lines {
+            match parse_input(line) {
+                Some(input) => ret.inputs.push(input),
+                None => return Err(format!("Line {}: Bad input '{}'", i, line)),
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Write the replay in the text format.
+    pub fn to_text(&self) -> String {
+        let mut ret = format!("seed {}\n", self.seed);
+        for &input in self.inputs.iter() {
+            ret.push_str(&input_text(input));
+            ret.push_str("\n");
+        }
+        ret
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let mut text = String::new();
+        try!(File::open(path)
+             .and_then(|mut f| f.read_to_string(&mut text))
+             .map_err(|e| format!("{}: {}", path.to_string_lossy(), e)));
+        Replay::parse(&text).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            try!(fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.to_string_lossy(), e)));
+        }
+        File::create(path)
+            .and_then(|mut f| f.write_all(self.to_text().as_bytes()))
+            .map_err(|e| format!("{}: {}", path.to_string_lossy(), e))
+    }
+}
+
+fn parse_input(line: &str) -> Option<Input> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.len() {
+        1 if words[0] == "pass" => Some(Input::Pass),
+        2 if words[0] == "drop" => parse_slot(words[1]).map(Input::DropItem),
+        2 => {
+            let dir = match parse_dir(words[1]) { Some(d) => d, None => return None };
+            match words[0] {
+                "step" => Some(Input::Step(dir)),
+                "melee" => Some(Input::Melee(dir)),
+                "shoot" => Some(Input::Shoot(dir)),
+                _ => None,
+            }
+        }
+        3 if words[0] == "swap" => match (parse_slot(words[1]), parse_slot(words[2])) {
+            (Some(a), Some(b)) => Some(Input::SwapItems(a, b)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn input_text(input: Input) -> String {
+    match input {
+        Input::Step(d) => format!("step {}", dir_name(d)),
+        Input::Melee(d) => format!("melee {}", dir_name(d)),
+        Input::Shoot(d) => format!("shoot {}", dir_name(d)),
+        Input::Pass => "pass".to_string(),
+        Input::SwapItems(a, b) => format!("swap {} {}", slot_name(a), slot_name(b)),
+        Input::DropItem(s) => format!("drop {}", slot_name(s)),
+    }
+}
+
+fn parse_dir(s: &str) -> Option<Dir6> {
+    match s {
+        "n" => Some(Dir6::North),
+        "ne" => Some(Dir6::NorthEast),
+        "se" => Some(Dir6::SouthEast),
+        "s" => Some(Dir6::South),
+        "sw" => Some(Dir6::SouthWest),
+        "nw" => Some(Dir6::NorthWest),
+        _ => None
+    }
+}
+
+static SLOTS: [Slot; 34] = [
+    Slot::Spell1, Slot::Spell2, Slot::Spell3, Slot::Spell4,
+    Slot::Spell5, Slot::Spell6, Slot::Spell7, Slot::Spell8,
+    Slot::Melee, Slot::Ranged, Slot::Head, Slot::Body, Slot::Feet,
+    Slot::TrinketF, Slot::TrinketG, Slot::TrinketH, Slot::TrinketI,
+    Slot::InventoryJ, Slot::InventoryK, Slot::InventoryL, Slot::InventoryM,
+    Slot::InventoryN, Slot::InventoryO, Slot::InventoryP, Slot::InventoryQ,
+    Slot::InventoryR, Slot::InventoryS, Slot::InventoryT, Slot::InventoryU,
+    Slot::InventoryV, Slot::InventoryW, Slot::InventoryX, Slot::InventoryY,
+    Slot::InventoryZ,
+];
+
+fn parse_slot(s: &str) -> Option<Slot> {
+    SLOTS.iter().find(|&&slot| slot_name(slot) == s).cloned()
+}
+
+fn slot_name(slot: Slot) -> String {
+    format!("{:?}", slot).to_lowercase()
+}
+
+fn dir_name(dir: Dir6) -> &'static str {
+    match dir {
+        Dir6::North => "n",
+        Dir6::NorthEast => "ne",
+        Dir6::SouthEast => "se",
+        Dir6::South => "s",
+        Dir6::SouthWest => "sw",
+        Dir6::NorthWest => "nw",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use calx::Dir6;
+    use action::Input;
+    use item::Slot;
+    use super::Replay;
+
+    #[test]
+    fn test_replay_text() {
+        let replay = Replay {
+            seed: 1234,
+            inputs: vec![Input::Step(Dir6::North), Input::Melee(Dir6::SouthWest),
+                         Input::Shoot(Dir6::NorthEast), Input::Pass,
+                         Input::SwapItems(Slot::InventoryJ, Slot::Melee),
+                         Input::DropItem(Slot::TrinketF)],
+        };
+        assert_eq!(Replay::parse(&replay.to_text()), Ok(replay));
+
+        let replay = Replay::parse("# Comment\n\nseed 7\n  step se\n").unwrap();
+        assert_eq!(replay.seed, 7);
+        assert_eq!(replay.inputs, vec![Input::Step(Dir6::SouthEast)]);
+
+        assert!(Replay::parse("").is_err());
+        assert!(Replay::parse("step n\n").is_err());
+        assert!(Replay::parse("seed 1\nstep up\n").is_err());
+        assert!(Replay::parse("seed 1\njump n\n").is_err());
+        assert!(Replay::parse("seed 1\ndrop pocket\n").is_err());
+        assert!(Replay::parse("seed 1\nswap melee\n").is_err());
+    }
+}
@@ -1,10 +1,10 @@
-use rand::{Rng, XorShiftRng, Rand};
-use calx::EncodeRng;
+use rand::{Rng, Rand};
+use calx::XorShift128;
 use world;
 
 /// Execute a closure with the world RNG.
 pub fn with<A, F>(f: F) -> A
-    where F: Fn(&mut EncodeRng<XorShiftRng>) -> A {
+    where F: Fn(&mut XorShift128) -> A {
     world::with_mut(|w| f(&mut w.flags.rng))
 }
 
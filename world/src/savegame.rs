@@ -0,0 +1,728 @@
+/*! Save file handling with format versioning and backups. */
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{self, Json};
+use bincode::SizeLimit;
+use bincode::rustc_serialize::{encode_into, decode_from};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use flate2::read::ZlibDecoder;
+use calx;
+use world::{self, WorldState};
+use action;
+
+/// Version of the save file layout. Bump this and add a step to `migrate`
+/// whenever a change to the world data would break older saves.
+pub static FORMAT_VERSION: u64 = 7;
+
+/// Name of the save file in the application data directory. Compressed
+/// JSON so that saves from older versions can still be migrated.
+static SAVE_FILENAME: &'static str = "save.jsonz";
+
+/// Names of the save file in the application data directory used by older
+/// versions. These are moved to the current save file when loaded.
+static OLD_SAVE_FILENAMES: [&'static str; 1] = ["save.savz"];
+
+/// Save file in the working directory from before the saves moved to the
+/// application data directory.
+static LEGACY_SAVE_PATH: &'static str = "phage_save.json";
+
+thread_local!(static SAVING: Cell<bool> = Cell::new(true));
+
+/// Identifier at the start of binary save data.
+static BINARY_MAGIC: &'static [u8] = b"PHAGESAV";
+
+/// Save file encodings, chosen by file extension.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SaveFormat {
+    /// Human-readable JSON, ".json".
+    Json,
+    /// Zlib-compressed JSON, ".jsonz".
+    CompressedJson,
+    /// Binary encoding, ".sav".
+    Binary,
+    /// Zlib-compressed binary encoding, ".savz".
+    Compressed,
+}
+
+impl SaveFormat {
+    pub fn from_path(path: &Path) -> Result<SaveFormat, String> {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("json") => Ok(SaveFormat::Json),
+            Some("jsonz") => Ok(SaveFormat::CompressedJson),
+            Some("sav") => Ok(SaveFormat::Binary),
+            Some("savz") => Ok(SaveFormat::Compressed),
+            _ => Err(format!("{}: Unknown save file type, use .json, .jsonz, .sav or .savz",
+                             path.to_string_lossy())),
+        }
+    }
+}
+
+/// Header and payload of a JSON save file.
+#[derive(RustcEncodable)]
+struct SaveFile<'a> {
+    /// Save file layout version, see `FORMAT_VERSION`.
+    format: u64,
+    /// Version of the game that wrote the save, for error messages.
+    game_version: &'a str,
+    world: &'a WorldState,
+}
+
+/// Header of a binary save file, followed by the world state.
+#[derive(RustcEncodable, RustcDecodable)]
+struct SaveHeader {
+    format: u64,
+    game_version: String,
+}
+
+fn save_path() -> PathBuf { calx::app_data_path("phage").join(SAVE_FILENAME) }
+
+/// Path with a suffix added after the full file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map_or(String::new(), |x| x.to_string_lossy().into_owned());
+    name.push_str(suffix);
+    path.with_file_name(name)
+}
+
+fn backup_path() -> PathBuf { with_suffix(&save_path(), ".bak") }
+
+/// Return the save file to continue from, either the current save file or
+/// a save left by an older version.
+fn existing_save() -> Option<PathBuf> {
+    let dir = calx::app_data_path("phage");
+    Some(save_path()).into_iter()
+        .chain(OLD_SAVE_FILENAMES.iter().map(|name| dir.join(name)))
+        .chain(Some(PathBuf::from(LEGACY_SAVE_PATH)).into_iter())
+        .find(|path| fs::metadata(path).is_ok())
+}
+
+/// Write an older version's save file that was just loaded into the current
+/// save file and remove the old file, so that it doesn't get loaded again
+/// after the current save is deleted.
+fn replace_old_save(old: &Path, game_version: &str) -> Result<(), String> {
+    if !saving() { return Ok(()); }
+    try!(save_game_to(&save_path(), game_version));
+    fs::remove_file(old).map_err(|e| io_error(old, e))
+}
+
+/// Save the game. Only saves if there's still a living player around.
+///
+/// The save is first written to a temporary file which then replaces the
+/// actual save file, so a crash during saving won't destroy the old save.
+/// The previous save is kept as a backup.
+pub fn save_game(game_version: &str) -> Result<(), String> {
+    if !saving() { return Ok(()); }
+    match action::player() {
+        Some(p) if !p.is_corpse() => {}
+        _ => return Ok(())
+    }
+
+    let path = save_path();
+    let format = try!(SaveFormat::from_path(&path));
+    let save_data = try!(world::with(|w| encode(format, game_version, w)));
+
+    let tmp_path = with_suffix(&path, ".tmp");
+    if let Some(dir) = path.parent() {
+        try!(fs::create_dir_all(dir).map_err(|e| io_error(dir, e)));
+    }
+
+    try!(write_file(&tmp_path, &save_data));
+
+    if fs::metadata(&path).is_ok() {
+        let backup = backup_path();
+        // Rename won't overwrite existing files on Windows.
+        let _ = fs::remove_file(&backup);
+        try!(fs::rename(&path, &backup).map_err(|e| io_error(&path, e)));
+    }
+    try!(fs::rename(&tmp_path, &path).map_err(|e| io_error(&path, e)));
+    Ok(())
+}
+
+/// Load a saved game if there is one. Saves left by older versions,
+/// including the old phage_save.json in the working directory, are migrated
+/// and moved to the current save file.
+///
+/// If the save can't be loaded, it is moved aside with a .corrupt suffix
+/// and the backup of the previous save, if any, is restored in its place.
+/// The world state is left unchanged and an error message is returned.
+pub fn load_game() -> Result<(), String> {
+    let path = match existing_save() {
+        Some(path) => path,
+        None => return Ok(())
+    };
+
+    match read_save(&path) {
+        Ok((game_version, state)) => {
+            world::set_state(state);
+            if path != save_path() { try!(replace_old_save(&path, &game_version)); }
+            Ok(())
+        }
+        // Leave the files alone if we aren't allowed to touch them.
+        Err(e) if !saving() => Err(e),
+        Err(e) => {
+            let corrupt = with_suffix(&path, ".corrupt");
+            let _ = fs::remove_file(&corrupt);
+            let _ = fs::rename(&path, &corrupt);
+            if path == save_path() && fs::rename(&backup_path(), &path).is_ok() {
+                Err(format!("{}\nRestored the previous save from backup.", e))
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Save the game into the given file regardless of the player state. The
+/// format is chosen by the file extension. Doesn't make backups.
+pub fn save_game_to(path: &Path, game_version: &str) -> Result<(), String> {
+    let format = try!(SaveFormat::from_path(path));
+    let data = try!(world::with(|w| encode(format, game_version, w)));
+    if let Some(dir) = path.parent() {
+        try!(fs::create_dir_all(dir).map_err(|e| io_error(dir, e)));
+    }
+    write_file(path, &data)
+}
+
+/// Load a game from the given file. The world state is left unchanged if the
+/// file can't be loaded.
+pub fn load_game_from(path: &Path) -> Result<(), String> {
+    let (_, state) = try!(read_save(path));
+    world::set_state(state);
+    Ok(())
+}
+
+/// Remove the current save and its backup.
+pub fn delete_save() {
+    if !saving() { return; }
+    let _ = fs::remove_file(save_path());
+    let _ = fs::remove_file(backup_path());
+}
+
+/// Turn writing and removing the default save on or off. Saving is on by
+/// default. Turn it off for test runs that mustn't touch the player's game.
+pub fn set_saving(on: bool) {
+    SAVING.with(|s| s.set(on));
+}
+
+/// Return whether the default save gets written and removed.
+pub fn saving() -> bool {
+    SAVING.with(|s| s.get())
+}
+
+pub fn save_exists() -> bool { existing_save().is_some() }
+
+/// Return the world seed of the saved game if there is a readable save.
+pub fn saved_seed() -> Option<u32> {
+    existing_save()
+        .and_then(|path| read_save(&path).ok())
+        .map(|(_, state)| state.flags.seed)
+}
+
+/// Convert a save file into another format. The formats are determined from
+/// the file extensions. Older JSON saves get migrated to the current format.
+pub fn convert_save(src: &Path, dst: &Path) -> Result<(), String> {
+    let format = try!(SaveFormat::from_path(dst));
+    let (game_version, state) = try!(read_save(src));
+    let data = try!(encode(format, &game_version, &state));
+    write_file(dst, &data)
+}
+
+fn io_error(path: &Path, e: ::std::io::Error) -> String {
+    format!("{}: {}", path.to_string_lossy(), e)
+}
+
+fn corrupt<E: ::std::fmt::Display>(e: E) -> String {
+    format!("Save file is corrupt: {}", e)
+}
+
+fn newer_version(game_version: &str) -> String {
+    format!("Save is from a newer version of the game (v{})", game_version)
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), String> {
+    let mut f = try!(File::create(path).map_err(|e| io_error(path, e)));
+    try!(f.write_all(data).map_err(|e| io_error(path, e)));
+    f.sync_all().map_err(|e| io_error(path, e))
+}
+
+fn encode(format: SaveFormat, game_version: &str, state: &WorldState) -> Result<Vec<u8>, String> {
+    let mut buf;
+    if format == SaveFormat::Json || format == SaveFormat::CompressedJson {
+        buf = try!(json::encode(&SaveFile {
+            format: FORMAT_VERSION,
+            game_version: game_version,
+            world: state,
+        }).map(|s| s.into_bytes()).map_err(|e| format!("Couldn't encode save: {}", e)));
+    } else {
+        let header = SaveHeader {
+            format: FORMAT_VERSION,
+            game_version: game_version.to_string(),
+        };
+        buf = BINARY_MAGIC.to_vec();
+        try!(encode_into(&header, &mut buf, SizeLimit::Infinite)
+             .and_then(|_| encode_into(state, &mut buf, SizeLimit::Infinite))
+             .map_err(|e| format!("Couldn't encode save: {}", e)));
+    }
+
+    if format == SaveFormat::Compressed || format == SaveFormat::CompressedJson {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::Default);
+        buf = try!(encoder.write_all(&buf)
+                   .and_then(|_| encoder.finish())
+                   .map_err(|e| format!("Couldn't compress save: {}", e)));
+    }
+    Ok(buf)
+}
+
+/// Read a save file, returning the version of the game that wrote it and
+/// the world state.
+fn read_save(path: &Path) -> Result<(String, WorldState), String> {
+    let format = try!(SaveFormat::from_path(path));
+    let mut data = Vec::new();
+    try!(File::open(path)
+         .and_then(|mut f| f.read_to_end(&mut data))
+         .map_err(|e| io_error(path, e)));
+
+    match format {
+        SaveFormat::Json => decode_json(&try!(String::from_utf8(data).map_err(corrupt))),
+        SaveFormat::CompressedJson => {
+            let mut text = String::new();
+            try!(ZlibDecoder::new(&data[..]).read_to_string(&mut text).map_err(corrupt));
+            decode_json(&text)
+        }
+        SaveFormat::Binary => decode_binary(&data),
+        SaveFormat::Compressed => {
+            let mut buf = Vec::new();
+            try!(ZlibDecoder::new(&data[..]).read_to_end(&mut buf).map_err(corrupt));
+            decode_binary(&buf)
+        }
+    }
+}
+
+fn decode_binary(data: &[u8]) -> Result<(String, WorldState), String> {
+    if !data.starts_with(BINARY_MAGIC) {
+        return Err("Not a binary save file".to_string());
+    }
+    let mut reader = &data[BINARY_MAGIC.len()..];
+    let header: SaveHeader = try!(decode_from(&mut reader, SizeLimit::Infinite).map_err(corrupt));
+
+    if header.format > FORMAT_VERSION {
+        return Err(newer_version(&header.game_version));
+    }
+    // XXX: Migrations work on the JSON tree and the binary format has no
+    // field names to go by, so older binary saves can't be upgraded. Convert
+    // them to JSON with the game version that wrote them first. The default
+    // save is JSON for this reason.
+    if header.format < FORMAT_VERSION {
+        return Err(format!("Binary save from v{} is too old to load, convert it to JSON first",
+                           header.game_version));
+    }
+
+    let state = try!(decode_from(&mut reader, SizeLimit::Infinite).map_err(corrupt));
+    Ok((header.game_version, state))
+}
+
+fn decode_json(text: &str) -> Result<(String, WorldState), String> {
+    let mut data = try!(Json::from_str(text).map_err(corrupt));
+
+    // Saves from before the header was added are just the bare world state.
+    let mut format = data.find("format").and_then(|x| x.as_u64()).unwrap_or(0);
+    let game_version = data.find("game_version")
+        .and_then(|x| x.as_string()).unwrap_or("unknown").to_string();
+
+    if format > FORMAT_VERSION {
+        return Err(newer_version(&game_version));
+    }
+
+    while format < FORMAT_VERSION {
+        data = try!(migrate(format, data));
+        format += 1;
+    }
+
+    let world_data = match data {
+        Json::Object(mut obj) => obj.remove("world"),
+        _ => None
+    };
+    let world_data = try!(world_data.ok_or("Save file has no world data".to_string()));
+
+    let mut decoder = json::Decoder::new(world_data);
+    let state = try!(Decodable::decode(&mut decoder).map_err(corrupt));
+    Ok((game_version, state))
+}
+
+/// Convert save data from the given format version to the next one.
+fn migrate(format: u64, data: Json) -> Result<Json, String> {
+    match format {
+        // Add the header.
+        0 => {
+            let mut obj = BTreeMap::new();
+            obj.insert("format".to_string(), Json::U64(1));
+            obj.insert("game_version".to_string(), Json::String("unknown".to_string()));
+            obj.insert("world".to_string(), data);
+            Ok(Json::Object(obj))
+        }
+        // The RNG state changed from a raw memory blob into state words.
+        1 => {
+            let mut data = data;
+            {
+                let rng = try!(find_mut(&mut data, &["world", "flags", "rng"]));
+                let words = try!(rng_blob_to_words(rng));
+                *rng = words;
+            }
+            if let Ok(format) = find_mut(&mut data, &["format"]) {
+                *format = Json::U64(2);
+            }
+            Ok(data)
+        }
+        // Moved to calx_ecs. Entity handles got unique ids and the component
+        // storages moved inside the ECS.
+        2 => {
+            let mut data = data;
+            {
+                let world = try!(find_mut(&mut data, &["world"]));
+                try!(migrate_ecs(world));
+            }
+            if let Ok(format) = find_mut(&mut data, &["format"]) {
+                *format = Json::U64(3);
+            }
+            Ok(data)
+        }
+        // Added the godmode flag. It was taken out of the saved state again
+        // in format 6.
+        3 => {
+            let mut data = data;
+            if let Json::Object(ref mut flags) = *try!(find_mut(&mut data, &["world", "flags"])) {
+                flags.insert("godmode".to_string(), Json::Boolean(false));
+            }
+            if let Ok(format) = find_mut(&mut data, &["format"]) {
+                *format = Json::U64(4);
+            }
+            Ok(data)
+        }
+        // Added the light source component. Old prototypes stay unlit.
+        4 => {
+            let mut data = data;
+            if let Json::Object(ref mut ecs) = *try!(find_mut(&mut data, &["world", "ecs"])) {
+                ecs.insert("light_sources".to_string(), Json::Object(BTreeMap::new()));
+            }
+            if let Ok(format) = find_mut(&mut data, &["format"]) {
+                *format = Json::U64(5);
+            }
+            Ok(data)
+        }
+        // The godmode debug flag is no longer saved.
+        5 => {
+            let mut data = data;
+            if let Json::Object(ref mut flags) = *try!(find_mut(&mut data, &["world", "flags"])) {
+                flags.remove("godmode");
+            }
+            if let Ok(format) = find_mut(&mut data, &["format"]) {
+                *format = Json::U64(6);
+            }
+            Ok(data)
+        }
+        // Text mode glyphs moved into the descriptions. Old saves have none,
+        // the frontends fall back to the first letter of the name.
+        6 => {
+            let mut data = data;
+            if let Json::Object(ref mut descs) = *try!(find_mut(&mut data, &["world", "ecs", "descs"])) {
+                for (_, desc) in descs.iter_mut() {
+                    if let Json::Object(ref mut desc) = *desc {
+                        desc.insert("glyph".to_string(), Json::Null);
+                    }
+                }
+            }
+            if let Ok(format) = find_mut(&mut data, &["format"]) {
+                *format = Json::U64(7);
+            }
+            Ok(data)
+        }
+        _ => Err(format!("Don't know how to migrate save format {}", format))
+    }
+}
+
+/// Find a value in nested JSON objects for modification.
+fn find_mut<'a>(data: &'a mut Json, path: &[&str]) -> Result<&'a mut Json, String> {
+    let mut ret = data;
+    for &key in path.iter() {
+        let current = ret;
+        ret = match *current {
+            Json::Object(ref mut obj) => match obj.get_mut(key) {
+                Some(x) => x,
+                None => return Err(format!("Save file has no '{}' field", key)),
+            },
+            _ => return Err(format!("Save file has no '{}' field", key)),
+        };
+    }
+    Ok(ret)
+}
+
+/// Convert the raw byte dump of the old XorShiftRng struct into the state
+/// words of XorShift128.
+fn rng_blob_to_words(blob: &Json) -> Result<Json, String> {
+    let err = || "Bad RNG data in save".to_string();
+    let bytes = try!(blob.as_array().ok_or_else(&err));
+    if bytes.len() != 16 { return Err(err()); }
+
+    // XXX: Assumes the save was made on a little-endian machine, which is
+    // what all the old saves are from.
+    let mut words = Vec::new();
+    for i in 0..4 {
+        let mut word = 0;
+        for j in 0..4 {
+            let b = try!(bytes[i * 4 + j].as_u64().ok_or_else(&err));
+            word = word | (b << (j * 8));
+        }
+        words.push(Json::U64(word));
+    }
+    Ok(Json::Array(words))
+}
+
+/// Convert the old world ECS and component table into a `calx_ecs` ECS and
+/// rewrite the entity handles in the world data.
+fn migrate_ecs(world: &mut Json) -> Result<(), String> {
+    let err = || "Bad entity data in save".to_string();
+    let (ecs, comps) = match *world {
+        Json::Object(ref mut obj) => (obj.remove("ecs"), obj.remove("comps")),
+        _ => (None, None),
+    };
+    let ecs = try!(ecs.ok_or_else(&err));
+    let mut comps = match comps {
+        Some(Json::Object(obj)) => obj,
+        _ => return Err(err()),
+    };
+
+    // Prototypes used to be marked with a component, now they're told apart
+    // by negative uids.
+    let protos: Vec<u64> = match comps.remove("prototypes") {
+        Some(Json::Object(obj)) => obj.iter()
+            .filter(|&(_, v)| !v.is_null())
+            .filter_map(|(k, _)| k.parse().ok())
+            .collect(),
+        _ => return Err(err()),
+    };
+
+    // Saves from before the energy scheduler have no action energy or speed
+    // stats. Mobs get energy through their brains like newly built
+    // prototypes do, and the new stats default to zero.
+    if !comps.contains_key("energies") {
+        let energies = match comps.get("brains") {
+            Some(&Json::Object(ref obj)) => obj.iter()
+                .filter(|&(_, v)| !v.is_null())
+                .map(|(k, _)| {
+                    let mut energy = BTreeMap::new();
+                    energy.insert("amount".to_string(), Json::I64(0));
+                    (k.clone(), Json::Object(energy))
+                })
+                .collect(),
+            _ => return Err(err()),
+        };
+        comps.insert("energies".to_string(), Json::Object(energies));
+    }
+    for name in ["stats", "stats_caches"].iter() {
+        if let Some(&mut Json::Object(ref mut storage)) = comps.get_mut(*name) {
+            for (_, stats) in storage.iter_mut() {
+                if let Json::Object(ref mut stats) = *stats {
+                    for field in ["speed", "move_cost", "attack_cost"].iter() {
+                        stats.entry(field.to_string()).or_insert(Json::I64(0));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut uids = BTreeMap::new();
+    let mut next_uid = 1;
+    let mut next_prototype_uid = -1;
+    let active = try!(ecs.find("active").and_then(|x| x.as_array()).ok_or_else(&err));
+    for (idx, a) in active.iter().enumerate() {
+        if a.as_boolean() != Some(true) { continue; }
+        let idx = idx as u64;
+        if protos.contains(&idx) {
+            uids.insert(idx, next_prototype_uid);
+            next_prototype_uid -= 1;
+        } else {
+            uids.insert(idx, next_uid);
+            next_uid += 1;
+        }
+    }
+
+    let mut parents = BTreeMap::new();
+    match ecs.find("parent") {
+        Some(&Json::Object(ref obj)) => {
+            for (idx, parent) in obj.iter() {
+                let parent = try!(parent.as_u64().ok_or_else(&err));
+                parents.insert(idx.clone(), entity_json(&uids, parent));
+            }
+        }
+        _ => return Err(err()),
+    }
+
+    let mut new_ecs = BTreeMap::new();
+    new_ecs.insert("next_idx".to_string(),
+                   try!(ecs.find("next_idx").cloned().ok_or_else(&err)));
+    new_ecs.insert("reusable_idxs".to_string(),
+                   try!(ecs.find("reusable_idxs").cloned().ok_or_else(&err)));
+    new_ecs.insert("next_entity_uid".to_string(), Json::I64(next_uid));
+    new_ecs.insert("next_prototype_uid".to_string(), Json::I64(next_prototype_uid));
+    new_ecs.insert("active".to_string(), Json::Object(
+        uids.iter().map(|(idx, &uid)| (idx.to_string(), Json::I64(uid))).collect()));
+    new_ecs.insert("parents".to_string(), Json::Object(parents));
+    for (name, storage) in comps.into_iter() {
+        new_ecs.insert(name, storage);
+    }
+
+    if let Json::Object(ref mut obj) = *world {
+        obj.insert("ecs".to_string(), Json::Object(new_ecs));
+    }
+    try!(rewrite_entities(try!(find_mut(world, &["spatial"])), &uids));
+    try!(rewrite_entities(try!(find_mut(world, &["flags"])), &uids));
+    Ok(())
+}
+
+/// Build a `calx_ecs` entity handle.
+fn entity_json(uids: &BTreeMap<u64, i64>, idx: u64) -> Json {
+    // Handles to entities that no longer exist get the unused uid 0 so they
+    // stay dead.
+    let uid = uids.get(&idx).cloned().unwrap_or(0);
+    let mut obj = BTreeMap::new();
+    obj.insert("idx".to_string(), Json::U64(idx));
+    obj.insert("uid".to_string(), Json::I64(uid));
+    Json::Object(obj)
+}
+
+/// Replace old entity handles, saved as `{"_field0": idx}`, with `calx_ecs`
+/// handles.
+fn rewrite_entities(data: &mut Json, uids: &BTreeMap<u64, i64>) -> Result<(), String> {
+    let old_idx = match *data {
+        Json::Object(ref obj) if obj.len() == 1 => obj.get("_field0").and_then(|x| x.as_u64()),
+        _ => None
+    };
+    if let Some(idx) = old_idx {
+        *data = entity_json(uids, idx);
+        return Ok(());
+    }
+
+    match *data {
+        Json::Object(ref mut obj) => {
+            for (_, x) in obj.iter_mut() { try!(rewrite_entities(x, uids)); }
+        }
+        Json::Array(ref mut arr) => {
+            for x in arr.iter_mut() { try!(rewrite_entities(x, uids)); }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::io::prelude::*;
+    use std::path::Path;
+    use rustc_serialize::json::Json;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use world::{self, World};
+    use action::{self, ControlState, Input};
+    use flags;
+    use super::{migrate, encode, find_mut, read_save, write_file, decode_json};
+    use super::{FORMAT_VERSION, SaveFormat};
+
+    /// Save written by the game before save files were versioned.
+    static BASELINE_SAVE: &'static str = include_str!("../assets/test/baseline_save.json");
+
+    #[test]
+    fn test_migrate() {
+        let mut data = Json::from_str(BASELINE_SAVE).unwrap();
+        for format in 0..FORMAT_VERSION {
+            data = migrate(format, data).unwrap();
+        }
+        assert_eq!(data.find("format").and_then(|x| x.as_u64()), Some(FORMAT_VERSION));
+        assert_eq!(data.find_path(&["world", "flags", "rng"]).unwrap(),
+                   &Json::from_str("[1234, 71501, 589827, 2147483692]").unwrap());
+
+        let uid = |path: &[&str]| data.find_path(path).and_then(|x| x.as_i64());
+        assert_eq!(uid(&["world", "ecs", "active", "0"]), Some(-1));
+        assert_eq!(uid(&["world", "ecs", "active", "5"]), Some(1));
+        assert_eq!(uid(&["world", "ecs", "parents", "5", "uid"]), Some(-3));
+        assert_eq!(uid(&["world", "ecs", "next_entity_uid"]), Some(4));
+        assert_eq!(uid(&["world", "flags", "player", "uid"]), Some(1));
+        assert!(data.find_path(&["world", "flags", "godmode"]).is_none());
+        assert_eq!(data.find_path(&["world", "ecs", "descs", "2", "glyph"]), Some(&Json::Null));
+        assert!(data.find_path(&["world", "ecs", "light_sources"]).is_some());
+        assert!(data.find_path(&["world", "comps"]).is_none());
+        let spatial = data.find_path(&["world", "spatial"]).and_then(|x| x.as_array()).unwrap();
+        assert_eq!(spatial[0].find_path(&["_field0", "uid"]).and_then(|x| x.as_i64()), Some(1));
+
+        // Everything with a brain gets action energy.
+        for idx in ["0", "1", "2", "6"].iter() {
+            assert!(data.find_path(&["world", "ecs", "energies", idx]).is_some());
+        }
+        assert!(data.find_path(&["world", "ecs", "energies", "3"]).is_none());
+        assert_eq!(uid(&["world", "ecs", "stats", "2", "speed"]), Some(0));
+        assert_eq!(uid(&["world", "ecs", "stats_caches", "5", "attack_cost"]), Some(0));
+    }
+
+    #[test]
+    fn test_load_baseline_save() {
+        let (game_version, state) = decode_json(BASELINE_SAVE).unwrap();
+        assert_eq!(game_version, "unknown");
+        assert_eq!(state.flags.seed, 1234);
+
+        World::from_state(state).enter(|| {
+            assert!(action::player().unwrap().is_alive());
+            // The player must get a turn and be able to use it.
+            for _ in 0..10 {
+                if action::control_state() == ControlState::AwaitingInput { break; }
+                action::update();
+            }
+            assert!(action::control_state() == ControlState::AwaitingInput);
+            let tick = flags::get_tick();
+            action::input(Input::Pass);
+            assert!(flags::get_tick() > tick);
+        });
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(SaveFormat::from_path(Path::new("a/save.json")), Ok(SaveFormat::Json));
+        assert_eq!(SaveFormat::from_path(Path::new("save.sav")), Ok(SaveFormat::Binary));
+        assert_eq!(SaveFormat::from_path(Path::new("save.savz")), Ok(SaveFormat::Compressed));
+        assert_eq!(SaveFormat::from_path(Path::new("save.jsonz")), Ok(SaveFormat::CompressedJson));
+        assert!(SaveFormat::from_path(Path::new("save.txt")).is_err());
+    }
+
+    #[test]
+    fn test_load_old_default_save() {
+        World::new(Some(1)).enter(|| {
+            // Turn a fresh world into a format 4 save in the default save
+            // file format.
+            let data = world::with(|w| encode(SaveFormat::Json, "0.0.1", w)).unwrap();
+            let mut data = Json::from_str(&String::from_utf8(data).unwrap()).unwrap();
+            *find_mut(&mut data, &["format"]).unwrap() = Json::U64(4);
+            if let Json::Object(ref mut ecs) = *find_mut(&mut data, &["world", "ecs"]).unwrap() {
+                ecs.remove("light_sources");
+            }
+            if let Json::Object(ref mut flags) = *find_mut(&mut data, &["world", "flags"]).unwrap() {
+                flags.insert("godmode".to_string(), Json::Boolean(false));
+            }
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::Default);
+            encoder.write_all(data.to_string().as_bytes()).unwrap();
+
+            let path = env::temp_dir().join("phage-test-old-save.jsonz");
+            write_file(&path, &encoder.finish().unwrap()).unwrap();
+            let loaded = read_save(&path);
+            let _ = fs::remove_file(&path);
+
+            let (game_version, state) = loaded.unwrap();
+            assert_eq!(game_version, "0.0.1");
+            assert_eq!(state.flags.seed, 1);
+        });
+    }
+}
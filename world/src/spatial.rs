@@ -1,8 +1,12 @@
 use std::collections::{BTreeMap};
+use std::collections::btree_map;
 use vec_map::{VecMap};
+use calx::{V2, HexGeom};
 use entity::{Entity};
 use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
 use location::{Location};
+use location_set::LocationSet;
+use ecs::Ecs;
 use item::Slot;
 use self::Place::*;
 
@@ -15,9 +19,12 @@ pub enum Place {
 }
 
 /// Spatial index for game entities
+#[derive(Clone)]
 pub struct Spatial {
     place_to_entities: BTreeMap<Place, Vec<Entity>>,
-    entity_to_place: VecMap<Place>,
+    /// Places indexed by entity slot. The full entity handle is stored so
+    /// that stale handles to the same slot won't match.
+    entity_to_place: VecMap<(Entity, Place)>,
 }
 
 impl Spatial {
@@ -28,9 +35,9 @@ impl Spatial {
         }
     }
 
-    fn insert(&mut self, Entity(idx): Entity, p: Place) {
+    fn insert(&mut self, e: Entity, p: Place) {
         // Remove the entity from its old position.
-        self.single_remove(Entity(idx));
+        self.single_remove(e);
 
         if let In(_, Some(_)) = p {
             // Slotted in-places are a special case that can hold at most one entity.
@@ -39,15 +46,15 @@ impl Spatial {
             }
         }
 
-        self.entity_to_place.insert(idx, p);
+        self.entity_to_place.insert(e.idx(), (e, p));
         match self.place_to_entities.get_mut(&p) {
-            Some(v) => { v.push(Entity(idx)); return; }
+            Some(v) => { v.push(e); return; }
             _ => ()
         };
         // Didn't return above, that means this location isn't indexed
         // yet and needs a brand new container. (Can't do this in match
         // block because borrows.)
-        self.place_to_entities.insert(p, vec![Entity(idx)]);
+        self.place_to_entities.insert(p, vec![e]);
     }
 
     /// Insert an entity into space.
@@ -57,8 +64,8 @@ impl Spatial {
 
     /// Return whether the parent entity or an entity contained in the parent
     /// entity contains entity e.
-    pub fn contains(&self, parent: Entity, Entity(idx): Entity) -> bool {
-        match self.entity_to_place.get(idx) {
+    pub fn contains(&self, parent: Entity, e: Entity) -> bool {
+        match self.get(e).as_ref() {
             Some(&In(p, _)) if p == parent => true,
             Some(&In(p, _)) => self.contains(parent, p),
             _ => false
@@ -80,11 +87,12 @@ impl Spatial {
     /// Remove an entity from the local structures but do not pop out its
     /// items. Unless the entity is added back in or the contents are handled
     /// somehow, this will leave the spatial index in an inconsistent state.
-    fn single_remove(&mut self, Entity(idx): Entity) {
-        if !self.entity_to_place.contains_key(idx) { return; }
-
-        let &p = self.entity_to_place.get(idx).unwrap();
-        self.entity_to_place.remove(idx);
+    fn single_remove(&mut self, e: Entity) {
+        let p = match self.get(e) {
+            Some(p) => p,
+            None => return
+        };
+        self.entity_to_place.remove(e.idx());
 
         {
             let v = self.place_to_entities.get_mut(&p).unwrap();
@@ -93,7 +101,7 @@ impl Spatial {
                 // More than one entity present, remove this one, keep the
                 // rest.
                 for i in 0..v.len() {
-                    if v[i] == Entity(idx) {
+                    if v[i] == e {
                         v.swap_remove(i);
                         return;
                     }
@@ -103,7 +111,7 @@ impl Spatial {
                 // This was the only entity in the location.
                 // Drop the entry for this location from the index.
                 // (Need to drop out of scope for borrows reasons)
-                assert!((*v)[0] == Entity(idx));
+                assert!((*v)[0] == e);
             }
         }
         // We only end up here if we need to clear the container for the
@@ -113,24 +121,96 @@ impl Spatial {
 
     /// Remove an entity from the space. Entities contained in the entity will
     /// also be removed from the space.
-    pub fn remove(&mut self, Entity(idx): Entity) {
+    pub fn remove(&mut self, e: Entity) {
         // Remove the contents
-        for &content in self.entities_in(Entity(idx)).iter() {
+        for &content in self.entities_in(e).iter() {
             self.remove(content);
         }
-        self.single_remove(Entity(idx));
+        self.single_remove(e);
+    }
+
+    /// List entities at a location.
+    pub fn entities_at(&self, loc: Location) -> Vec<Entity> {
+        self.at(loc).to_vec()
     }
 
-    fn entities(&self, p: Place) -> Vec<Entity> {
-        match self.place_to_entities.get(&p) {
-            None => vec![],
-            Some(v) => v.clone(),
+    /// Entities at a location without copying.
+    pub fn at(&self, loc: Location) -> &[Entity] {
+        match self.place_to_entities.get(&At(loc)) {
+            None => &[],
+            Some(v) => &v[..],
         }
     }
 
-    /// List entities at a location.
-    pub fn entities_at(&self, loc: Location) -> Vec<Entity> {
-        self.entities(At(loc))
+    /// Return the mob at a location, if any.
+    pub fn mob_at(&self, ecs: &Ecs, loc: Location) -> Option<Entity> {
+        self.at(loc).iter().find(|&&e| ecs.brains().has(e)).cloned()
+    }
+
+    /// Iterate the locations that have entities in them along with the
+    /// entities, in location order.
+    pub fn occupied(&self) -> OccupiedIter {
+        OccupiedIter { inner: self.place_to_entities.iter() }
+    }
+
+    /// List the entities within radius hexes of center, nearest first.
+    pub fn entities_within(&self, center: Location, radius: u32) -> Vec<Entity> {
+        self.query(center, radius, |_| true)
+    }
+
+    /// List the entities within radius hexes of center that are in the given
+    /// field of view, nearest first.
+    pub fn entities_in_fov(&self, center: Location, radius: u32, fov: &LocationSet) -> Vec<Entity> {
+        self.query(center, radius, |loc| fov.contains(&loc))
+    }
+
+    /// List the mobs within radius hexes of center, nearest first.
+    pub fn mobs_within(&self, ecs: &Ecs, center: Location, radius: u32) -> Vec<Entity> {
+        self.query(center, radius, |_| true).into_iter()
+            .filter(|&e| ecs.brains().has(e))
+            .collect()
+    }
+
+    /// List the mobs within radius hexes of center that are in the given
+    /// field of view, nearest first.
+    pub fn mobs_in_fov(&self, ecs: &Ecs, center: Location, radius: u32, fov: &LocationSet) -> Vec<Entity> {
+        self.query(center, radius, |loc| fov.contains(&loc)).into_iter()
+            .filter(|&e| ecs.brains().has(e))
+            .collect()
+    }
+
+    fn query<P>(&self, center: Location, radius: u32, pred: P) -> Vec<Entity>
+        where P: Fn(Location) -> bool
+    {
+        let r = radius as i32;
+        let mut locs = Vec::new();
+
+        // Look up every cell in the radius or scan all the occupied
+        // locations, whichever is less work.
+        if ((3 * r * (r + 1) + 1) as usize) < self.place_to_entities.len() {
+            for y in -r..(r + 1) {
+                for x in -r..(r + 1) {
+                    let v = V2(x, y);
+                    let dist = v.hex_dist();
+                    if dist > r { continue; }
+                    let loc = center + v;
+                    // Coordinates wrap around at the edge of the map, make
+                    // sure we get the same results as the scan.
+                    if center.distance_from(loc) != Some(dist) { continue; }
+                    if !self.at(loc).is_empty() && pred(loc) { locs.push(loc); }
+                }
+            }
+        } else {
+            for (loc, _) in self.occupied() {
+                match center.distance_from(loc) {
+                    Some(d) if d <= r && pred(loc) => locs.push(loc),
+                    _ => {}
+                }
+            }
+        }
+
+        locs.sort_by_key(|&loc| (center.distance_from(loc), loc));
+        locs.iter().flat_map(|&loc| self.at(loc).iter().cloned()).collect()
     }
 
     /// List entities in a container.
@@ -170,15 +250,18 @@ impl Spatial {
     }
 
     /// Return the place of an entity if the entity is present in the space.
-    pub fn get(&self, Entity(idx): Entity) -> Option<Place> {
-        self.entity_to_place.get(idx).map(|&loc| loc)
+    pub fn get(&self, e: Entity) -> Option<Place> {
+        match self.entity_to_place.get(e.idx()) {
+            Some(&(stored, p)) if stored == e => Some(p),
+            _ => None
+        }
     }
 
     /// Flatten to an easily serializable vector.
     fn dump(&self) -> Vec<Elt> {
         let mut ret = vec![];
-        for (idx, &loc) in self.entity_to_place.iter() {
-            ret.push(Elt(Entity(idx), loc));
+        for (_, &(e, loc)) in self.entity_to_place.iter() {
+            ret.push(Elt(e, loc));
         }
         ret
     }
@@ -194,6 +277,23 @@ impl Spatial {
     }
 }
 
+/// Iterator for the occupied locations of `Spatial`.
+pub struct OccupiedIter<'a> {
+    inner: btree_map::Iter<'a, Place, Vec<Entity>>,
+}
+
+impl<'a> Iterator for OccupiedIter<'a> {
+    type Item = (Location, &'a [Entity]);
+
+    fn next(&mut self) -> Option<(Location, &'a [Entity])> {
+        // At places sort before In places, so we're done at the first In.
+        match self.inner.next() {
+            Some((&At(loc), v)) => Some((loc, &v[..])),
+            _ => None
+        }
+    }
+}
+
 #[derive(Clone, RustcDecodable, RustcEncodable)]
 struct Elt(Entity, Place);
 
@@ -211,9 +311,18 @@ impl Encodable for Spatial {
 
 #[cfg(test)]
 mod test {
-    use super::{Place};
+    use super::{Place, Spatial};
     use item::Slot;
+    use calx_ecs;
+    use ecs::Ecs;
     use entity::Entity;
+    use location::Location;
+    use location_set::LocationSet;
+    use components::{Brain, BrainState, Alignment};
+
+    fn entity(idx: u32) -> Entity {
+        Entity(calx_ecs::Entity { idx: idx, uid: idx as i32 + 1 })
+    }
 
     #[test]
     fn test_place_adjacency() {
@@ -225,19 +334,56 @@ mod test {
         // it's not obvious which way the derived lexical order sorts, so put
         // an unit test here to check it out.
         let mut places = vec![
-            Place::In(Entity(0), Some(Slot::Melee)),
-            Place::In(Entity(1), None),
-            Place::In(Entity(0), Some(Slot::Ranged)),
-            Place::In(Entity(0), None),
+            Place::In(entity(0), Some(Slot::Melee)),
+            Place::In(entity(1), None),
+            Place::In(entity(0), Some(Slot::Ranged)),
+            Place::In(entity(0), None),
         ];
 
         places.sort();
         assert_eq!(places,
             vec![
-                Place::In(Entity(0), None),
-                Place::In(Entity(0), Some(Slot::Melee)),
-                Place::In(Entity(0), Some(Slot::Ranged)),
-                Place::In(Entity(1), None),
+                Place::In(entity(0), None),
+                Place::In(entity(0), Some(Slot::Melee)),
+                Place::In(entity(0), Some(Slot::Ranged)),
+                Place::In(entity(1), None),
             ]);
     }
+
+    #[test]
+    fn test_queries() {
+        let mut ecs = Ecs::new();
+        let mob = Entity(ecs.make(None));
+        ecs.mu().brains().insert(mob, Brain {
+            state: BrainState::Asleep,
+            alignment: Alignment::Indigenous,
+        });
+        let item1 = Entity(ecs.make(None));
+        let item2 = Entity(ecs.make(None));
+        let item3 = Entity(ecs.make(None));
+
+        let mut spatial = Spatial::new();
+        let center = Location::new(10, 10);
+        spatial.insert_at(item1, Location::new(12, 10));
+        spatial.insert_at(mob, Location::new(11, 10));
+        spatial.insert_at(item2, Location::new(11, 10));
+        spatial.insert_at(item3, Location::new(20, 10));
+        spatial.insert_in(item3, mob);
+
+        assert_eq!(spatial.at(Location::new(11, 10)), &[mob, item2]);
+        assert_eq!(spatial.mob_at(&ecs, Location::new(11, 10)), Some(mob));
+        assert_eq!(spatial.mob_at(&ecs, Location::new(12, 10)), None);
+        assert_eq!(spatial.occupied().map(|(loc, _)| loc).collect::<Vec<_>>(),
+                   vec![Location::new(11, 10), Location::new(12, 10)]);
+
+        assert_eq!(spatial.entities_within(center, 0), vec![]);
+        assert_eq!(spatial.entities_within(center, 1), vec![mob, item2]);
+        assert_eq!(spatial.entities_within(center, 5), vec![mob, item2, item1]);
+        assert_eq!(spatial.mobs_within(&ecs, center, 5), vec![mob]);
+
+        let mut fov = LocationSet::new();
+        fov.insert(Location::new(12, 10));
+        assert_eq!(spatial.entities_in_fov(center, 5, &fov), vec![item1]);
+        assert_eq!(spatial.mobs_in_fov(&ecs, center, 5, &fov), vec![]);
+    }
 }
@@ -3,11 +3,9 @@ use rand::Rng;
 use rand::distributions::{Weighted, WeightedChoice, IndependentSample};
 use components::{Category};
 use entity::Entity;
-use action;
 use Biome;
 use location::{Location};
 use world;
-use ecs::{ComponentAccess};
 
 /// Representation for an abstract spawnable object. Does not refer to
 /// concrete entity prototypes and can be used before the prototype set has
@@ -43,18 +41,21 @@ impl Spawn {
     pub fn spawn<R: Rng>(&self, rng: &mut R, loc: Location) -> Entity {
         // XXX: Optimization option: memoize WeightedChoices for the biomask,
         // catmask, depth tuples.
-        let mut items: Vec<Weighted<Entity>> = action::entities()
-            .filter_map(|e| world::with(|w| {
-                if let Some(spawn) = w.spawns().get_local(e) {
+        let mut items: Vec<Weighted<Entity>> = world::with(|w| {
+            w.ecs.spawns().entities_local().into_iter()
+                .map(Entity)
+                .filter_map(|e| {
+                    let spawn = w.ecs.spawns().get_local(e).unwrap();
                     if spawn.min_depth <= self.depth
                         && self.biome_mask & (spawn.biome as u32) != 0
                         && self.category_mask & (spawn.category as u32) != 0 {
-                        return Some(Weighted { weight: spawn.commonness as u32, item: e });
+                        Some(Weighted { weight: spawn.commonness as u32, item: e })
+                    } else {
+                        None
                     }
-                }
-                return None;
-            }))
-            .collect();
+                })
+                .collect()
+        });
         assert!(!items.is_empty(), format!("Couldn't find spawns for {:?}", self));
         let dist = WeightedChoice::new(&mut items[..]);
         let e = dist.ind_sample(rng);
@@ -19,6 +19,12 @@ pub struct Stats {
     pub ranged_range: u32,
     /// Ranged attack power
     pub ranged_power: i32,
+    /// Speed bonus, added to the energy gained every tick.
+    pub speed: i32,
+    /// Energy cost modifier for moving.
+    pub move_cost: i32,
+    /// Energy cost modifier for melee and ranged attacks.
+    pub attack_cost: i32,
 
     /// Bit flags for intrinsics
     pub intrinsics: u32,
@@ -40,6 +46,9 @@ impl Stats {
     pub fn attack(self, attack: i32) -> Stats { Stats { attack: attack, .. self } }
     pub fn ranged_range(self, ranged_range: u32) -> Stats { Stats { ranged_range: ranged_range, .. self } }
     pub fn ranged_power(self, ranged_power: i32) -> Stats { Stats { ranged_power: ranged_power, .. self } }
+    pub fn speed(self, speed: i32) -> Stats { Stats { speed: speed, .. self } }
+    pub fn move_cost(self, move_cost: i32) -> Stats { Stats { move_cost: move_cost, .. self } }
+    pub fn attack_cost(self, attack_cost: i32) -> Stats { Stats { attack_cost: attack_cost, .. self } }
 }
 
 impl Add<Stats> for Stats {
@@ -57,6 +66,9 @@ impl Add<Stats> for Stats {
             // type dealie.
             ranged_range: self.ranged_range + other.ranged_range,
             ranged_power: self.ranged_power + other.ranged_power,
+            speed: self.speed + other.speed,
+            move_cost: self.move_cost + other.move_cost,
+            attack_cost: self.attack_cost + other.attack_cost,
             intrinsics: self.intrinsics | other.intrinsics,
         }
     }
@@ -64,11 +76,11 @@ impl Add<Stats> for Stats {
 
 #[derive(Copy, Eq, PartialEq, Clone, Debug, RustcEncodable, RustcDecodable)]
 pub enum Intrinsic {
-    /// Moves 1/3 slower than usual.
+    /// Gains energy 1/3 slower than usual.
     Slow        = 0b1,
-    /// Moves 1/3 faster than usual, stacks with Quick status.
+    /// Gains energy 1/3 faster than usual, stacks with Quick status.
     Fast        = 0b10,
-    /// Moves 1/3 faster than usual, stacks with Fast status.
+    /// Gains energy 1/3 faster than usual, stacks with Fast status.
     Quick       = 0b100,
     /// Can manipulate objects and doors.
     Hands       = 0b1000,
@@ -76,4 +88,6 @@ pub enum Intrinsic {
     Dead        = 0b10000,
     /// Inorganic, phage can't use corpse
     Robotic     = 0b100000,
+    /// Only hunts at night.
+    Nocturnal   = 0b1000000,
 }
@@ -1,3 +1,4 @@
+use calx::{Rgba, color};
 use self::TerrainType::*;
 
 // TODO: Figure out how to not require explicit element count.
@@ -25,7 +26,7 @@ macro_rules! terrain_data {
 }
 
 terrain_data! {
-    count: 28;
+    count: 29;
 
     Void, "void";
     Floor, "floor";
@@ -56,6 +57,7 @@ terrain_data! {
     CraterNW, "crater";
     Crater, "crater";
     Pod, "pod";
+    Lamp, "lamp";
 }
 
 
@@ -104,7 +106,16 @@ impl TerrainType {
 
     pub fn is_door(self) -> bool { self == Door }
 
-    pub fn is_luminous(self) -> bool { self == Magma }
+    /// Color and radius of the light the terrain gives off.
+    pub fn light_source(self) -> Option<(Rgba, u32)> {
+        match self {
+            Magma => Some((color::ORANGERED, 3)),
+            Lamp => Some((color::LIGHTYELLOW, 6)),
+            // Bioluminescent.
+            Pod => Some((color::DARKCYAN, 1)),
+            _ => None
+        }
+    }
 
     pub fn is_hole(self) -> bool { self == Chasm }
 
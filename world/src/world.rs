@@ -1,52 +1,198 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 use rand;
 use rand::Rng;
 use rustc_serialize::json;
-use ecs::{Ecs, Comps};
+use ecs::Ecs;
 use area::Area;
 use spatial::Spatial;
 use flags::Flags;
+use fov::FovCache;
+use light::LightCache;
+use replay::Replay;
 use action;
 use prototype;
+use fov;
 
-thread_local!(static WORLD_STATE: RefCell<WorldState> = RefCell::new(WorldState::new(None)));
+thread_local!(static CURRENT_WORLD: RefCell<World> = RefCell::new(World::from_state(WorldState::new(None))));
+
+/// Handle to a game world.
+///
+/// Any number of worlds can exist at the same time. The free functions of
+/// this crate, and the methods of `Entity`, operate on the current world of
+/// the thread. Use `enter` to run code against a specific world.
+///
+/// Cloning the handle gives another reference to the same world, use
+/// `duplicate` to get an independent copy.
+#[derive(Clone)]
+pub struct World {
+    state: Rc<RefCell<WorldState>>,
+    msgs: Rc<RefCell<Vec<::Msg>>>,
+    fov: Rc<RefCell<FovCache>>,
+    lights: Rc<RefCell<LightCache>>,
+    /// Player inputs since the start of the game, None if the start isn't
+    /// known because the world was loaded.
+    replay: Rc<RefCell<Option<Replay>>>,
+    /// Developer mode where the player takes no damage. Not saved.
+    godmode: Rc<Cell<bool>>,
+}
+
+impl World {
+    /// Create a fresh start-game world with an optional fixed random number
+    /// generator seed.
+    pub fn new(seed: Option<u32>) -> World {
+        let ret = World::from_state(WorldState::new(seed));
+        ret.enter(|| {
+            prototype::init();
+            action::start_level(1);
+        });
+        *ret.replay.borrow_mut() = Some(Replay::new(ret.state.borrow().flags.seed));
+        ret
+    }
+
+    /// Wrap world state data into a new world.
+    pub fn from_state(state: WorldState) -> World {
+        World {
+            state: Rc::new(RefCell::new(state)),
+            msgs: Rc::new(RefCell::new(Vec::new())),
+            fov: Rc::new(RefCell::new(FovCache::new())),
+            lights: Rc::new(RefCell::new(LightCache::new())),
+            replay: Rc::new(RefCell::new(None)),
+            godmode: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Load a world from a json string.
+    pub fn from_json(json: &str) -> Result<World, json::DecoderError> {
+        Ok(World::from_state(try!(json::decode::<WorldState>(json))))
+    }
+
+    /// Save the world into a json string.
+    pub fn to_json(&self) -> String {
+        json::encode(&*self.state.borrow()).unwrap()
+    }
+
+    /// Return the current world of the thread.
+    pub fn current() -> World {
+        CURRENT_WORLD.with(|w| w.borrow().clone())
+    }
+
+    /// Make this world the current world of the thread.
+    pub fn make_current(&self) {
+        CURRENT_WORLD.with(|w| *w.borrow_mut() = self.clone());
+    }
+
+    /// Run a function with this world as the current world. The previous
+    /// current world is restored afterwards.
+    pub fn enter<A, F>(&self, f: F) -> A
+        where F: FnOnce() -> A {
+        // Restore the previous world even if f panics.
+        struct Restore(World);
+        impl Drop for Restore {
+            fn drop(&mut self) { self.0.make_current(); }
+        }
+
+        let _restore = Restore(World::current());
+        self.make_current();
+        f()
+    }
+
+    /// Make an independent copy of the world. Pending messages and cached
+    /// data are not copied.
+    pub fn duplicate(&self) -> World {
+        World::from_state(self.state.borrow().clone())
+    }
+
+    /// Return whether the handles refer to the same world.
+    pub fn is_same(&self, other: &World) -> bool {
+        &*self.state as *const _ == &*other.state as *const _
+    }
+}
 
 /// Access world state for reading. The world state may not be reaccessed for
 /// writing while within this function.
 pub fn with<A, F>(mut f: F) -> A
     where F: FnMut(&WorldState) -> A {
-    WORLD_STATE.with(|w| f(& *w.borrow()))
+    let state = CURRENT_WORLD.with(|w| w.borrow().state.clone());
+    let ret = f(& *state.borrow());
+    ret
 }
 
 /// Access world state for reading and writing. The world state may not be
 /// reaccessed while within this function.
 pub fn with_mut<A, F>(mut f: F) -> A
     where F: FnMut(&mut WorldState) -> A {
-    WORLD_STATE.with(|w| f(&mut *w.borrow_mut()))
+    let state = CURRENT_WORLD.with(|w| w.borrow().state.clone());
+    let ret = f(&mut *state.borrow_mut());
+    ret
+}
+
+/// Access the message queue of the current world.
+pub fn with_msgs<A, F>(mut f: F) -> A
+    where F: FnMut(&mut Vec<::Msg>) -> A {
+    let msgs = CURRENT_WORLD.with(|w| w.borrow().msgs.clone());
+    let ret = f(&mut *msgs.borrow_mut());
+    ret
+}
+
+/// Access the field of view cache of the current world.
+pub fn with_fov<A, F>(mut f: F) -> A
+    where F: FnMut(&mut FovCache) -> A {
+    let fov = CURRENT_WORLD.with(|w| w.borrow().fov.clone());
+    let ret = f(&mut *fov.borrow_mut());
+    ret
+}
+
+/// Access the light map cache of the current world.
+pub fn with_lights<A, F>(mut f: F) -> A
+    where F: FnMut(&mut LightCache) -> A {
+    let lights = CURRENT_WORLD.with(|w| w.borrow().lights.clone());
+    let ret = f(&mut *lights.borrow_mut());
+    ret
+}
+
+/// Access the player input log of the current world.
+pub fn with_replay<A, F>(mut f: F) -> A
+    where F: FnMut(&mut Option<Replay>) -> A {
+    let replay = CURRENT_WORLD.with(|w| w.borrow().replay.clone());
+    let ret = f(&mut *replay.borrow_mut());
+    ret
+}
+
+/// Return whether the player of the current world is invulnerable.
+pub fn godmode() -> bool {
+    CURRENT_WORLD.with(|w| w.borrow().godmode.get())
+}
+
+pub fn set_godmode(on: bool) {
+    CURRENT_WORLD.with(|w| w.borrow().godmode.set(on));
 }
 
 /// Save the global world state into a json string.
 pub fn save() -> String {
-    with(|w| json::encode(w).unwrap())
+    World::current().to_json()
 }
 
 /// Load the global world state from a json string. If the load operation
 /// is successful, the previous world state will be overwritten by the loaded
 /// one.
 pub fn load(json: &str) -> Result<(), json::DecoderError> {
-    WORLD_STATE.with(|w| {
-        match json::decode::<WorldState>(json) {
-            Ok(ws) => {
-                *w.borrow_mut() = ws;
-                Ok(())
-            }
-            Err(e) => Err(e)
-        }
-    })
+    let ws = try!(json::decode::<WorldState>(json));
+    set_state(ws);
+    Ok(())
+}
+
+/// Replace the global world state with a loaded one.
+pub fn set_state(state: WorldState) {
+    let current = CURRENT_WORLD.with(|w| w.borrow().state.clone());
+    *current.borrow_mut() = state;
+    // The loaded world didn't start from the recorded inputs.
+    with_replay(|r| *r = None);
+    fov::invalidate();
 }
 
 /// The internal object that holds all the world state data.
-#[derive(RustcEncodable, RustcDecodable)]
+#[derive(Clone, RustcEncodable, RustcDecodable)]
 pub struct WorldState {
     /// Global entity handler.
     pub ecs: Ecs,
@@ -56,8 +202,6 @@ pub struct WorldState {
     pub spatial: Spatial,
     /// Global gamestate flags.
     pub flags: Flags,
-
-    pub comps: Comps,
 }
 
 impl<'a> WorldState {
@@ -72,7 +216,6 @@ impl<'a> WorldState {
             area: Area::new(seed, ::AreaSpec::new(::Biome::Overland, 1)),
             spatial: Spatial::new(),
             flags: Flags::new(seed),
-            comps: Comps::new(),
         }
     }
 }
@@ -81,8 +224,32 @@ impl<'a> WorldState {
 /// generator seed. Calling init_world will cause any existing world state to
 /// be discarded.
 pub fn init_world(seed: Option<u32>) {
-    WORLD_STATE.with(|w| *w.borrow_mut() = WorldState::new(seed));
-    prototype::init();
+    World::new(seed).make_current();
+}
 
-    action::start_level(1);
+#[cfg(test)]
+mod test {
+    use super::World;
+    use flags;
+
+    #[test]
+    fn test_separate_worlds() {
+        let a = World::new(Some(1));
+        let b = World::new(Some(2));
+        assert_eq!(a.enter(|| super::with(|w| w.flags.seed)), 1);
+        assert_eq!(b.enter(|| super::with(|w| w.flags.seed)), 2);
+
+        let tick = a.enter(|| flags::get_tick());
+        let c = a.duplicate();
+        assert!(!c.is_same(&a));
+        c.enter(|| super::with_mut(|w| w.flags.tick = tick + 100));
+        assert_eq!(c.enter(|| flags::get_tick()), tick + 100);
+        assert_eq!(a.enter(|| flags::get_tick()), tick);
+    }
+
+    #[test]
+    fn test_zero_seed() {
+        super::init_world(Some(0));
+        assert_eq!(super::with(|w| w.flags.seed), 0);
+    }
 }